@@ -0,0 +1,385 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{ApiRequestError, Gemini, BASE_URL};
+
+/// OAuth2 scope required by the Generative Language API.
+pub const GENERATIVE_LANGUAGE_SCOPE: &str = "https://www.googleapis.com/auth/generative-language";
+/// Broad OAuth2 scope accepted by Google Cloud APIs, including Vertex.
+pub const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+/// Provides bearer tokens used instead of an API key.
+///
+/// Implementations are asked for a token before every request and are expected to cache
+/// and refresh it themselves.
+#[async_trait]
+pub trait TokenSource: Send + Sync {
+    async fn token(&self) -> Result<String, ApiRequestError>;
+}
+
+/// A fixed bearer token, e.g. one obtained with `gcloud auth print-access-token`.
+#[derive(Clone)]
+pub struct StaticToken(String);
+
+impl StaticToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+}
+
+impl fmt::Debug for StaticToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StaticToken").field(&"[REDACTED]").finish()
+    }
+}
+
+#[async_trait]
+impl TokenSource for StaticToken {
+    async fn token(&self) -> Result<String, ApiRequestError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// How an [`ApiKeyPool`] picks the key for the next request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RotationStrategy {
+    /// Cycle through the keys in order.
+    #[default]
+    RoundRobin,
+    /// Prefer the key that was rate limited longest ago (or never), cycling between ties.
+    LeastRecentlyThrottled,
+}
+
+/// Several API keys used in rotation, so per-key rate limits can be spread across keys.
+pub struct ApiKeyPool {
+    keys: Vec<String>,
+    strategy: RotationStrategy,
+    next: AtomicUsize,
+    /// Logical clock bumped on every reported rate limit; avoids `Instant` on wasm32.
+    clock: AtomicU64,
+    /// Clock value of the last rate limit per key, `0` when never throttled.
+    throttled_at: Mutex<Vec<u64>>,
+}
+
+impl ApiKeyPool {
+    pub fn new(keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let keys: Vec<String> = keys.into_iter().map(Into::into).collect();
+        let throttled_at = Mutex::new(vec![0; keys.len()]);
+        Self {
+            keys,
+            strategy: RotationStrategy::default(),
+            next: AtomicUsize::new(0),
+            clock: AtomicU64::new(0),
+            throttled_at,
+        }
+    }
+
+    #[must_use]
+    pub fn with_strategy(mut self, strategy: RotationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the key to use for the next request, or `None` if the pool is empty.
+    #[must_use]
+    pub fn next_key(&self) -> Option<&str> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = match self.strategy {
+            RotationStrategy::RoundRobin => start % self.keys.len(),
+            RotationStrategy::LeastRecentlyThrottled => {
+                let throttled_at = self.throttled_at.lock().unwrap();
+                (0..self.keys.len())
+                    .map(|offset| (start + offset) % self.keys.len())
+                    .min_by_key(|&i| throttled_at[i])
+                    .unwrap_or_default()
+            }
+        };
+        Some(&self.keys[index])
+    }
+
+    /// Records that `key` hit a rate limit.
+    pub fn report_rate_limit(&self, key: &str) {
+        if let Some(index) = self.keys.iter().position(|k| k == key) {
+            let tick = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+            self.throttled_at.lock().unwrap()[index] = tick;
+        }
+    }
+}
+
+impl fmt::Debug for ApiKeyPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyPool")
+            .field("keys", &format!("{} keys", self.keys.len()))
+            .field("strategy", &self.strategy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Application Default Credentials: service account keys, user credentials from
+/// `gcloud auth application-default login`, or the GCE metadata server.
+#[cfg(feature = "gcp-auth")]
+#[derive(Clone)]
+pub struct ApplicationDefaultCredentials {
+    provider: std::sync::Arc<dyn gcp_auth::TokenProvider>,
+    scopes: Vec<String>,
+}
+
+#[cfg(feature = "gcp-auth")]
+impl ApplicationDefaultCredentials {
+    /// Discovers credentials and requests the Generative Language scope.
+    pub async fn new() -> Result<Self, ApiRequestError> {
+        Self::with_scopes([GENERATIVE_LANGUAGE_SCOPE]).await
+    }
+
+    pub async fn with_scopes(
+        scopes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Self, ApiRequestError> {
+        let provider = gcp_auth::provider()
+            .await
+            .map_err(|e| ApiRequestError::AuthenticationError(e.to_string()))?;
+        Ok(Self {
+            provider,
+            scopes: scopes.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+#[cfg(feature = "gcp-auth")]
+impl fmt::Debug for ApplicationDefaultCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApplicationDefaultCredentials")
+            .field("scopes", &self.scopes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "gcp-auth")]
+#[async_trait]
+impl TokenSource for ApplicationDefaultCredentials {
+    async fn token(&self) -> Result<String, ApiRequestError> {
+        let scopes: Vec<&str> = self.scopes.iter().map(String::as_str).collect();
+        let token = self
+            .provider
+            .token(&scopes)
+            .await
+            .map_err(|e| ApiRequestError::AuthenticationError(e.to_string()))?;
+        Ok(token.as_str().to_string())
+    }
+}
+
+/// A short-lived token minted server-side with [`Gemini::create_auth_token`] and handed to a
+/// browser or mobile client, which connects to the Live API with it instead of the API key.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthToken {
+    /// The token, `auth_tokens/*`; assigned by the API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// How many sessions the token can start; the API defaults to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uses: Option<u32>,
+    /// RFC 3339 timestamp after which the token can't be used; the API defaults to 30
+    /// minutes from now.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_time: Option<String>,
+    /// RFC 3339 timestamp after which no new sessions can be started; the API defaults to
+    /// 1 minute from now.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_session_expire_time: Option<String>,
+    /// Locks sessions started with the token to this setup, e.g. a serialized `LiveSetup`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bidi_generate_content_setup: Option<Value>,
+}
+
+impl AuthToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_uses(mut self, uses: u32) -> Self {
+        self.uses = Some(uses);
+        self
+    }
+
+    #[must_use]
+    pub fn with_expire_time(mut self, expire_time: impl Into<String>) -> Self {
+        self.expire_time = Some(expire_time.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_new_session_expire_time(mut self, expire_time: impl Into<String>) -> Self {
+        self.new_session_expire_time = Some(expire_time.into());
+        self
+    }
+
+    /// Restricts the token to sessions with `setup`, so clients can't pick another model or
+    /// system instruction.
+    pub fn with_live_setup(mut self, setup: &impl Serialize) -> Result<Self, ApiRequestError> {
+        self.bidi_generate_content_setup = Some(serde_json::to_value(setup)?);
+        Ok(self)
+    }
+}
+
+impl Gemini {
+    /// Mints an ephemeral token. Only available on `v1alpha`, which is used regardless of
+    /// the client's API version.
+    pub async fn create_auth_token(&self, token: &AuthToken) -> Result<AuthToken, ApiRequestError> {
+        let url = format!("{BASE_URL}/v1alpha/auth_tokens");
+        let res = self
+            .send_checked(self.client.post(&url).json(token))
+            .await?;
+        crate::read_json(res).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::tests::MockTransport, API_KEY_HEADER};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_authorize_with_token_source() {
+        let gemini = Gemini::builder()
+            .token_source(StaticToken::new("secret-token"))
+            .build();
+
+        let request = gemini
+            .authorize_with_key(gemini.client.post("https://example.com/"))
+            .await
+            .unwrap()
+            .0
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.headers()[reqwest::header::AUTHORIZATION],
+            "Bearer secret-token"
+        );
+        assert_eq!(request.url().query(), None);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_api_key_pool_round_robin() {
+        let pool = ApiKeyPool::new(["a", "b", "c"]);
+        let keys: Vec<_> = (0..4).filter_map(|_| pool.next_key()).collect();
+        assert_eq!(keys, ["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_api_key_pool_least_recently_throttled() {
+        let pool = ApiKeyPool::new(["a", "b", "c"])
+            .with_strategy(RotationStrategy::LeastRecentlyThrottled);
+        pool.report_rate_limit("a");
+        pool.report_rate_limit("b");
+        assert_eq!(pool.next_key(), Some("c"));
+        pool.report_rate_limit("c");
+        assert_eq!(pool.next_key(), Some("a"));
+        assert!(ApiKeyPool::new(Vec::<String>::new()).next_key().is_none());
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_authorize_with_api_key_pool() {
+        let gemini = Gemini::builder()
+            .api_key_pool(ApiKeyPool::new(["first", "second"]))
+            .build();
+
+        for expected in ["first", "second"] {
+            let (request, api_key) = gemini
+                .authorize_with_key(gemini.client.post("https://example.com/"))
+                .await
+                .unwrap();
+            assert_eq!(api_key.as_deref(), Some(expected));
+            assert_eq!(request.build().unwrap().headers()[API_KEY_HEADER], expected);
+        }
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_authorize_with_api_key() {
+        let gemini = Gemini::builder().api_key("secret-key").build();
+
+        let request = gemini
+            .authorize_with_key(gemini.client.post("https://example.com/"))
+            .await
+            .unwrap()
+            .0
+            .build()
+            .unwrap();
+
+        assert!(request
+            .headers()
+            .get(reqwest::header::AUTHORIZATION)
+            .is_none());
+        assert_eq!(request.headers()[API_KEY_HEADER], "secret-key");
+        assert_eq!(request.url().query(), None);
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_create_auth_token() {
+        let transport = MockTransport::json(200, &json!({"name": "auth_tokens/abc", "uses": 1}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let token = gemini
+            .create_auth_token(
+                &AuthToken::new()
+                    .with_uses(1)
+                    .with_expire_time("2025-06-01T12:30:00Z")
+                    .with_live_setup(&json!({"model": "models/gemini-2.0-flash-live-001"}))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(token.name.as_deref(), Some("auth_tokens/abc"));
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url().path(), "/v1alpha/auth_tokens");
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "uses": 1,
+                "expireTime": "2025-06-01T12:30:00Z",
+                "bidiGenerateContentSetup": {"model": "models/gemini-2.0-flash-live-001"}
+            })
+        );
+    }
+}
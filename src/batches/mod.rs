@@ -0,0 +1,383 @@
+//! Batch Mode: submit many `generateContent` requests as one asynchronous job, at a lower price
+//! than interactive calls.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use typed_builder::TypedBuilder;
+
+use crate::{
+    messages::{GenerateContentRequest, GenerateContentResponse},
+    operations::LongRunning,
+    ApiRequestError, Gemini, BASE_URL,
+};
+
+/// One request of an inline batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InlinedRequest {
+    pub request: Value,
+    /// Returned unchanged with the matching result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+}
+
+impl InlinedRequest {
+    /// Wraps a request, tagging it with `key` so its result can be matched up later.
+    pub fn new(
+        request: &GenerateContentRequest,
+        key: impl Into<String>,
+    ) -> Result<Self, ApiRequestError> {
+        Ok(Self {
+            request: serde_json::to_value(request)?,
+            metadata: Some(json!({ "key": key.into() })),
+        })
+    }
+}
+
+/// Where the requests of a batch come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchInput {
+    Inline(Vec<InlinedRequest>),
+    /// Name of an uploaded JSONL file (`files/...`) with one `{"key", "request"}` object per
+    /// line.
+    File(String),
+}
+
+impl Serialize for BatchInput {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            BatchInput::Inline(requests) => {
+                json!({ "requests": { "requests": requests } }).serialize(serializer)
+            }
+            BatchInput::File(file_name) => json!({ "fileName": file_name }).serialize(serializer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchState {
+    #[default]
+    #[serde(rename = "BATCH_STATE_UNSPECIFIED")]
+    Unspecified,
+    #[serde(rename = "BATCH_STATE_PENDING")]
+    Pending,
+    #[serde(rename = "BATCH_STATE_RUNNING")]
+    Running,
+    #[serde(rename = "BATCH_STATE_SUCCEEDED")]
+    Succeeded,
+    #[serde(rename = "BATCH_STATE_FAILED")]
+    Failed,
+    #[serde(rename = "BATCH_STATE_CANCELLED")]
+    Cancelled,
+    #[serde(rename = "BATCH_STATE_EXPIRED")]
+    Expired,
+}
+
+impl BatchState {
+    /// Whether the job has stopped and will not change state anymore.
+    #[must_use]
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            BatchState::Succeeded
+                | BatchState::Failed
+                | BatchState::Cancelled
+                | BatchState::Expired
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchStats {
+    #[serde(default, with = "crate::int64")]
+    pub request_count: u64,
+    #[serde(default, with = "crate::int64")]
+    pub successful_request_count: u64,
+    #[serde(default, with = "crate::int64")]
+    pub failed_request_count: u64,
+    #[serde(default, with = "crate::int64")]
+    pub pending_request_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchMetadata {
+    pub model: Option<String>,
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub state: BatchState,
+    pub create_time: Option<String>,
+    pub update_time: Option<String>,
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub batch_stats: BatchStats,
+}
+
+/// Result of a single request in the batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchResult {
+    /// Key of the request, for results read from a responses file.
+    pub key: Option<String>,
+    /// Metadata of the request, for inline results.
+    pub metadata: Option<Value>,
+    pub response: Option<GenerateContentResponse>,
+    pub error: Option<Value>,
+}
+
+impl BatchResult {
+    /// The key the request was tagged with, from either output format.
+    #[must_use]
+    pub fn key(&self) -> Option<&str> {
+        self.key
+            .as_deref()
+            .or_else(|| self.metadata.as_ref()?["key"].as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlinedResponses {
+    #[serde(default)]
+    pub inlined_responses: Vec<BatchResult>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOutput {
+    /// Name of the JSONL file holding the results of a file-based batch.
+    pub responses_file: Option<String>,
+    pub inlined_responses: Option<InlinedResponses>,
+}
+
+/// A batch job, as returned by the long-running operation endpoints.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchJob {
+    /// Resource name, `batches/...`.
+    pub name: String,
+    #[serde(default)]
+    pub metadata: BatchMetadata,
+    #[serde(default)]
+    pub done: bool,
+    pub response: Option<BatchOutput>,
+    pub error: Option<Value>,
+}
+
+impl BatchJob {
+    #[must_use]
+    pub fn state(&self) -> BatchState {
+        self.metadata.state
+    }
+}
+
+impl LongRunning for BatchJob {
+    fn is_done(&self) -> bool {
+        self.done || self.state().is_terminal()
+    }
+}
+
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct BatchRequest {
+    #[builder(setter(into))]
+    model: String,
+    #[builder(default, setter(into))]
+    display_name: String,
+    input: BatchInput,
+    gemini: Gemini,
+}
+
+impl BatchRequest {
+    /// Submits the batch, returning the pending job.
+    pub async fn send(&self) -> Result<BatchJob, ApiRequestError> {
+        let url = self.gemini.model_url(&self.model, "batchGenerateContent");
+        let body = json!({
+            "batch": {
+                "displayName": self.display_name,
+                "inputConfig": self.input,
+            }
+        });
+        let res = self
+            .gemini
+            .send_checked(self.gemini.client.post(&url).json(&body))
+            .await?;
+        crate::read_json(res).await
+    }
+}
+
+impl Gemini {
+    pub fn create_batch(&self) -> BatchRequestBuilder<((), (), (), (Gemini,))> {
+        BatchRequest::builder().gemini(self.clone())
+    }
+
+    /// Fetches the current state of a batch job. To wait until it finishes, use
+    /// [`Gemini::wait_for`] with a `BatchJob`.
+    pub async fn get_batch(&self, name: &str) -> Result<BatchJob, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn cancel_batch(&self, name: &str) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}:cancel", BASE_URL, self.api_version, name);
+        self.send_checked(self.client.post(&url)).await?;
+        Ok(())
+    }
+
+    pub async fn delete_batch(&self, name: &str) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        self.send_checked(self.client.delete(&url)).await?;
+        Ok(())
+    }
+
+    /// Results of a finished job, read inline or downloaded from its responses file.
+    pub async fn batch_results(&self, job: &BatchJob) -> Result<Vec<BatchResult>, ApiRequestError> {
+        let Some(output) = &job.response else {
+            return Ok(Vec::new());
+        };
+        if let Some(inlined) = &output.inlined_responses {
+            return Ok(inlined.inlined_responses.clone());
+        }
+        let Some(file) = &output.responses_file else {
+            return Ok(Vec::new());
+        };
+        let text = self
+            .send_checked(self.client.get(self.download_url(file)))
+            .await?
+            .text()
+            .await?;
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(ApiRequestError::from))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_batch_job_deserialization() {
+        let job: BatchJob = serde_json::from_value(json!({
+            "name": "batches/123",
+            "metadata": {
+                "@type": "type.googleapis.com/google.ai.generativelanguage.v1main.GenerateContentBatch",
+                "model": "models/gemini-2.5-flash",
+                "state": "BATCH_STATE_SUCCEEDED",
+                "batchStats": {"requestCount": "2", "successfulRequestCount": "2"}
+            },
+            "done": true,
+            "response": {
+                "inlinedResponses": {
+                    "inlinedResponses": [{
+                        "response": {
+                            "candidates": [{
+                                "content": {"role": "model", "parts": [{"text": "Hi"}]},
+                                "finishReason": "STOP",
+                                "index": 0
+                            }]
+                        },
+                        "metadata": {"key": "first"}
+                    }]
+                }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(job.state(), BatchState::Succeeded);
+        assert!(job.state().is_terminal());
+        assert_eq!(job.metadata.batch_stats.request_count, 2);
+        let results = job.response.unwrap().inlined_responses.unwrap();
+        assert_eq!(results.inlined_responses[0].key(), Some("first"));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_create_batch() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "name": "batches/123",
+                "metadata": {"state": "BATCH_STATE_PENDING"}
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-2.5-flash")
+            .build();
+
+        let job = gemini
+            .create_batch()
+            .model("gemini-2.5-flash")
+            .display_name("eval")
+            .input(BatchInput::Inline(vec![InlinedRequest::new(
+                &request, "first",
+            )
+            .unwrap()]))
+            .build()
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(job.name, "batches/123");
+        assert_eq!(job.state(), BatchState::Pending);
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].url().path(),
+            "/v1beta/models/gemini-2.5-flash:batchGenerateContent"
+        );
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        let inlined = &body["batch"]["inputConfig"]["requests"]["requests"][0];
+        assert_eq!(inlined["metadata"]["key"], "first");
+        assert_eq!(
+            inlined["request"]["contents"][0]["parts"][0]["text"],
+            "hello"
+        );
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_batch_results_from_file() {
+        let transport = MockTransport::new(
+            200,
+            concat!(
+                r#"{"key": "a", "response": {"candidates": []}}"#,
+                "\n",
+                r#"{"key": "b", "error": {"code": 400, "message": "bad"}}"#,
+                "\n"
+            ),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let job: BatchJob = serde_json::from_value(json!({
+            "name": "batches/123",
+            "done": true,
+            "response": {"responsesFile": "files/out"}
+        }))
+        .unwrap();
+
+        let results = gemini.batch_results(&job).await.unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].key(), Some("a"));
+        assert!(results[0].response.is_some());
+        assert!(results[1].error.is_some());
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].url().path(),
+            "/download/v1beta/files/out:download"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Synchronous request support built on `reqwest::blocking`, for CLI tools and build scripts
+//! that don't run an async runtime. Enabled with the `blocking` feature.
+//!
+//! Requests are built exactly like their async counterparts and sent with `send_blocking`.
+
+use crate::{ApiRequestError, Credential, Gemini, HttpDetails, API_KEY_HEADER};
+
+impl Gemini {
+    /// The blocking HTTP client, built on first use with the configured proxies, root
+    /// certificates and connection options. reqwest has no HTTP/2 keep-alive for blocking
+    /// clients, so `http2_keep_alive_interval` only applies to async requests.
+    pub(crate) fn blocking_client(&self) -> &reqwest::blocking::Client {
+        self.blocking_client.get_or_init(|| {
+            let mut builder = self
+                .proxies
+                .iter()
+                .cloned()
+                .fold(reqwest::blocking::Client::builder(), |builder, proxy| {
+                    builder.proxy(proxy)
+                });
+            builder = self
+                .root_certificates
+                .iter()
+                .cloned()
+                .fold(builder, |builder, certificate| {
+                    builder.add_root_certificate(certificate)
+                })
+                .tcp_keepalive(self.tcp_keepalive);
+            if let Some(max) = self.pool_max_idle_per_host {
+                builder = builder.pool_max_idle_per_host(max);
+            }
+            if let Some(timeout) = self.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            builder
+                .build()
+                .expect("Failed to build blocking HTTP client")
+        })
+    }
+
+    /// Blocking counterpart of `Gemini::authorize_with_key`. Token sources are polled with a
+    /// minimal executor, so they must not depend on a particular async runtime.
+    pub(crate) fn authorize_blocking(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> Result<(reqwest::blocking::RequestBuilder, Option<String>), ApiRequestError> {
+        match futures::executor::block_on(self.credential())? {
+            Credential::BearerToken(token) => Ok((request.bearer_auth(token), None)),
+            Credential::ApiKey(api_key) => {
+                Ok((request.header(API_KEY_HEADER, &api_key), Some(api_key)))
+            }
+            Credential::Anonymous => Ok((request, None)),
+        }
+    }
+
+    /// Blocking counterpart of `Gemini::check_status`.
+    pub(crate) fn check_status_blocking(
+        &self,
+        res: reqwest::blocking::Response,
+        api_key: Option<&str>,
+    ) -> Result<reqwest::blocking::Response, ApiRequestError> {
+        match res.status().as_u16() {
+            200..=299 => Ok(res),
+            429 => {
+                self.report_rate_limit(api_key);
+                Err(ApiRequestError::RateLimit)
+            }
+            _ => Err(error_from_response(res)),
+        }
+    }
+
+    /// Blocking counterpart of `Gemini::acquire_rate_limit`. The limiters' async waits need a
+    /// tokio timer, so this polls them and sleeps the thread in between instead.
+    #[cfg(feature = "leaky-bucket")]
+    pub(crate) fn acquire_rate_limit_blocking(&self, model: Option<&str>) {
+        for limiter in self.rate_limiters(model) {
+            while !limiter.try_acquire(1) {
+                std::thread::sleep(limiter.interval());
+            }
+        }
+    }
+}
+
+/// Blocking counterpart of `crate::read_json`.
+pub(crate) fn read_json<T: serde::de::DeserializeOwned>(
+    res: reqwest::blocking::Response,
+) -> Result<T, ApiRequestError> {
+    let status = res.status().as_u16();
+    let headers = res.headers().clone();
+    let body = res.text()?;
+    crate::decode_json(status, &headers, &body)
+}
+
+/// Blocking counterpart of `ApiRequestError::from_response`.
+pub(crate) fn error_from_response(res: reqwest::blocking::Response) -> ApiRequestError {
+    let status = res.status().as_u16();
+    let headers = res.headers().clone();
+    match res.text() {
+        Ok(body) => {
+            ApiRequestError::from_error_body(HttpDetails::new(status, &headers, &body), &body)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::StaticToken;
+
+    #[test]
+    fn test_authorize_blocking() {
+        let gemini = Gemini::builder().api_key("secret-key").build();
+        let (request, api_key) = gemini
+            .authorize_blocking(gemini.blocking_client().post("https://example.com/"))
+            .unwrap();
+        assert_eq!(api_key.as_deref(), Some("secret-key"));
+        let request = request.build().unwrap();
+        assert_eq!(request.headers()[API_KEY_HEADER], "secret-key");
+        assert_eq!(request.url().query(), None);
+
+        let gemini = Gemini::builder()
+            .token_source(StaticToken::new("secret-token"))
+            .build();
+        let (request, _) = gemini
+            .authorize_blocking(gemini.blocking_client().post("https://example.com/"))
+            .unwrap();
+        assert_eq!(
+            request.build().unwrap().headers()[reqwest::header::AUTHORIZATION],
+            "Bearer secret-token"
+        );
+    }
+}
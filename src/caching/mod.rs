@@ -0,0 +1,381 @@
+//! Context caching: contents stored once on the server and referenced by later requests, so
+//! large documents aren't re-sent and are billed at the reduced cached-input rate.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{messages::message::Content, models::ModelResource, ApiRequestError, Gemini, BASE_URL};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedContent {
+    /// Resource name, `cachedContents/*`; assigned by the API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Model the contents are cached for, `models/*`. Requests using the cache must use the
+    /// same model.
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contents: Vec<Content<'static>>,
+    /// Time to live, e.g. `"300s"`; only sent when creating or updating the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub create_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_metadata: Option<CachedContentUsageMetadata>,
+}
+
+impl CachedContent {
+    pub fn new(
+        model: impl Into<String>,
+        contents: impl IntoIterator<Item = Content<'static>>,
+    ) -> Self {
+        Self {
+            model: ModelResource::parse(&model.into()).to_string(),
+            contents: contents.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(format!("{}s", ttl.as_secs()));
+        self
+    }
+
+    #[must_use]
+    pub fn with_display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.display_name = Some(display_name.into());
+        self
+    }
+
+    /// When the cache expires, `None` if the API hasn't reported it yet.
+    #[must_use]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expire_time
+            .as_deref()
+            .and_then(crate::timestamp::parse)
+    }
+
+    /// Whether the cache has expired as of the last time it was fetched or updated. Not
+    /// available on wasm32, which has no system clock.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| expires_at <= SystemTime::now())
+    }
+
+    /// Makes the cache expire `ttl` from now, and updates `self` with the API's response.
+    pub async fn extend_ttl(
+        &mut self,
+        gemini: &Gemini,
+        ttl: Duration,
+    ) -> Result<(), ApiRequestError> {
+        *self = gemini
+            .update_cached_content_expiration(self.resource_name()?, Expiration::Ttl(ttl))
+            .await?;
+        Ok(())
+    }
+
+    /// Makes the cache expire at `expire_time`, and updates `self` with the API's response.
+    pub async fn set_expire_time(
+        &mut self,
+        gemini: &Gemini,
+        expire_time: SystemTime,
+    ) -> Result<(), ApiRequestError> {
+        *self = gemini
+            .update_cached_content_expiration(
+                self.resource_name()?,
+                Expiration::ExpireTime(expire_time),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Keeps extending the cache by `ttl`, every half `ttl`, until the returned guard is
+    /// dropped. Failed refreshes are retried on the next tick.
+    #[cfg(feature = "tokio")]
+    pub fn keep_alive(
+        &self,
+        gemini: &Gemini,
+        ttl: Duration,
+    ) -> Result<CacheKeepAlive, ApiRequestError> {
+        let name = self.resource_name()?.to_string();
+        let gemini = gemini.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                futures_timer::Delay::new(ttl / 2).await;
+                // Whatever went wrong, the next refresh comes well before the cache expires.
+                let _ = gemini
+                    .update_cached_content_expiration(&name, Expiration::Ttl(ttl))
+                    .await;
+            }
+        });
+        Ok(CacheKeepAlive { task })
+    }
+
+    fn resource_name(&self) -> Result<&str, ApiRequestError> {
+        self.name
+            .as_deref()
+            .ok_or_else(|| ApiRequestError::InvalidRequestError {
+                code: None,
+                details: Box::new(json!({})),
+                message: "Cached content has no name; create it first".to_string(),
+                status: None,
+                http: None,
+            })
+    }
+}
+
+/// When a cache expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiration {
+    /// This long from now.
+    Ttl(Duration),
+    /// At this time.
+    ExpireTime(SystemTime),
+}
+
+/// Refreshes a cache's TTL until dropped, see [`CachedContent::keep_alive`].
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct CacheKeepAlive {
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "tokio")]
+impl Drop for CacheKeepAlive {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedContentUsageMetadata {
+    #[serde(default)]
+    pub total_token_count: u32,
+}
+
+impl Gemini {
+    /// Stores `content` on the server; pass the returned `name` as `cached_content` of later
+    /// requests.
+    pub async fn create_cached_content(
+        &self,
+        content: &CachedContent,
+    ) -> Result<CachedContent, ApiRequestError> {
+        let url = format!("{}/{}/cachedContents", BASE_URL, self.api_version);
+        let res = self
+            .send_checked(self.client.post(&url).json(content))
+            .await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn get_cached_content(&self, name: &str) -> Result<CachedContent, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    /// Changes when the cache `name` expires; the only update the API allows.
+    pub async fn update_cached_content_expiration(
+        &self,
+        name: &str,
+        expiration: Expiration,
+    ) -> Result<CachedContent, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let (update_mask, body) = match expiration {
+            Expiration::Ttl(ttl) => ("ttl", json!({ "ttl": format!("{}s", ttl.as_secs()) })),
+            Expiration::ExpireTime(expire_time) => (
+                "expireTime",
+                json!({ "expireTime": crate::timestamp::format(expire_time) }),
+            ),
+        };
+        let request = self
+            .client
+            .patch(&url)
+            .query(&[("updateMask", update_mask)])
+            .json(&body);
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn delete_cached_content(&self, name: &str) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        self.send_checked(self.client.delete(&url)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[tokio::test]
+    async fn test_create_cached_content() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "name": "cachedContents/abc",
+                "model": "models/gemini-1.5-flash-001",
+                "expireTime": "2024-10-01T12:05:00Z",
+                "usageMetadata": {"totalTokenCount": 40000}
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let content = CachedContent::new("gemini-1.5-flash-001", [Content::from("long text")])
+            .with_ttl(Duration::from_mins(5));
+        let cached = gemini.create_cached_content(&content).await.unwrap();
+        assert_eq!(cached.name.as_deref(), Some("cachedContents/abc"));
+        assert_eq!(cached.usage_metadata.unwrap().total_token_count, 40000);
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url().path(), "/v1beta/cachedContents");
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "model": "models/gemini-1.5-flash-001",
+                "contents": [{"role": "user", "parts": [{"text": "long text"}]}],
+                "ttl": "300s"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_expiration() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "name": "cachedContents/abc",
+                "model": "models/gemini-1.5-flash-001",
+                "expireTime": "2024-10-01T12:15:00.5Z"
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let mut cached = CachedContent {
+            name: Some("cachedContents/abc".to_string()),
+            expire_time: Some("2024-10-01T12:05:00Z".to_string()),
+            ..CachedContent::default()
+        };
+        assert!(cached.is_expired());
+
+        cached
+            .extend_ttl(&gemini, Duration::from_mins(10))
+            .await
+            .unwrap();
+        let expire_time = UNIX_EPOCH + Duration::from_mins(28_796_420);
+        cached.set_expire_time(&gemini, expire_time).await.unwrap();
+
+        // `self` now holds the API's response.
+        assert_eq!(
+            cached.expires_at(),
+            Some(UNIX_EPOCH + Duration::new(1_727_784_900, 500_000_000))
+        );
+        assert!(CachedContent::default()
+            .set_expire_time(&gemini, expire_time)
+            .await
+            .is_err());
+
+        let requests = transport.requests.lock().unwrap();
+        let bodies: Vec<Value> = requests
+            .iter()
+            .map(|request| {
+                serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(requests[0].method(), "PATCH");
+        assert_eq!(requests[0].url().path(), "/v1beta/cachedContents/abc");
+        assert_eq!(requests[0].url().query(), Some("updateMask=ttl"));
+        assert_eq!(bodies[0], json!({"ttl": "600s"}));
+        assert_eq!(requests[1].url().query(), Some("updateMask=expireTime"));
+        assert_eq!(
+            bodies[1],
+            json!({"expireTime": "2024-10-01T12:20:00.000000000Z"})
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let transport = MockTransport::json(200, &json!({"model": "models/gemini-1.5-flash"}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let cached = CachedContent {
+            name: Some("cachedContents/abc".to_string()),
+            ..CachedContent::default()
+        };
+
+        let keep_alive = cached
+            .keep_alive(&gemini, Duration::from_millis(20))
+            .unwrap();
+        futures_timer::Delay::new(Duration::from_millis(35)).await;
+        drop(keep_alive);
+        let refreshes = transport.requests.lock().unwrap().len();
+        futures_timer::Delay::new(Duration::from_millis(30)).await;
+
+        assert!(refreshes >= 1);
+        assert_eq!(transport.requests.lock().unwrap().len(), refreshes);
+        assert_eq!(
+            transport.requests.lock().unwrap()[0].url().query(),
+            Some("updateMask=ttl")
+        );
+    }
+
+    #[test]
+    fn test_timestamps() {
+        use crate::timestamp::{format, parse};
+
+        for (timestamp, secs, nanos) in [
+            ("1970-01-01T00:00:00Z", 0, 0),
+            ("2024-02-29T23:59:59.123456Z", 1_709_251_199, 123_456_000),
+            ("2024-10-01T14:05:00+02:00", 1_727_784_300, 0),
+            (
+                "2024-10-01T10:05:00.1234567891-02:00",
+                1_727_784_300,
+                123_456_789,
+            ),
+        ] {
+            let time = UNIX_EPOCH + Duration::new(secs, nanos);
+            assert_eq!(parse(timestamp), Some(time), "{timestamp}");
+            assert_eq!(parse(&format(time)), Some(time));
+        }
+        assert_eq!(
+            format(UNIX_EPOCH + Duration::from_secs(1_709_251_199)),
+            "2024-02-29T23:59:59.000000000Z"
+        );
+        for malformed in [
+            "",
+            "2024-10-01",
+            "2024-13-01T00:00:00Z",
+            "2024-10-01T00:00:00",
+        ] {
+            assert_eq!(parse(malformed), None, "{malformed}");
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Stops sending requests to a degraded upstream for a while, so failing calls fail fast
+//! instead of every worker retrying into an outage.
+//!
+//! Register a [`CircuitBreaker`] with `Gemini::builder().circuit_breaker(...)`. After
+//! `failure_threshold` consecutive server errors or timeouts the circuit opens and requests
+//! fail with [`ApiRequestError::CircuitOpen`] without being sent. Once `cooldown` has passed a
+//! single probe request is let through: the circuit closes if it succeeds and opens again if
+//! it fails. State changes are reported to
+//! [`Observer::on_circuit_state_changed`](crate::observer::Observer::on_circuit_state_changed).
+//!
+//! Applies to async requests only, and is unavailable on wasm32, which has no monotonic clock.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::ApiRequestError;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests are sent normally.
+    #[default]
+    Closed,
+    /// Requests are rejected until the cooldown has passed.
+    Open,
+    /// A probe request is in flight; other requests are rejected until it completes.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    /// When the circuit opened, or when the current probe was let through.
+    since: Option<Instant>,
+}
+
+/// How a request that got past the breaker went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Success,
+    /// A server error, timeout or connection failure.
+    Failure,
+    /// Neither, e.g. a client error or a request that couldn't be built.
+    Neutral,
+}
+
+impl Outcome {
+    pub(crate) fn of(result: &Result<reqwest::Response, ApiRequestError>) -> Self {
+        match result {
+            Ok(res) if res.status().is_server_error() => Outcome::Failure,
+            Ok(_) => Outcome::Success,
+            Err(ApiRequestError::ReqwestError(e)) if e.is_timeout() || e.is_connect() => {
+                Outcome::Failure
+            }
+            Err(_) => Outcome::Neutral,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Opens after `failure_threshold` consecutive failures and probes again after `cooldown`.
+    #[must_use]
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Lets a request through or rejects it. Returns the state change it caused, if any.
+    pub(crate) fn acquire(&self) -> Result<Option<CircuitState>, ApiRequestError> {
+        let mut inner = self.inner.lock().unwrap();
+        let elapsed = inner.since.map_or(Duration::MAX, |since| since.elapsed());
+        match inner.state {
+            CircuitState::Closed => Ok(None),
+            // A probe whose outcome was never recorded (e.g. it was cancelled) is replaced
+            // after another cooldown.
+            CircuitState::Open | CircuitState::HalfOpen if elapsed >= self.cooldown => {
+                let changed = inner.state != CircuitState::HalfOpen;
+                inner.state = CircuitState::HalfOpen;
+                inner.since = Some(Instant::now());
+                Ok(changed.then_some(CircuitState::HalfOpen))
+            }
+            CircuitState::Open | CircuitState::HalfOpen => Err(ApiRequestError::CircuitOpen {
+                retry_after: self.cooldown.saturating_sub(elapsed),
+            }),
+        }
+    }
+
+    /// Records the outcome of a request let through by [`CircuitBreaker::acquire`]. Returns
+    /// the state change it caused, if any.
+    pub(crate) fn record(&self, outcome: Outcome) -> Option<CircuitState> {
+        let mut inner = self.inner.lock().unwrap();
+        let next = match (inner.state, outcome) {
+            // Requests let through before the circuit opened may still be completing.
+            (_, Outcome::Neutral) | (CircuitState::Open, Outcome::Failure) => return None,
+            (_, Outcome::Success) => {
+                inner.consecutive_failures = 0;
+                CircuitState::Closed
+            }
+            (CircuitState::Closed, Outcome::Failure) => {
+                inner.consecutive_failures += 1;
+                if inner.consecutive_failures < self.failure_threshold {
+                    return None;
+                }
+                CircuitState::Open
+            }
+            (CircuitState::HalfOpen, Outcome::Failure) => CircuitState::Open,
+        };
+        if next == inner.state {
+            return None;
+        }
+        if next == CircuitState::Open {
+            inner.since = Some(Instant::now());
+        }
+        inner.state = next;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{observer::Observer, transport::tests::MockTransport, Gemini};
+
+    #[test]
+    fn test_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::ZERO);
+
+        assert_eq!(breaker.record(Outcome::Failure), None);
+        assert_eq!(breaker.record(Outcome::Failure), Some(CircuitState::Open));
+        assert_eq!(breaker.acquire().unwrap(), Some(CircuitState::HalfOpen));
+        assert_eq!(breaker.record(Outcome::Failure), Some(CircuitState::Open));
+        assert_eq!(breaker.acquire().unwrap(), Some(CircuitState::HalfOpen));
+        assert_eq!(breaker.record(Outcome::Success), Some(CircuitState::Closed));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_rejects_while_open() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(30));
+        breaker.record(Outcome::Failure);
+
+        assert!(matches!(
+            breaker.acquire(),
+            Err(ApiRequestError::CircuitOpen { retry_after }) if retry_after > Duration::from_secs(29)
+        ));
+        // Client errors say nothing about the upstream's health.
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(30));
+        assert_eq!(breaker.record(Outcome::Neutral), None);
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[derive(Default)]
+    struct StateChanges(Mutex<Vec<CircuitState>>);
+
+    impl Observer for Arc<StateChanges> {
+        fn on_circuit_state_changed(&self, state: CircuitState) {
+            self.0.lock().unwrap().push(state);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_fails_fast_when_open() {
+        let transport = MockTransport::json(503, &json!({}));
+        let changes = Arc::new(StateChanges::default());
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .circuit_breaker(CircuitBreaker::new(2, Duration::from_secs(30)))
+            .observer(Arc::clone(&changes))
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents("hello")
+            .model("gemini-1.5-flash")
+            .build();
+
+        for _ in 0..2 {
+            assert!(matches!(
+                request.send().await,
+                Err(ApiRequestError::HttpError { .. })
+            ));
+        }
+        let err = request.send().await.unwrap_err();
+
+        assert!(matches!(err, ApiRequestError::CircuitOpen { .. }));
+        assert!(!err.is_transient());
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+        assert_eq!(*changes.0.lock().unwrap(), vec![CircuitState::Open]);
+    }
+}
@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{ApiRequestError, Gemini, BASE_URL};
+
+/// Maximum number of chunks per batch request.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// A piece of a document, the unit that is embedded and retrieved.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    /// Resource name, `corpora/*/documents/*/chunks/*`. Generated when empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub data: ChunkData,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_metadata: Vec<CustomMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<ChunkState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub create_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_time: Option<String>,
+}
+
+impl Chunk {
+    pub fn new(data: impl Into<ChunkData>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: impl IntoIterator<Item = CustomMetadata>) -> Self {
+        self.custom_metadata.extend(metadata);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkData {
+    pub string_value: String,
+}
+
+impl From<String> for ChunkData {
+    fn from(string_value: String) -> Self {
+        Self { string_value }
+    }
+}
+
+impl From<&str> for ChunkData {
+    fn from(value: &str) -> Self {
+        value.to_string().into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ChunkState {
+    StateUnspecified,
+    StatePendingProcessing,
+    StateActive,
+    StateFailed,
+}
+
+/// User-provided metadata stored with documents and chunks, usable in metadata filters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomMetadata {
+    pub key: String,
+    #[serde(flatten)]
+    pub value: MetadataValue,
+}
+
+impl CustomMetadata {
+    pub fn new(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MetadataValue {
+    StringValue(String),
+    StringListValue(StringList),
+    NumericValue(f64),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StringList {
+    pub values: Vec<String>,
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        MetadataValue::StringValue(value)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::StringValue(value.to_string())
+    }
+}
+
+impl From<Vec<String>> for MetadataValue {
+    fn from(values: Vec<String>) -> Self {
+        MetadataValue::StringListValue(StringList { values })
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::NumericValue(value)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListChunksResponse {
+    #[serde(default)]
+    pub chunks: Vec<Chunk>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct BatchChunksResponse {
+    #[serde(default)]
+    chunks: Vec<Chunk>,
+}
+
+impl Gemini {
+    /// Creates `chunk` in `document` (`corpora/*/documents/*`).
+    pub async fn create_chunk(
+        &self,
+        document: &str,
+        chunk: &Chunk,
+    ) -> Result<Chunk, ApiRequestError> {
+        let url = format!("{}/{}/{}/chunks", BASE_URL, self.api_version, document);
+        let res = self
+            .send_checked(self.client.post(&url).json(chunk))
+            .await?;
+        crate::read_json(res).await
+    }
+
+    /// Creates many chunks in `document`, split into as many batch requests as the service's
+    /// per-request limit requires. Returns the created chunks in input order.
+    pub async fn batch_create_chunks(
+        &self,
+        document: &str,
+        chunks: &[Chunk],
+    ) -> Result<Vec<Chunk>, ApiRequestError> {
+        let url = format!(
+            "{}/{}/{}/chunks:batchCreate",
+            BASE_URL, self.api_version, document
+        );
+        let mut created = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(MAX_BATCH_SIZE) {
+            let requests: Vec<_> = batch
+                .iter()
+                .map(|chunk| json!({ "parent": document, "chunk": chunk }))
+                .collect();
+            let res = self
+                .send_checked(
+                    self.client
+                        .post(&url)
+                        .json(&json!({ "requests": requests })),
+                )
+                .await?;
+            created.extend(res.json::<BatchChunksResponse>().await?.chunks);
+        }
+        Ok(created)
+    }
+
+    /// Lists one page of the chunks in `document`.
+    pub async fn list_chunks(
+        &self,
+        document: &str,
+        page_token: Option<&str>,
+    ) -> Result<ListChunksResponse, ApiRequestError> {
+        let url = format!("{}/{}/{}/chunks", BASE_URL, self.api_version, document);
+        let mut request = self.client.get(&url);
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn get_chunk(&self, name: &str) -> Result<Chunk, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    /// Replaces the data and custom metadata of the chunk named `chunk.name`.
+    pub async fn update_chunk(&self, chunk: &Chunk) -> Result<Chunk, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, chunk.name);
+        let request = self
+            .client
+            .patch(&url)
+            .query(&[("updateMask", "data,customMetadata")])
+            .json(chunk);
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn delete_chunk(&self, name: &str) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        self.send_checked(self.client.delete(&url)).await?;
+        Ok(())
+    }
+
+    /// Deletes the chunks named in `names` from `document`.
+    pub async fn batch_delete_chunks(
+        &self,
+        document: &str,
+        names: &[impl AsRef<str>],
+    ) -> Result<(), ApiRequestError> {
+        let url = format!(
+            "{}/{}/{}/chunks:batchDelete",
+            BASE_URL, self.api_version, document
+        );
+        for batch in names.chunks(MAX_BATCH_SIZE) {
+            let requests: Vec<_> = batch
+                .iter()
+                .map(|name| json!({ "name": name.as_ref() }))
+                .collect();
+            self.send_checked(
+                self.client
+                    .post(&url)
+                    .json(&json!({ "requests": requests })),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_batch_create_chunks_splits_requests() {
+        let transport = MockTransport::json(
+            200,
+            &json!({"chunks": [{"name": "corpora/c/documents/d/chunks/x", "data": {"stringValue": "x"}}]}),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let chunks: Vec<Chunk> = (0..150)
+            .map(|i| {
+                Chunk::new(format!("chunk {i}"))
+                    .with_metadata([CustomMetadata::new("index", f64::from(i))])
+            })
+            .collect();
+
+        let created = gemini
+            .batch_create_chunks("corpora/c/documents/d", &chunks)
+            .await
+            .unwrap();
+
+        assert_eq!(created.len(), 2);
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].url().path(),
+            "/v1beta/corpora/c/documents/d/chunks:batchCreate"
+        );
+        let body: Value =
+            serde_json::from_slice(requests[1].body().unwrap().as_bytes().unwrap()).unwrap();
+        let batch = body["requests"].as_array().unwrap();
+        assert_eq!(batch.len(), 50);
+        assert_eq!(batch[0]["parent"], "corpora/c/documents/d");
+        assert_eq!(
+            batch[0]["chunk"],
+            json!({
+                "data": {"stringValue": "chunk 100"},
+                "customMetadata": [{"key": "index", "numericValue": 100.0}]
+            })
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use super::CustomMetadata;
+use crate::{ApiRequestError, Gemini, BASE_URL};
+
+/// A document in a corpus, holding a list of chunks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    /// Resource name, `corpora/*/documents/*`. Generated when empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_metadata: Vec<CustomMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub create_time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_time: Option<String>,
+}
+
+impl Document {
+    pub fn new(display_name: impl Into<String>) -> Self {
+        Self {
+            display_name: Some(display_name.into()),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: impl IntoIterator<Item = CustomMetadata>) -> Self {
+        self.custom_metadata.extend(metadata);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListDocumentsResponse {
+    #[serde(default)]
+    pub documents: Vec<Document>,
+    pub next_page_token: Option<String>,
+}
+
+impl Gemini {
+    /// Creates `document` in `corpus` (`corpora/*`).
+    pub async fn create_document(
+        &self,
+        corpus: &str,
+        document: &Document,
+    ) -> Result<Document, ApiRequestError> {
+        let url = format!("{}/{}/{}/documents", BASE_URL, self.api_version, corpus);
+        let res = self
+            .send_checked(self.client.post(&url).json(document))
+            .await?;
+        crate::read_json(res).await
+    }
+
+    /// Lists one page of the documents in `corpus`.
+    pub async fn list_documents(
+        &self,
+        corpus: &str,
+        page_token: Option<&str>,
+    ) -> Result<ListDocumentsResponse, ApiRequestError> {
+        let url = format!("{}/{}/{}/documents", BASE_URL, self.api_version, corpus);
+        let mut request = self.client.get(&url);
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn get_document(&self, name: &str) -> Result<Document, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    /// Replaces the display name and custom metadata of the document named `document.name`.
+    pub async fn update_document(&self, document: &Document) -> Result<Document, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, document.name);
+        let request = self
+            .client
+            .patch(&url)
+            .query(&[("updateMask", "displayName,customMetadata")])
+            .json(document);
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    /// Deletes a document. With `force`, its chunks are deleted too; otherwise deleting a
+    /// non-empty document fails.
+    pub async fn delete_document(&self, name: &str, force: bool) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let request = self.client.delete(&url).query(&[("force", force)]);
+        self.send_checked(request).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+//! Semantic Retrieval: corpora of documents and chunks that can be queried for relevant
+//! passages.
+//!
+//! A typical ingestion pipeline creates a corpus, a [`Document`] per source and then uploads
+//! its passages with [`Gemini::batch_create_chunks`].
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use typed_builder::TypedBuilder;
+
+use crate::{ApiRequestError, Gemini, BASE_URL};
+
+mod chunks;
+mod documents;
+
+pub use chunks::{
+    Chunk, ChunkData, ChunkState, CustomMetadata, ListChunksResponse, MetadataValue, StringList,
+};
+pub use documents::{Document, ListDocumentsResponse};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Corpus {
+    /// Resource name, `corpora/*`.
+    pub name: String,
+    pub display_name: Option<String>,
+    pub create_time: Option<String>,
+    pub update_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListCorporaResponse {
+    #[serde(default)]
+    pub corpora: Vec<Corpus>,
+    /// Pass to [`Gemini::list_corpora`] to fetch the next page; `None` on the last page.
+    pub next_page_token: Option<String>,
+}
+
+/// Restricts a query to chunks (or documents) whose metadata under `key` matches any of the
+/// `conditions`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataFilter {
+    pub key: String,
+    pub conditions: Vec<Condition>,
+}
+
+impl MetadataFilter {
+    pub fn new(key: impl Into<String>, conditions: impl IntoIterator<Item = Condition>) -> Self {
+        Self {
+            key: key.into(),
+            conditions: conditions.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    #[serde(flatten)]
+    pub value: ConditionValue,
+    pub operation: Operator,
+}
+
+impl Condition {
+    pub fn new(operation: Operator, value: impl Into<ConditionValue>) -> Self {
+        Self {
+            value: value.into(),
+            operation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConditionValue {
+    StringValue(String),
+    NumericValue(f64),
+}
+
+impl From<&str> for ConditionValue {
+    fn from(value: &str) -> Self {
+        ConditionValue::StringValue(value.to_string())
+    }
+}
+
+impl From<String> for ConditionValue {
+    fn from(value: String) -> Self {
+        ConditionValue::StringValue(value)
+    }
+}
+
+impl From<f64> for ConditionValue {
+    fn from(value: f64) -> Self {
+        ConditionValue::NumericValue(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Operator {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+    NotEqual,
+    Includes,
+    Excludes,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCorpusResponse {
+    #[serde(default)]
+    pub relevant_chunks: Vec<RelevantChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelevantChunk {
+    #[serde(default)]
+    pub chunk_relevance_score: f32,
+    pub chunk: Chunk,
+}
+
+#[derive(Debug, Clone, Serialize, TypedBuilder)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCorpusRequest {
+    /// Corpus to query, `corpora/*`.
+    #[builder(setter(into))]
+    #[serde(skip)]
+    corpus: String,
+    #[builder(setter(into))]
+    query: String,
+    #[builder(default, setter(transform = |filters: impl IntoIterator<Item = MetadataFilter>| filters.into_iter().collect()))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    metadata_filters: Vec<MetadataFilter>,
+    /// Maximum number of chunks to return; the service defaults to 10 and allows up to 100.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results_count: Option<u32>,
+    #[serde(skip)]
+    gemini: Gemini,
+}
+
+impl QueryCorpusRequest {
+    pub async fn send(&self) -> Result<QueryCorpusResponse, ApiRequestError> {
+        let url = format!(
+            "{}/{}/{}:query",
+            BASE_URL, self.gemini.api_version, self.corpus
+        );
+        let res = self
+            .gemini
+            .send_checked(self.gemini.client.post(&url).json(self))
+            .await?;
+        crate::read_json(res).await
+    }
+}
+
+impl Gemini {
+    pub async fn create_corpus(
+        &self,
+        display_name: impl Into<String>,
+    ) -> Result<Corpus, ApiRequestError> {
+        let url = format!("{}/{}/corpora", BASE_URL, self.api_version);
+        let body = json!({ "displayName": display_name.into() });
+        let res = self
+            .send_checked(self.client.post(&url).json(&body))
+            .await?;
+        crate::read_json(res).await
+    }
+
+    /// Lists one page of corpora; pass the returned `next_page_token` to get the next one.
+    pub async fn list_corpora(
+        &self,
+        page_token: Option<&str>,
+    ) -> Result<ListCorporaResponse, ApiRequestError> {
+        let url = format!("{}/{}/corpora", BASE_URL, self.api_version);
+        let mut request = self.client.get(&url);
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn get_corpus(&self, name: &str) -> Result<Corpus, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    /// Deletes a corpus. With `force`, its documents and chunks are deleted too; otherwise
+    /// deleting a non-empty corpus fails.
+    pub async fn delete_corpus(&self, name: &str, force: bool) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let request = self.client.delete(&url).query(&[("force", force)]);
+        self.send_checked(request).await?;
+        Ok(())
+    }
+
+    pub fn query_corpus(&self) -> QueryCorpusRequestBuilder<((), (), (), (), (Gemini,))> {
+        QueryCorpusRequest::builder().gemini(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_metadata_serialization() {
+        let filter = MetadataFilter::new("year", [Condition::new(Operator::GreaterEqual, 2020.0)]);
+        assert_eq!(
+            serde_json::to_value(&filter).unwrap(),
+            json!({"key": "year", "conditions": [{"numericValue": 2020.0, "operation": "GREATER_EQUAL"}]})
+        );
+        let metadata = CustomMetadata::new("tags", vec!["rust".to_string()]);
+        assert_eq!(
+            serde_json::to_value(&metadata).unwrap(),
+            json!({"key": "tags", "stringListValue": {"values": ["rust"]}})
+        );
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_query_corpus() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "relevantChunks": [{
+                    "chunkRelevanceScore": 0.8,
+                    "chunk": {
+                        "name": "corpora/c/documents/d/chunks/1",
+                        "data": {"stringValue": "Rust is a systems language."},
+                        "state": "STATE_ACTIVE"
+                    }
+                }]
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let response = gemini
+            .query_corpus()
+            .corpus("corpora/c")
+            .query("What is Rust?")
+            .results_count(5)
+            .build()
+            .send()
+            .await
+            .unwrap();
+
+        let chunk = &response.relevant_chunks[0].chunk;
+        assert_eq!(chunk.data.string_value, "Rust is a systems language.");
+        assert_eq!(chunk.state, Some(ChunkState::StateActive));
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url().path(), "/v1beta/corpora/c:query");
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body, json!({"query": "What is Rust?", "resultsCount": 5}));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_delete_corpus_error() {
+        let transport = MockTransport::json(
+            400,
+            &json!({"error": {"code": 400, "message": "Corpus is not empty", "status": "FAILED_PRECONDITION"}}),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let err = gemini.delete_corpus("corpora/c", false).await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            ApiRequestError::InvalidRequestError { ref status, .. }
+                if status.as_deref() == Some("FAILED_PRECONDITION")
+        ));
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].method(), reqwest::Method::DELETE);
+        assert!(requests[0].url().query().unwrap().contains("force=false"));
+    }
+}
@@ -0,0 +1,330 @@
+//! Question answering over a document: uploads it, waits until the API has processed it and
+//! answers questions against it, optionally from a context cache.
+//!
+//! ```no_run
+//! # async fn run(gemini: gemini_ox::Gemini, pdf: &[u8]) -> Result<(), gemini_ox::ApiRequestError> {
+//! let session = gemini
+//!     .document_session()
+//!     .data(pdf)
+//!     .model("gemini-1.5-flash-001")
+//!     .build()
+//!     .open()
+//!     .await?;
+//! let answer = session.ask("What is the main finding?").await?;
+//! session.close().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use typed_builder::TypedBuilder;
+
+use crate::{
+    caching::CachedContent,
+    files::File,
+    messages::message::{Content, Part, Role},
+    operations::Backoff,
+    ApiRequestError, Gemini,
+};
+
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct DocumentSessionRequest<'a> {
+    data: &'a [u8],
+    #[builder(default = "document.pdf".to_string(), setter(into))]
+    file_name: String,
+    #[builder(default = "application/pdf".to_string(), setter(into))]
+    mime_type: String,
+    #[builder(setter(into))]
+    model: String,
+    /// Caches the document for this long, so every question doesn't pay for it at the full
+    /// input rate. The document has to reach the model's minimum cache size.
+    #[builder(default, setter(strip_option))]
+    cache_ttl: Option<Duration>,
+    /// Polling schedule while the API processes the upload.
+    #[builder(default)]
+    backoff: Backoff,
+    gemini: Gemini,
+}
+
+/// Builder returned by [`Gemini::document_session`].
+pub type DocumentSessionBuilder<'a> =
+    DocumentSessionRequestBuilder<'a, ((), (), (), (), (), (), (Gemini,))>;
+
+impl Gemini {
+    pub fn document_session(&self) -> DocumentSessionBuilder<'_> {
+        DocumentSessionRequest::builder().gemini(self.clone())
+    }
+}
+
+impl DocumentSessionRequest<'_> {
+    /// Uploads the document, waits until it is `ACTIVE` and creates the cache, if requested.
+    pub async fn open(&self) -> Result<DocumentSession, ApiRequestError> {
+        let uri = self
+            .gemini
+            .upload_file()
+            .file_name(self.file_name.clone())
+            .mime_type(self.mime_type.clone())
+            .data(self.data)
+            .build()
+            .send()
+            .await?;
+        match self.prepare(&uri).await {
+            Ok(session) => Ok(session),
+            Err(e) => {
+                // Best effort; the error that stopped the session matters more.
+                let _ = self.gemini.delete_file(&uri).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Waits until the uploaded file is processed and caches it if requested.
+    async fn prepare(&self, uri: &str) -> Result<DocumentSession, ApiRequestError> {
+        let file = self.gemini.wait_for_file(uri, &self.backoff).await?;
+
+        let cached_content = match self.cache_ttl {
+            Some(ttl) => {
+                let content = Content::builder()
+                    .role(Role::User)
+                    .parts([Part::FileData(file.file_data())])
+                    .build();
+                let cache = CachedContent::new(self.model.clone(), [content]).with_ttl(ttl);
+                Some(self.gemini.create_cached_content(&cache).await?)
+            }
+            None => None,
+        };
+
+        Ok(DocumentSession {
+            gemini: self.gemini.clone(),
+            model: self.model.clone(),
+            file,
+            cached_content,
+            #[cfg(feature = "tokio")]
+            keep_alive: None,
+        })
+    }
+}
+
+/// An uploaded document questions can be asked about.
+#[derive(Debug, Clone)]
+pub struct DocumentSession {
+    gemini: Gemini,
+    model: String,
+    file: File,
+    cached_content: Option<CachedContent>,
+    /// Shared by clones, so the cache is refreshed until the last one is dropped.
+    #[cfg(feature = "tokio")]
+    keep_alive: Option<std::sync::Arc<crate::caching::CacheKeepAlive>>,
+}
+
+impl DocumentSession {
+    #[must_use]
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    #[must_use]
+    pub fn cached_content(&self) -> Option<&CachedContent> {
+        self.cached_content.as_ref()
+    }
+
+    /// Keeps the cache, if any, from expiring for as long as the session lives, extending it
+    /// by `ttl` every half `ttl`.
+    #[cfg(feature = "tokio")]
+    pub fn keep_cache_alive(&mut self, ttl: Duration) -> Result<(), ApiRequestError> {
+        if let Some(cache) = &self.cached_content {
+            self.keep_alive = Some(std::sync::Arc::new(cache.keep_alive(&self.gemini, ttl)?));
+        }
+        Ok(())
+    }
+
+    /// Answers `question` about the document. Questions are independent of each other.
+    pub async fn ask(&self, question: impl Into<String>) -> Result<String, ApiRequestError> {
+        let mut parts = Vec::with_capacity(2);
+        if self.cached_content.is_none() {
+            parts.push(Part::FileData(self.file.file_data()));
+        }
+        parts.push(Part::from(question.into()));
+
+        let response = self
+            .gemini
+            .generate_content()
+            .contents([Content::builder().role(Role::User).parts(parts).build()])
+            .cached_content(
+                self.cached_content
+                    .as_ref()
+                    .and_then(|cache| cache.name.clone()),
+            )
+            .model(self.model.clone())
+            .build()
+            .send()
+            .await?
+            .error_for_block()?;
+        Ok(response.text())
+    }
+
+    /// Deletes the cache, if any, and the uploaded file.
+    pub async fn close(self) -> Result<(), ApiRequestError> {
+        if let Some(name) = self.cached_content.and_then(|cache| cache.name) {
+            self.gemini.delete_cached_content(&name).await?;
+        }
+        self.gemini.delete_file(&self.file.name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::transport::Transport;
+
+    /// Method, path and JSON body of a recorded request.
+    type Recorded = (String, String, Option<Value>);
+
+    /// Serves the upload, file, cache and generate endpoints a session talks to.
+    #[derive(Clone, Default)]
+    struct DocumentApi {
+        requests: Arc<Mutex<Vec<Recorded>>>,
+        /// Rejects creating the cache.
+        fail_cache: bool,
+    }
+
+    #[async_trait]
+    impl Transport for DocumentApi {
+        async fn execute(
+            &self,
+            request: reqwest::Request,
+        ) -> Result<reqwest::Response, ApiRequestError> {
+            let method = request.method().to_string();
+            let path = request.url().path().to_string();
+            let body = request
+                .body()
+                .and_then(reqwest::Body::as_bytes)
+                .and_then(|body| serde_json::from_slice(body).ok());
+            let response = http::Response::builder().status(200);
+            let multipart = request.url().query() == Some("uploadType=multipart");
+            let response = match (method.as_str(), path.as_str()) {
+                ("POST", "/upload/v1beta/files") if multipart => response.body(
+                    json!({"file": {"name": "files/doc", "uri": "https://generativelanguage.googleapis.com/v1beta/files/doc"}})
+                        .to_string(),
+                ),
+                ("POST", "/upload/v1beta/files") => response
+                    .header("X-Goog-Upload-URL", "https://upload.invalid/session")
+                    .body(String::new()),
+                ("POST", "/session") => response.body(
+                    json!({"file": {"name": "files/doc", "uri": "https://generativelanguage.googleapis.com/v1beta/files/doc"}})
+                        .to_string(),
+                ),
+                ("GET", "/v1beta/files/doc") => response.body(
+                    json!({
+                        "name": "files/doc",
+                        "mimeType": "application/pdf",
+                        "sizeBytes": "1024",
+                        "uri": "https://generativelanguage.googleapis.com/v1beta/files/doc",
+                        "state": "ACTIVE"
+                    })
+                    .to_string(),
+                ),
+                ("POST", "/v1beta/cachedContents") if self.fail_cache => {
+                    response.status(400).body("{}".to_string())
+                }
+                ("POST", "/v1beta/cachedContents") => response.body(
+                    json!({"name": "cachedContents/doc-cache", "model": "models/gemini-1.5-flash-001"})
+                        .to_string(),
+                ),
+                ("POST", "/v1beta/models/gemini-1.5-flash-001:generateContent") => response.body(
+                    json!({
+                        "candidates": [{
+                            "content": {"role": "model", "parts": [{"text": "42"}]},
+                            "finishReason": "STOP",
+                            "index": 0
+                        }]
+                    })
+                    .to_string(),
+                ),
+                ("DELETE", _) => response.body("{}".to_string()),
+                _ => response.status(404).body("{}".to_string()),
+            };
+            self.requests.lock().unwrap().push((method, path, body));
+            Ok(response.unwrap().into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_document_session_with_cache() {
+        let api = DocumentApi::default();
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(api.clone())
+            .build();
+
+        let session = gemini
+            .document_session()
+            .data(b"%PDF-1.7")
+            .model("gemini-1.5-flash-001")
+            .cache_ttl(Duration::from_mins(10))
+            .build()
+            .open()
+            .await
+            .unwrap();
+        assert_eq!(session.file().size_bytes, 1024);
+        assert_eq!(session.ask("What is the answer?").await.unwrap(), "42");
+        session.close().await.unwrap();
+
+        let requests = api.requests.lock().unwrap();
+        let (_, _, cache) = &requests[2];
+        assert_eq!(
+            cache.as_ref().unwrap()["contents"][0]["parts"][0]["fileData"]["fileUri"],
+            "https://generativelanguage.googleapis.com/v1beta/files/doc"
+        );
+        assert_eq!(cache.as_ref().unwrap()["ttl"], "600s");
+
+        let (_, _, generate) = &requests[3];
+        let generate = generate.as_ref().unwrap();
+        assert_eq!(generate["cached_content"], "cachedContents/doc-cache");
+        assert_eq!(
+            generate["contents"][0]["parts"],
+            json!([{"text": "What is the answer?"}])
+        );
+
+        let deleted: Vec<_> = requests[4..].iter().map(|(_, path, _)| path).collect();
+        assert_eq!(
+            deleted,
+            ["/v1beta/cachedContents/doc-cache", "/v1beta/files/doc"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_document_session_deletes_file_on_error() {
+        let api = DocumentApi {
+            fail_cache: true,
+            ..DocumentApi::default()
+        };
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(api.clone())
+            .build();
+
+        let result = gemini
+            .document_session()
+            .data(b"%PDF-1.7")
+            .model("gemini-1.5-flash-001")
+            .cache_ttl(Duration::from_mins(10))
+            .build()
+            .open()
+            .await;
+        assert!(result.is_err());
+
+        let requests = api.requests.lock().unwrap();
+        let (method, path, _) = requests.last().unwrap();
+        assert_eq!(
+            (method.as_str(), path.as_str()),
+            ("DELETE", "/v1beta/files/doc")
+        );
+    }
+}
@@ -0,0 +1,491 @@
+//! Text embeddings via `embedContent`.
+//!
+//! Set [`TaskType`] to match how the embedding will be used: retrieval in particular expects
+//! queries embedded with [`TaskType::RetrievalQuery`] and documents with
+//! [`TaskType::RetrievalDocument`].
+
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{
+    messages::message::Content, models::ModelResource, operations::Backoff, ApiRequestError, Gemini,
+};
+
+/// Most texts a single `batchEmbedContents` call accepts.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// What an embedding is optimized for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
+pub enum TaskType {
+    /// A search query, compared against [`TaskType::RetrievalDocument`] embeddings.
+    RetrievalQuery,
+    /// A document in a search corpus; may carry a title.
+    RetrievalDocument,
+    SemanticSimilarity,
+    Classification,
+    Clustering,
+    /// A question, compared against [`TaskType::RetrievalDocument`] embeddings of answers.
+    QuestionAnswering,
+    /// A claim, compared against [`TaskType::RetrievalDocument`] embeddings of evidence.
+    FactVerification,
+    #[serde(other)]
+    TaskTypeUnspecified,
+}
+
+#[derive(Debug, Clone, Serialize, TypedBuilder)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbedContentRequest {
+    #[builder(setter(into))]
+    content: Content<'static>,
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_type: Option<TaskType>,
+    /// Title of the document; only allowed with [`TaskType::RetrievalDocument`], where it
+    /// improves retrieval quality.
+    #[builder(default, setter(into, strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// Truncates the embedding to this many dimensions. Only supported by newer models such
+    /// as `text-embedding-004`.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<u32>,
+    #[builder(default = "text-embedding-004".to_string(), setter(into))]
+    #[serde(skip)]
+    model: String,
+    #[serde(skip)]
+    gemini: Gemini,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentEmbedding {
+    #[serde(default)]
+    pub values: Vec<f32>,
+}
+
+impl ContentEmbedding {
+    /// Number of dimensions.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Dot product over the shared dimensions. Equals the cosine similarity for normalized
+    /// embeddings.
+    #[must_use]
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.values
+            .iter()
+            .zip(&other.values)
+            .map(|(a, b)| a * b)
+            .sum()
+    }
+
+    /// Euclidean length.
+    #[must_use]
+    pub fn norm(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Cosine similarity in `[-1, 1]`; `0.0` when either embedding is all zeros.
+    #[must_use]
+    pub fn cosine_similarity(&self, other: &Self) -> f32 {
+        let norms = self.norm() * other.norm();
+        if norms == 0.0 {
+            0.0
+        } else {
+            self.dot(other) / norms
+        }
+    }
+
+    /// Scales the embedding to unit length, leaving an all-zero embedding unchanged.
+    pub fn normalize(&mut self) {
+        let norm = self.norm();
+        if norm > 0.0 {
+            self.values.iter_mut().for_each(|value| *value /= norm);
+        }
+    }
+
+    #[must_use]
+    pub fn normalized(mut self) -> Self {
+        self.normalize();
+        self
+    }
+
+    /// Keeps the first `dimensions` values and renormalizes. Matryoshka-trained models such as
+    /// `text-embedding-004` front-load information, so truncated embeddings stay usable;
+    /// the result matches requesting `output_dimensionality` up to normalization.
+    #[must_use]
+    pub fn truncated(mut self, dimensions: usize) -> Self {
+        self.values.truncate(dimensions);
+        self.normalized()
+    }
+}
+
+impl From<Vec<f32>> for ContentEmbedding {
+    fn from(values: Vec<f32>) -> Self {
+        Self { values }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbedContentResponse {
+    pub embedding: ContentEmbedding,
+}
+
+/// Builder returned by [`Gemini::embed_content`].
+pub type EmbedContentBuilder = EmbedContentRequestBuilder<((), (), (), (), (), (Gemini,))>;
+
+impl Gemini {
+    pub fn embed_content(&self) -> EmbedContentBuilder {
+        EmbedContentRequest::builder().gemini(self.clone())
+    }
+}
+
+impl EmbedContentRequest {
+    pub async fn send(&self) -> Result<ContentEmbedding, ApiRequestError> {
+        #[cfg(feature = "leaky-bucket")]
+        self.gemini.acquire_rate_limit(Some(&self.model)).await;
+        let url = self.gemini.model_url(&self.model, "embedContent");
+        let (request, api_key) = self
+            .gemini
+            .authorize_with_key(self.gemini.client.post(&url))
+            .await?;
+        let res = self.gemini.execute(request.json(self)).await?;
+
+        match res.status().as_u16() {
+            200 | 201 => Ok(crate::read_json::<EmbedContentResponse>(res)
+                .await?
+                .embedding),
+            429 => {
+                self.gemini.report_rate_limit(api_key.as_deref());
+                Err(ApiRequestError::RateLimit)
+            }
+            _ => Err(ApiRequestError::from_response(res).await),
+        }
+    }
+}
+
+/// Embeds a corpus with `batchEmbedContents`, see [`Gemini::embed_many`].
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct EmbedManyRequest {
+    texts: Vec<String>,
+    /// Texts per `batchEmbedContents` call, capped at [`MAX_BATCH_SIZE`].
+    chunk_size: usize,
+    /// Calls in flight at once.
+    concurrency: usize,
+    #[builder(default, setter(strip_option))]
+    task_type: Option<TaskType>,
+    #[builder(default, setter(strip_option))]
+    output_dimensionality: Option<u32>,
+    #[builder(default = "text-embedding-004".to_string(), setter(into))]
+    model: String,
+    /// Attempts per call, including the first; only transient errors are retried.
+    #[builder(default = 3)]
+    max_attempts: u32,
+    /// Delays between attempts.
+    #[builder(default)]
+    backoff: Backoff,
+    gemini: Gemini,
+}
+
+/// Builder returned by [`Gemini::embed_many`].
+pub type EmbedManyBuilder = EmbedManyRequestBuilder<(
+    (Vec<String>,),
+    (usize,),
+    (usize,),
+    (),
+    (),
+    (),
+    (),
+    (),
+    (Gemini,),
+)>;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchEmbedContentsRequest<'a> {
+    requests: Vec<BatchEmbedEntry<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchEmbedEntry<'a> {
+    model: &'a str,
+    content: Content<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_type: Option<TaskType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct BatchEmbedContentsResponse {
+    #[serde(default)]
+    embeddings: Vec<ContentEmbedding>,
+}
+
+impl Gemini {
+    /// Embeds `texts` in batches of `chunk_size`, with up to `concurrency` batches in flight.
+    /// Every batch passes the client's rate limiters and is retried on transient errors.
+    pub fn embed_many(
+        &self,
+        texts: impl IntoIterator<Item = impl Into<String>>,
+        chunk_size: usize,
+        concurrency: usize,
+    ) -> EmbedManyBuilder {
+        EmbedManyRequest::builder()
+            .texts(texts.into_iter().map(Into::into).collect())
+            .chunk_size(chunk_size)
+            .concurrency(concurrency)
+            .gemini(self.clone())
+    }
+}
+
+impl EmbedManyRequest {
+    /// Yields `(index, embedding)` pairs as batches complete, so not in input order. A batch
+    /// that keeps failing yields a single error and the remaining batches carry on.
+    pub fn stream(
+        &self,
+    ) -> impl Stream<Item = Result<(usize, ContentEmbedding), ApiRequestError>> + '_ {
+        let chunk_size = self.chunk_size.clamp(1, MAX_BATCH_SIZE);
+        futures::stream::iter(self.texts.chunks(chunk_size).enumerate())
+            .map(move |(chunk, texts)| async move {
+                let offset = chunk * chunk_size;
+                self.send_chunk(texts).await.map(|embeddings| {
+                    embeddings
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(i, embedding)| (offset + i, embedding))
+                })
+            })
+            .buffer_unordered(self.concurrency.max(1))
+            .flat_map(|result| match result {
+                Ok(embeddings) => futures::stream::iter(embeddings.map(Ok)).left_stream(),
+                Err(e) => futures::stream::once(async { Err(e) }).right_stream(),
+            })
+    }
+
+    async fn send_chunk(&self, texts: &[String]) -> Result<Vec<ContentEmbedding>, ApiRequestError> {
+        let mut delays = self.backoff.delays();
+        let mut attempt = 1;
+        loop {
+            match self.send_batch(texts).await {
+                Err(e) if e.is_transient() && attempt < self.max_attempts => {
+                    let delay = delays.next().unwrap_or(self.backoff.max_delay);
+                    futures_timer::Delay::new(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_batch(&self, texts: &[String]) -> Result<Vec<ContentEmbedding>, ApiRequestError> {
+        #[cfg(feature = "leaky-bucket")]
+        self.gemini.acquire_rate_limit(Some(&self.model)).await;
+        let model = ModelResource::parse(&self.model).to_string();
+        let url = self.gemini.model_url(&self.model, "batchEmbedContents");
+        let body = BatchEmbedContentsRequest {
+            requests: texts
+                .iter()
+                .map(|text| BatchEmbedEntry {
+                    model: &model,
+                    content: Content::from(text),
+                    task_type: self.task_type,
+                    output_dimensionality: self.output_dimensionality,
+                })
+                .collect(),
+        };
+        let (request, api_key) = self
+            .gemini
+            .authorize_with_key(self.gemini.client.post(&url))
+            .await?;
+        let res = self.gemini.execute(request.json(&body)).await?;
+
+        match res.status().as_u16() {
+            200 | 201 => {
+                let embeddings = crate::read_json::<BatchEmbedContentsResponse>(res)
+                    .await?
+                    .embeddings;
+                if embeddings.len() == texts.len() {
+                    Ok(embeddings)
+                } else {
+                    Err(ApiRequestError::UnexpectedResponse {
+                        response: format!(
+                            "{} embeddings for {} texts",
+                            embeddings.len(),
+                            texts.len()
+                        ),
+                    })
+                }
+            }
+            429 => {
+                self.gemini.report_rate_limit(api_key.as_deref());
+                Err(ApiRequestError::RateLimit)
+            }
+            _ => Err(ApiRequestError::from_response(res).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[tokio::test]
+    async fn test_embed_many() {
+        let transport = MockTransport::json(
+            200,
+            &json!({"embeddings": [{"values": [1.0]}, {"values": [2.0]}]}),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let request = gemini
+            .embed_many(["a", "b", "c", "d"], 2, 2)
+            .task_type(TaskType::RetrievalDocument)
+            .build();
+        let mut results: Vec<_> = request
+            .stream()
+            .map(Result::unwrap)
+            .map(|(index, embedding)| (index, embedding.values[0]))
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+        assert_eq!(results, [(0, 1.0), (1, 2.0), (2, 1.0), (3, 2.0)]);
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].url().path(),
+            "/v1beta/models/text-embedding-004:batchEmbedContents"
+        );
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body["requests"][1],
+            json!({
+                "model": "models/text-embedding-004",
+                "content": {"role": "user", "parts": [{"text": "b"}]},
+                "taskType": "RETRIEVAL_DOCUMENT"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embed_many_retries_transient_errors() {
+        let transport = MockTransport::json(429, &json!({}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let request = gemini
+            .embed_many(["a", "b", "c"], 2, 1)
+            .backoff(Backoff {
+                initial_delay: Duration::from_millis(1),
+                ..Backoff::default()
+            })
+            .build();
+        let results: Vec<_> = request.stream().collect().await;
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(ApiRequestError::RateLimit))));
+        assert_eq!(transport.requests.lock().unwrap().len(), 6);
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_embedding_math() {
+        let a = ContentEmbedding::from(vec![3.0, 4.0, 0.0]);
+        let b = ContentEmbedding::from(vec![4.0, 3.0, 0.0]);
+        assert_close(a.dot(&b), 24.0);
+        assert_close(a.norm(), 5.0);
+        assert_close(a.cosine_similarity(&b), 0.96);
+        assert_close(a.cosine_similarity(&a), 1.0);
+        assert_close(
+            a.cosine_similarity(&ContentEmbedding::from(vec![0.0; 3])),
+            0.0,
+        );
+
+        let unit = a.clone().normalized();
+        assert_close(unit.norm(), 1.0);
+        assert_close(unit.dot(&b.clone().normalized()), a.cosine_similarity(&b));
+
+        let zero = ContentEmbedding::from(vec![0.0, 0.0]).normalized();
+        assert_eq!(zero.values, [0.0, 0.0]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_embedding_truncated() {
+        let embedding = ContentEmbedding::from(vec![3.0, 4.0, 12.0]).normalized();
+        let truncated = embedding.truncated(2);
+        assert_eq!(truncated.len(), 2);
+        assert_close(truncated.values[0], 0.6);
+        assert_close(truncated.values[1], 0.8);
+    }
+
+    #[tokio::test]
+    async fn test_embed_content() {
+        let transport =
+            MockTransport::json(200, &json!({"embedding": {"values": [0.25, -0.5, 1.0]}}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let embedding = gemini
+            .embed_content()
+            .content("Rust ownership rules")
+            .task_type(TaskType::RetrievalDocument)
+            .title("The Rust Book")
+            .output_dimensionality(256)
+            .build()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(embedding.values, [0.25, -0.5, 1.0]);
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].url().path(),
+            "/v1beta/models/text-embedding-004:embedContent"
+        );
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "content": {"role": "user", "parts": [{"text": "Rust ownership rules"}]},
+                "taskType": "RETRIEVAL_DOCUMENT",
+                "title": "The Rust Book",
+                "outputDimensionality": 256
+            })
+        );
+    }
+}
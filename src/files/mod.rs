@@ -1,7 +1,258 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use typed_builder::TypedBuilder;
 
-use crate::{ApiRequestError, Gemini, BASE_URL};
+#[cfg(feature = "tracing")]
+use crate::telemetry;
+use crate::{
+    messages::{
+        media::{detect_mime_type, MediaError},
+        message::{Blob, FileData, Part},
+    },
+    observer::{Operation, RequestInfo},
+    operations::{Backoff, LongRunning, Status},
+    with_timeout, ApiRequestError, Gemini, HttpDetails, BASE_URL,
+};
+
+/// A file uploaded with the Files API.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct File {
+    /// Resource name, `files/*`.
+    pub name: String,
+    pub display_name: Option<String>,
+    pub mime_type: Option<String>,
+    #[serde(default, with = "crate::int64")]
+    pub size_bytes: u64,
+    pub create_time: Option<String>,
+    pub update_time: Option<String>,
+    pub expiration_time: Option<String>,
+    pub sha256_hash: Option<String>,
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    pub state: FileState,
+    /// Why processing failed, when `state` is `FAILED`.
+    pub error: Option<Status>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListFilesResponse {
+    #[serde(default)]
+    pub files: Vec<File>,
+    /// Pass to [`Gemini::list_files`] to fetch the next page; `None` on the last page.
+    pub next_page_token: Option<String>,
+}
+
+impl File {
+    /// References the file in a prompt.
+    #[must_use]
+    pub fn file_data(&self) -> FileData {
+        FileData {
+            mime_type: self.mime_type.clone(),
+            file_uri: self.uri.clone(),
+            video_metadata: None,
+        }
+    }
+}
+
+impl LongRunning for File {
+    fn is_done(&self) -> bool {
+        self.state != FileState::Processing
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FileState {
+    #[default]
+    StateUnspecified,
+    /// Still being processed; the file can't be used in prompts yet.
+    Processing,
+    Active,
+    Failed,
+}
+
+/// Largest media [`Attachment::into_part`] sends inline. Base64 grows data by a third, so
+/// this keeps the request, with room for the rest of the prompt, under the 20 MB limit.
+pub const MAX_INLINE_BYTES: usize = 14 * 1024 * 1024;
+
+/// Media for a prompt, given as bytes or a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttachmentSource {
+    Bytes(Vec<u8>),
+    Path(PathBuf),
+}
+
+impl From<Vec<u8>> for AttachmentSource {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Bytes(data)
+    }
+}
+
+impl From<&[u8]> for AttachmentSource {
+    fn from(data: &[u8]) -> Self {
+        Self::Bytes(data.to_vec())
+    }
+}
+
+impl From<PathBuf> for AttachmentSource {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&Path> for AttachmentSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+/// Media that is sent inline when small enough and uploaded with the Files API otherwise,
+/// so callers don't have to know the request size limit.
+///
+/// ```no_run
+/// # async fn run(gemini: gemini_ox::Gemini) -> Result<(), gemini_ox::ApiRequestError> {
+/// use gemini_ox::files::Attachment;
+///
+/// let video = Attachment::auto(std::path::Path::new("talk.mp4"))
+///     .into_part(&gemini)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    source: AttachmentSource,
+    mime_type: Option<String>,
+    display_name: Option<String>,
+    max_inline_bytes: usize,
+}
+
+impl Attachment {
+    /// Inlines media up to [`MAX_INLINE_BYTES`] and uploads anything larger.
+    pub fn auto(source: impl Into<AttachmentSource>) -> Self {
+        Self {
+            source: source.into(),
+            mime_type: None,
+            display_name: None,
+            max_inline_bytes: MAX_INLINE_BYTES,
+        }
+    }
+
+    /// Sets the MIME type, which is otherwise taken from the file extension or detected
+    /// from the contents.
+    #[must_use]
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// Sets the display name of an upload, which defaults to the file name.
+    #[must_use]
+    pub fn display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.display_name = Some(display_name.into());
+        self
+    }
+
+    /// Lowers the size above which media is uploaded, e.g. to keep requests with several
+    /// attachments under the limit.
+    #[must_use]
+    pub fn max_inline_bytes(mut self, max_inline_bytes: usize) -> Self {
+        self.max_inline_bytes = max_inline_bytes.min(MAX_INLINE_BYTES);
+        self
+    }
+
+    /// Reads the media and turns it into an inline part, or uploads it and waits until the
+    /// API has processed it. Files too large for a multipart upload are streamed from disk.
+    pub async fn into_part(self, gemini: &Gemini) -> Result<Part<'static>, ApiRequestError> {
+        let (data, head, path) = match self.source {
+            AttachmentSource::Bytes(data) => {
+                let data = Bytes::from(data);
+                (UploadData::Bytes(data.clone()), data, None)
+            }
+            AttachmentSource::Path(path) => {
+                let max_bytes = self.max_inline_bytes.max(MULTIPART_UPLOAD_MAX_BYTES);
+                let file = path.clone();
+                let (data, head) = crate::unblock(move || read_attachment(file, max_bytes)).await?;
+                (data, head, Some(path))
+            }
+        };
+        let mime_type = self
+            .mime_type
+            .or_else(|| detect_mime_type(path.as_deref(), &head))
+            .ok_or(MediaError::UnknownFormat)?;
+        if let UploadData::Bytes(data) = &data {
+            if data.len() <= self.max_inline_bytes {
+                return Ok(Part::InlineData(Blob::new(&mime_type, data)));
+            }
+        }
+
+        let display_name = self.display_name.or_else(|| {
+            path.as_deref()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+        });
+        let mut upload = gemini
+            .upload_file()
+            .file_name(display_name.unwrap_or_default())
+            .mime_type(mime_type.as_str())
+            .build();
+        upload.owned = Some(data);
+        let uri = upload.send().await?;
+        let file = gemini.wait_for_file(&uri, &Backoff::default()).await?;
+        let mut file_data = file.file_data();
+        file_data.mime_type.get_or_insert(mime_type);
+        Ok(file_data.into())
+    }
+}
+
+/// Bytes read from the start of a streamed file to detect its format.
+#[cfg(not(target_arch = "wasm32"))]
+const SNIFF_BYTES: u64 = 64;
+
+/// Reads a file of up to `max_bytes`. Larger ones are left to be streamed, with only their
+/// first bytes read to detect the format. Returns the data and the bytes to sniff.
+fn read_attachment(path: PathBuf, max_bytes: usize) -> std::io::Result<(UploadData, Bytes)> {
+    let mut file = std::fs::File::open(&path)?;
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let len = file.metadata()?.len();
+        if len > max_bytes as u64 {
+            let mut head = Vec::new();
+            file.take(SNIFF_BYTES).read_to_end(&mut head)?;
+            return Ok((UploadData::File { path, len }, head.into()));
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = max_bytes;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let data = Bytes::from(data);
+    Ok((UploadData::Bytes(data.clone()), data))
+}
+
+/// Data an upload owns rather than borrows, so it is sent without being copied.
+#[derive(Debug, Clone)]
+enum UploadData {
+    Bytes(Bytes),
+    /// Streamed from disk as the request body is sent.
+    #[cfg(not(target_arch = "wasm32"))]
+    File {
+        path: PathBuf,
+        len: u64,
+    },
+}
 
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct FileUploadRequest<'a> {
@@ -11,22 +262,242 @@ pub struct FileUploadRequest<'a> {
     mime_type: String,
     #[builder(default)]
     data: &'a [u8],
+    /// Bounds the whole upload, independent of any client-level timeout.
+    #[builder(default, setter(strip_option))]
+    timeout: Option<Duration>,
+    #[builder(default)]
+    protocol: UploadProtocol,
+    /// Called as the request body is sent, e.g. to render a progress bar. Not called on
+    /// wasm32, where request bodies can't be streamed.
+    #[builder(default, setter(transform = |callback: impl Fn(UploadProgress) + Send + Sync + 'static| Some(ProgressCallback(Arc::new(callback)))))]
+    on_progress: Option<ProgressCallback>,
+    /// Replaces `data`; set by [`Attachment::into_part`].
+    #[builder(default, setter(skip))]
+    owned: Option<UploadData>,
     gemini: Gemini,
 }
 
-impl<'a> FileUploadRequest<'a> {
-    pub async fn send(&self) -> Result<String, ApiRequestError> {
-        let num_bytes = self.data.len();
+/// How much of an upload request body has been sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadProgress {
+    pub bytes_sent: u64,
+    pub total_bytes: u64,
+}
+
+/// The callback set with `FileUploadRequestBuilder::on_progress`.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(UploadProgress) + Send + Sync>);
+
+impl std::fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Size of the chunks progress is reported for.
+const PROGRESS_CHUNK_BYTES: usize = 256 * 1024;
 
-        let init_url = format!(
-            "{}/upload/{}/files?key={}",
-            BASE_URL, self.gemini.api_version, self.gemini.api_key
+/// Largest file [`UploadProtocol::Auto`] uploads in a single multipart request.
+pub const MULTIPART_UPLOAD_MAX_BYTES: usize = 5 * 1024 * 1024;
+
+/// How a file is uploaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UploadProtocol {
+    /// Multipart up to [`MULTIPART_UPLOAD_MAX_BYTES`], resumable above.
+    #[default]
+    Auto,
+    /// Metadata and data in a single request.
+    Multipart,
+    /// A request starting an upload session, then one sending the data.
+    Resumable,
+}
+
+/// Separates the metadata from the data of a multipart upload, followed by a counter that
+/// is increased until the boundary occurs in neither.
+const MULTIPART_BOUNDARY_PREFIX: &str = "gemini-ox-upload-boundary";
+
+impl FileUploadRequest<'_> {
+    pub async fn send(&self) -> Result<String, ApiRequestError> {
+        let info = RequestInfo {
+            operation: Operation::UploadFile,
+            model: None,
+        };
+        let future = self.send_untraced();
+        #[cfg(feature = "tracing")]
+        let future = telemetry::traced(
+            telemetry::upload_file_span(&self.mime_type, self.len()),
+            future,
         );
+        self.gemini.observed(info, future).await
+    }
 
-        let init_response = self
+    fn len(&self) -> usize {
+        match &self.owned {
+            None => self.data.len(),
+            Some(UploadData::Bytes(data)) => data.len(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(UploadData::File { len, .. }) => usize::try_from(*len).unwrap_or(usize::MAX),
+        }
+    }
+
+    /// The data held in memory, which is all of it unless a file is streamed.
+    fn in_memory(&self) -> &[u8] {
+        match &self.owned {
+            None => self.data,
+            Some(UploadData::Bytes(data)) => data,
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(UploadData::File { .. }) => &[],
+        }
+    }
+
+    fn is_multipart(&self) -> bool {
+        // Only the resumable protocol streams files.
+        #[cfg(not(target_arch = "wasm32"))]
+        if matches!(self.owned, Some(UploadData::File { .. })) {
+            return false;
+        }
+        match self.protocol {
+            UploadProtocol::Auto => self.len() <= MULTIPART_UPLOAD_MAX_BYTES,
+            UploadProtocol::Multipart => true,
+            UploadProtocol::Resumable => false,
+        }
+    }
+
+    fn multipart_url(&self) -> String {
+        format!(
+            "{}/upload/{}/files?uploadType=multipart",
+            BASE_URL, self.gemini.api_version
+        )
+    }
+
+    /// The boundary and a `multipart/related` body of the metadata followed by the data.
+    fn multipart_body(&self) -> (String, Vec<u8>) {
+        let metadata = json!({ "file": { "display_name": self.file_name } }).to_string();
+        let mime_type = if self.mime_type.is_empty() {
+            "application/octet-stream"
+        } else {
+            &self.mime_type
+        };
+        let contains = |haystack: &[u8], needle: &str| {
+            haystack
+                .windows(needle.len())
+                .any(|window| window == needle.as_bytes())
+        };
+        let boundary = (0..=usize::MAX)
+            .map(|n| format!("{MULTIPART_BOUNDARY_PREFIX}-{n}"))
+            .find(|boundary| {
+                !contains(self.in_memory(), boundary) && !contains(metadata.as_bytes(), boundary)
+            })
+            .expect("finite data contains finitely many boundaries");
+        let mut body = format!(
+            "--{boundary}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{metadata}\r\n--{boundary}\r\nContent-Type: {mime_type}\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(self.in_memory());
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+        (boundary, body)
+    }
+
+    /// The request body, reporting progress if a callback is set.
+    fn body(&self, body: Bytes) -> reqwest::Body {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(callback) = &self.on_progress {
+            let total_bytes = body.len() as u64;
+            return reqwest::Body::wrap_stream(with_progress(
+                chunks(body),
+                total_bytes,
+                callback.clone(),
+            ));
+        }
+        body.into()
+    }
+
+    /// The body of the resumable upload request, which sends the data alone.
+    fn resumable_body(&self) -> reqwest::Body {
+        match &self.owned {
+            None => self.body(Bytes::copy_from_slice(self.data)),
+            Some(UploadData::Bytes(data)) => self.body(data.clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(UploadData::File { path, len }) => match &self.on_progress {
+                Some(callback) => reqwest::Body::wrap_stream(with_progress(
+                    file_chunks(path.clone()),
+                    *len,
+                    callback.clone(),
+                )),
+                None => reqwest::Body::wrap_stream(file_chunks(path.clone())),
+            },
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    fn blocking_body(
+        &self,
+        data: impl Read + Send + 'static,
+        total_bytes: u64,
+    ) -> reqwest::blocking::Body {
+        match &self.on_progress {
+            Some(callback) => reqwest::blocking::Body::sized(
+                ProgressReader {
+                    data,
+                    bytes_sent: 0,
+                    total_bytes,
+                    callback: callback.clone(),
+                },
+                total_bytes,
+            ),
+            None => reqwest::blocking::Body::sized(data, total_bytes),
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    fn blocking_resumable_body(&self) -> std::io::Result<reqwest::blocking::Body> {
+        let total_bytes = self.len() as u64;
+        Ok(match &self.owned {
+            None => self.blocking_body(std::io::Cursor::new(self.data.to_vec()), total_bytes),
+            Some(UploadData::Bytes(data)) => {
+                self.blocking_body(std::io::Cursor::new(data.clone()), total_bytes)
+            }
+            Some(UploadData::File { path, .. }) => {
+                self.blocking_body(std::fs::File::open(path)?, total_bytes)
+            }
+        })
+    }
+
+    async fn send_untraced(&self) -> Result<String, ApiRequestError> {
+        #[cfg(feature = "leaky-bucket")]
+        self.gemini.acquire_rate_limit(None).await;
+        if self.is_multipart() {
+            let (request, api_key) = self
+                .gemini
+                .authorize_with_key(self.gemini.client.post(self.multipart_url()))
+                .await?;
+            let (boundary, body) = self.multipart_body();
+            let request = with_timeout(request, self.timeout)
+                .header("X-Goog-Upload-Protocol", "multipart")
+                .header(
+                    "Content-Type",
+                    format!("multipart/related; boundary={boundary}"),
+                )
+                .body(self.body(body.into()));
+            let response = self.gemini.execute(request).await?;
+            let response = self
+                .gemini
+                .check_status(response, api_key.as_deref())
+                .await?;
+            let (status, headers) = (response.status().as_u16(), response.headers().clone());
+            return file_uri(status, &headers, &response.text().await?);
+        }
+        let num_bytes = self.len();
+        #[cfg(not(target_arch = "wasm32"))]
+        let started = std::time::Instant::now();
+
+        let init_url = format!("{}/upload/{}/files", BASE_URL, self.gemini.api_version);
+
+        let (init_request, api_key) = self
             .gemini
-            .client
-            .post(&init_url)
+            .authorize_with_key(self.gemini.client.post(&init_url))
+            .await?;
+        let init_request = with_timeout(init_request, self.timeout)
             .header("X-Goog-Upload-Protocol", "resumable")
             .header("X-Goog-Upload-Command", "start")
             .header("X-Goog-Upload-Header-Content-Length", num_bytes.to_string())
@@ -35,57 +506,387 @@ impl<'a> FileUploadRequest<'a> {
                 "file": {
                     "display_name": self.file_name
                 }
-            }))
-            .send()
+            }));
+        let init_response = self.gemini.execute(init_request).await?;
+        let init_response = self
+            .gemini
+            .check_status(init_response, api_key.as_deref())
             .await?;
 
-        let upload_url = init_response
-            .headers()
-            .get("X-Goog-Upload-URL")
-            .and_then(|h| h.to_str().ok())
-            .ok_or_else(|| ApiRequestError::InvalidRequestError {
-                code: None,
-                details: json!({}),
-                message: "Missing upload URL in response".to_string(),
-                status: None,
-            })?
-            .to_string();
+        let (status, headers) = (
+            init_response.status().as_u16(),
+            init_response.headers().clone(),
+        );
+        let upload_url = upload_url(status, &headers, &init_response.text().await?)?;
 
+        // The upload only gets whatever is left of the timeout after the init request.
+        #[cfg(not(target_arch = "wasm32"))]
+        let remaining = self.timeout.map(|t| t.saturating_sub(started.elapsed()));
+        #[cfg(target_arch = "wasm32")]
+        let remaining = self.timeout;
+        let upload_request = with_timeout(self.gemini.client.post(&upload_url), remaining)
+            .header("Content-Length", num_bytes.to_string())
+            .header("X-Goog-Upload-Offset", "0")
+            .header("X-Goog-Upload-Command", "upload, finalize")
+            .body(self.resumable_body());
+        let upload_response = self.gemini.execute(upload_request).await?;
         let upload_response = self
             .gemini
-            .client
-            .post(&upload_url)
+            .check_status(upload_response, api_key.as_deref())
+            .await?;
+
+        let (status, headers) = (
+            upload_response.status().as_u16(),
+            upload_response.headers().clone(),
+        );
+        file_uri(status, &headers, &upload_response.text().await?)
+    }
+
+    /// Blocking variant of [`FileUploadRequest::send`], for callers without an async runtime.
+    #[cfg(feature = "blocking")]
+    pub fn send_blocking(&self) -> Result<String, ApiRequestError> {
+        #[cfg(feature = "leaky-bucket")]
+        self.gemini.acquire_rate_limit_blocking(None);
+        if self.is_multipart() {
+            let (mut request, api_key) = self
+                .gemini
+                .authorize_blocking(self.gemini.blocking_client().post(self.multipart_url()))?;
+            if let Some(timeout) = self.timeout {
+                request = request.timeout(timeout);
+            }
+            let (boundary, body) = self.multipart_body();
+            let total_bytes = body.len() as u64;
+            let response = request
+                .header("X-Goog-Upload-Protocol", "multipart")
+                .header(
+                    "Content-Type",
+                    format!("multipart/related; boundary={boundary}"),
+                )
+                .body(self.blocking_body(std::io::Cursor::new(body), total_bytes))
+                .send()?;
+            let response = self
+                .gemini
+                .check_status_blocking(response, api_key.as_deref())?;
+            let (status, headers) = (response.status().as_u16(), response.headers().clone());
+            return file_uri(status, &headers, &response.text()?);
+        }
+        let num_bytes = self.len();
+        let started = std::time::Instant::now();
+
+        let init_url = format!("{}/upload/{}/files", BASE_URL, self.gemini.api_version);
+
+        let (mut init_request, api_key) = self
+            .gemini
+            .authorize_blocking(self.gemini.blocking_client().post(&init_url))?;
+        if let Some(timeout) = self.timeout {
+            init_request = init_request.timeout(timeout);
+        }
+        let init_response = init_request
+            .header("X-Goog-Upload-Protocol", "resumable")
+            .header("X-Goog-Upload-Command", "start")
+            .header("X-Goog-Upload-Header-Content-Length", num_bytes.to_string())
+            .header("X-Goog-Upload-Header-Content-Type", &self.mime_type)
+            .json(&json!({
+                "file": {
+                    "display_name": self.file_name
+                }
+            }))
+            .send()?;
+        let init_response = self
+            .gemini
+            .check_status_blocking(init_response, api_key.as_deref())?;
+
+        let (status, headers) = (
+            init_response.status().as_u16(),
+            init_response.headers().clone(),
+        );
+        let upload_url = upload_url(status, &headers, &init_response.text()?)?;
+
+        let mut upload_request = self.gemini.blocking_client().post(&upload_url);
+        if let Some(timeout) = self.timeout {
+            upload_request = upload_request.timeout(timeout.saturating_sub(started.elapsed()));
+        }
+        let upload_response = upload_request
             .header("Content-Length", num_bytes.to_string())
             .header("X-Goog-Upload-Offset", "0")
             .header("X-Goog-Upload-Command", "upload, finalize")
-            .body(self.data.to_vec())
-            .send()
-            .await?;
+            .body(self.blocking_resumable_body()?)
+            .send()?;
+        let upload_response = self
+            .gemini
+            .check_status_blocking(upload_response, api_key.as_deref())?;
 
-        let file_info: serde_json::Value = upload_response.json().await?;
-        let file_uri = file_info["file"]["uri"]
-            .as_str()
-            .ok_or_else(|| ApiRequestError::InvalidRequestError {
-                code: None,
-                details: json!({}),
-                message: "Missing file URI in response".to_string(),
-                status: None,
-            })?
-            .to_string();
+        let (status, headers) = (
+            upload_response.status().as_u16(),
+            upload_response.headers().clone(),
+        );
+        file_uri(status, &headers, &upload_response.text()?)
+    }
+}
+
+/// Splits `body` into chunks of [`PROGRESS_CHUNK_BYTES`].
+#[cfg(not(target_arch = "wasm32"))]
+fn chunks(body: Bytes) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    let chunks = (0..body.len())
+        .step_by(PROGRESS_CHUNK_BYTES)
+        .map(move |start| Ok(body.slice(start..(start + PROGRESS_CHUNK_BYTES).min(body.len()))));
+    futures::stream::iter(chunks)
+}
+
+/// Reads the file at `path` in chunks of [`PROGRESS_CHUNK_BYTES`] on a thread of its own,
+/// one chunk ahead of the upload.
+#[cfg(not(target_arch = "wasm32"))]
+fn file_chunks(path: PathBuf) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    let worker = crate::BlockingWorker::new();
+    futures::stream::try_unfold(None, move |file: Option<std::fs::File>| {
+        let path = path.clone();
+        worker.run(move || {
+            let mut file = match file {
+                Some(file) => file,
+                None => std::fs::File::open(path)?,
+            };
+            let mut chunk = Vec::with_capacity(PROGRESS_CHUNK_BYTES);
+            (&mut file)
+                .take(PROGRESS_CHUNK_BYTES as u64)
+                .read_to_end(&mut chunk)?;
+            Ok((!chunk.is_empty()).then(|| (Bytes::from(chunk), Some(file))))
+        })
+    })
+}
+
+/// Reports the bytes sent as each chunk is handed to the connection.
+#[cfg(not(target_arch = "wasm32"))]
+fn with_progress(
+    chunks: impl Stream<Item = Result<Bytes, std::io::Error>>,
+    total_bytes: u64,
+    callback: ProgressCallback,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    chunks.scan(0, move |bytes_sent, chunk| {
+        if let Ok(chunk) = &chunk {
+            *bytes_sent += chunk.len() as u64;
+            (callback.0)(UploadProgress {
+                bytes_sent: *bytes_sent,
+                total_bytes,
+            });
+        }
+        futures::future::ready(Some(chunk))
+    })
+}
 
-        Ok(file_uri)
+/// Reports the bytes read by a blocking upload.
+#[cfg(feature = "blocking")]
+struct ProgressReader<R> {
+    data: R,
+    bytes_sent: u64,
+    total_bytes: u64,
+    callback: ProgressCallback,
+}
+
+#[cfg(feature = "blocking")]
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.data.read(buf)?;
+        if read > 0 {
+            self.bytes_sent += read as u64;
+            (self.callback.0)(UploadProgress {
+                bytes_sent: self.bytes_sent,
+                total_bytes: self.total_bytes,
+            });
+        }
+        Ok(read)
+    }
+}
+
+fn upload_url(
+    status: u16,
+    headers: &reqwest::header::HeaderMap,
+    body: &str,
+) -> Result<String, ApiRequestError> {
+    headers
+        .get("X-Goog-Upload-URL")
+        .and_then(|h| h.to_str().ok())
+        .map(String::from)
+        .ok_or_else(|| missing_in_response("upload URL", status, headers, body))
+}
+
+fn file_uri(
+    status: u16,
+    headers: &reqwest::header::HeaderMap,
+    body: &str,
+) -> Result<String, ApiRequestError> {
+    let file_info: serde_json::Value = crate::decode_json(status, headers, body)?;
+    file_info["file"]["uri"]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| missing_in_response("file URI", status, headers, body))
+}
+
+fn missing_in_response(
+    what: &str,
+    status: u16,
+    headers: &reqwest::header::HeaderMap,
+    body: &str,
+) -> ApiRequestError {
+    ApiRequestError::InvalidRequestError {
+        code: None,
+        details: Box::new(json!({})),
+        message: format!("Missing {what} in response"),
+        status: None,
+        http: Some(Box::new(HttpDetails::new(status, headers, body))),
     }
 }
 
+/// The `files/*` name of a file given by name or URI.
+fn file_name(file: &str) -> &str {
+    file.find("files/").map_or(file, |start| &file[start..])
+}
+
+/// Builder returned by [`Gemini::upload_file`].
+pub type UploadFileBuilder<'a> = FileUploadRequestBuilder<'a, ((), (), (), (), (), (), (Gemini,))>;
+
 impl Gemini {
-    pub fn upload_file(&self) -> FileUploadRequestBuilder<'_, ((), (), (), (Gemini,))> {
+    pub fn upload_file(&self) -> UploadFileBuilder<'_> {
         FileUploadRequest::builder().gemini(self.clone())
     }
+
+    /// Streams the contents of a file, e.g. one generated by a model or a batch job. `file` is
+    /// either the file name (`files/abc-123`) or its URI.
+    pub async fn download_file(
+        &self,
+        file: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes, ApiRequestError>>, ApiRequestError> {
+        let res = self
+            .send_checked(self.client.get(self.download_url(file)))
+            .await?;
+        Ok(res.bytes_stream().map(|chunk| chunk.map_err(Into::into)))
+    }
+
+    /// Downloads a file into `writer` chunk by chunk, returning the number of bytes written.
+    #[cfg(feature = "tokio")]
+    pub async fn download_file_to<W>(
+        &self,
+        file: &str,
+        writer: &mut W,
+    ) -> Result<u64, ApiRequestError>
+    where
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        use tokio::io::AsyncWriteExt;
+
+        let mut stream = std::pin::pin!(self.download_file(file).await?);
+        let mut written = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Downloads a file to `path`, creating or truncating it. The file is written on a
+    /// thread of its own, so this doesn't block the executor.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn download_file_to_path(
+        &self,
+        file: &str,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<u64, ApiRequestError> {
+        let mut stream = std::pin::pin!(self.download_file(file).await?);
+        let worker = crate::BlockingWorker::new();
+        let path = path.as_ref().to_path_buf();
+        let mut writer = worker
+            .run(move || std::fs::File::create(path).map(std::io::BufWriter::new))
+            .await?;
+        let mut written = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            written += chunk.len() as u64;
+            writer = worker
+                .run(move || writer.write_all(&chunk).map(|()| writer))
+                .await?;
+        }
+        worker.run(move || writer.flush()).await?;
+        Ok(written)
+    }
+
+    /// Media download URL of a file given by name or URI.
+    pub(crate) fn download_url(&self, file: &str) -> String {
+        format!(
+            "{}/download/{}/{}:download?alt=media",
+            BASE_URL,
+            self.api_version,
+            file_name(file)
+        )
+    }
+
+    /// Gets a file's metadata; `file` is either the file name or its URI.
+    pub async fn get_file(&self, file: &str) -> Result<File, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, file_name(file));
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    /// Lists one page of files; pass the returned `next_page_token` to get the next one.
+    pub async fn list_files(
+        &self,
+        page_token: Option<&str>,
+    ) -> Result<ListFilesResponse, ApiRequestError> {
+        let url = format!("{}/{}/files", BASE_URL, self.api_version);
+        let mut request = self.client.get(&url);
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    /// Lists all files, fetching the next page only once the previous one is consumed. The
+    /// stream ends after the first error.
+    pub fn list_files_stream(&self) -> impl Stream<Item = Result<File, ApiRequestError>> + '_ {
+        crate::paginate(move |page_token| async move {
+            let page = self.list_files(page_token.as_deref()).await?;
+            Ok((page.files, page.next_page_token))
+        })
+    }
+
+    pub async fn delete_file(&self, file: &str) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, file_name(file));
+        self.send_checked(self.client.delete(&url)).await?;
+        Ok(())
+    }
+
+    /// Polls a file until the API has processed it, e.g. after uploading a video or PDF.
+    /// A file whose processing failed is returned as an error.
+    pub async fn wait_for_file(
+        &self,
+        file: &str,
+        backoff: &Backoff,
+    ) -> Result<File, ApiRequestError> {
+        let file: File = self.wait_for(file_name(file), backoff).await?;
+        if file.state != FileState::Failed {
+            return Ok(file);
+        }
+        Err(file.error.map_or_else(
+            || ApiRequestError::InvalidRequestError {
+                code: None,
+                details: Box::new(json!({})),
+                message: format!("Processing of {} failed", file.name),
+                status: None,
+                http: None,
+            },
+            Into::into,
+        ))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        transport::tests::{MockTransport, PagedTransport},
+        ApiVersion, API_KEY_HEADER,
+    };
     use std::path::PathBuf;
 
     #[cfg(target_arch = "wasm32")]
@@ -110,7 +911,7 @@ mod tests {
         let api_key = get_api_key();
         let gemini = Gemini::builder()
             .api_key(api_key)
-            .api_version("v1beta")
+            .api_version(ApiVersion::V1Beta)
             .build();
 
         let file_content = include_bytes!("/home/ribelo/documents/kio/2009_1488.pdf");
@@ -138,7 +939,7 @@ mod tests {
         let api_key = get_api_key();
         let gemini = Gemini::builder()
             .api_key(api_key)
-            .api_version("v1beta")
+            .api_version(ApiVersion::V1Beta)
             .build();
 
         let file_path = std::env::var("TEST_FILE").expect("TEST_FILE env var not set");
@@ -198,6 +999,417 @@ mod tests {
         );
     }
 
+    /// Plays the resumable upload protocol and serves the uploaded file as active.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[derive(Clone, Default)]
+    struct UploadTransport {
+        requests: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_trait::async_trait]
+    impl crate::transport::Transport for UploadTransport {
+        async fn execute(
+            &self,
+            request: reqwest::Request,
+        ) -> Result<reqwest::Response, ApiRequestError> {
+            let file = json!({
+                "name": "files/abc-123",
+                "mimeType": "video/mp4",
+                "uri": "https://generativelanguage.googleapis.com/v1beta/files/abc-123",
+                "state": "ACTIVE"
+            });
+            let command = request
+                .headers()
+                .get("X-Goog-Upload-Command")
+                .map(|command| command.to_str().unwrap().to_string());
+            self.requests.lock().unwrap().push(format!(
+                "{} {}",
+                request.method(),
+                request.url().path()
+            ));
+            let multipart = request.url().query() == Some("uploadType=multipart");
+            let response = match command.as_deref() {
+                Some("start") => http::Response::builder()
+                    .header("X-Goog-Upload-URL", "https://upload.example.com/session")
+                    .body(String::new()),
+                Some(_) => http::Response::builder().body(json!({ "file": file }).to_string()),
+                None if multipart => {
+                    http::Response::builder().body(json!({ "file": file }).to_string())
+                }
+                None => http::Response::builder().body(file.to_string()),
+            };
+            Ok(response.unwrap().into())
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_attachment_inlines_small_media() {
+        let transport = UploadTransport::default();
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let part = Attachment::auto(png).into_part(&gemini).await.unwrap();
+
+        assert_eq!(part.expect_inline_data().mime_type, "image/png");
+        assert!(transport.requests.lock().unwrap().is_empty());
+        assert!(matches!(
+            Attachment::auto(b"plain".as_slice())
+                .into_part(&gemini)
+                .await,
+            Err(ApiRequestError::MediaError(MediaError::UnknownFormat))
+        ));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_attachment_uploads_large_media() {
+        let transport = UploadTransport::default();
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let path = std::env::temp_dir().join(format!("gemini-ox-{}.mp4", std::process::id()));
+        std::fs::write(&path, vec![0; 64]).unwrap();
+
+        let part = Attachment::auto(path.as_path())
+            .max_inline_bytes(32)
+            .into_part(&gemini)
+            .await
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let file_data = part.expect_file_data();
+        assert_eq!(
+            file_data.file_uri,
+            "https://generativelanguage.googleapis.com/v1beta/files/abc-123"
+        );
+        assert_eq!(file_data.mime_type.as_deref(), Some("video/mp4"));
+        assert_eq!(
+            *transport.requests.lock().unwrap(),
+            ["POST /upload/v1beta/files", "GET /v1beta/files/abc-123"]
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_attachment_streams_large_files() {
+        let transport = UploadTransport::default();
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let path = std::env::temp_dir().join(format!("gemini-ox-{}-large.mp4", std::process::id()));
+        let data: Vec<u8> = (0..=u8::MAX)
+            .cycle()
+            .take(MULTIPART_UPLOAD_MAX_BYTES + 1)
+            .collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let chunks: Vec<Bytes> = file_chunks(path.clone())
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        let part = Attachment::auto(path.as_path())
+            .max_inline_bytes(32)
+            .into_part(&gemini)
+            .await
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(chunks.concat(), data);
+        assert_eq!(
+            part.expect_file_data().mime_type.as_deref(),
+            Some("video/mp4")
+        );
+        assert_eq!(
+            *transport.requests.lock().unwrap(),
+            [
+                "POST /upload/v1beta/files",
+                "POST /session",
+                "GET /v1beta/files/abc-123"
+            ]
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_upload_reports_progress() {
+        let transport = MockTransport::json(
+            200,
+            &json!({"file": {"uri": "https://generativelanguage.googleapis.com/v1beta/files/abc-123"}}),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let data = vec![7; 2 * PROGRESS_CHUNK_BYTES + 10];
+
+        let uri = gemini
+            .upload_file()
+            .file_name("data.bin")
+            .data(&data)
+            .on_progress({
+                let reported = Arc::clone(&reported);
+                move |progress| reported.lock().unwrap().push(progress)
+            })
+            .build()
+            .send()
+            .await
+            .unwrap();
+        assert!(uri.ends_with("files/abc-123"));
+
+        // The mock doesn't read the body, so it is sent here.
+        let mut request = transport.requests.lock().unwrap().pop().unwrap();
+        let body = request.body_mut().take().unwrap();
+        let sent = reqwest::Response::from(http::Response::new(body))
+            .bytes()
+            .await
+            .unwrap();
+
+        let reported = reported.lock().unwrap();
+        let total_bytes = sent.len() as u64;
+        assert!(reported.len() > 1);
+        assert!(reported
+            .windows(2)
+            .all(|pair| pair[0].bytes_sent < pair[1].bytes_sent));
+        assert!(reported
+            .iter()
+            .all(|progress| progress.total_bytes == total_bytes));
+        assert_eq!(reported.last().unwrap().bytes_sent, total_bytes);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_progress_chunks() {
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback = {
+            let reported = Arc::clone(&reported);
+            ProgressCallback(Arc::new(move |progress: UploadProgress| {
+                reported.lock().unwrap().push(progress.bytes_sent);
+            }))
+        };
+        let body = Bytes::from(vec![7; 2 * PROGRESS_CHUNK_BYTES + 10]);
+
+        let total_bytes = body.len() as u64;
+        let chunks: Vec<Bytes> = with_progress(chunks(body.clone()), total_bytes, callback)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(chunks.concat(), body);
+        assert_eq!(
+            *reported.lock().unwrap(),
+            [
+                PROGRESS_CHUNK_BYTES as u64,
+                2 * PROGRESS_CHUNK_BYTES as u64,
+                body.len() as u64
+            ]
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_upload_picks_protocol_by_size() {
+        let transport = UploadTransport::default();
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let upload = |data: &'static [u8], protocol| {
+            gemini
+                .upload_file()
+                .file_name("notes.txt")
+                .mime_type("text/plain")
+                .data(data)
+                .protocol(protocol)
+                .build()
+        };
+
+        let small = upload(b"hello", UploadProtocol::Auto);
+        assert!(small.is_multipart());
+        let (boundary, body) = small.multipart_body();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("\"display_name\":\"notes.txt\""));
+        assert!(body.contains("Content-Type: text/plain\r\n\r\nhello\r\n"));
+        assert!(body.ends_with(&format!("--{boundary}--\r\n")));
+        // Data containing the boundary gets another one.
+        assert_eq!(boundary, "gemini-ox-upload-boundary-0");
+        let (boundary, _) =
+            upload(b"--gemini-ox-upload-boundary-0--", UploadProtocol::Auto).multipart_body();
+        assert_eq!(boundary, "gemini-ox-upload-boundary-1");
+        assert!(small.send().await.unwrap().ends_with("files/abc-123"));
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+
+        upload(b"hello", UploadProtocol::Resumable)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(transport.requests.lock().unwrap().len(), 3);
+        let large = vec![0; MULTIPART_UPLOAD_MAX_BYTES + 1];
+        assert!(!gemini.upload_file().data(&large).build().is_multipart());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_upload_surfaces_error_responses() {
+        let error = json!({
+            "error": {"code": 403, "message": "Permission denied", "status": "PERMISSION_DENIED"}
+        });
+        for protocol in [UploadProtocol::Multipart, UploadProtocol::Resumable] {
+            let gemini = Gemini::builder()
+                .api_key("test")
+                .transport(MockTransport::json(403, &error))
+                .build();
+            let err = gemini
+                .upload_file()
+                .file_name("notes.txt")
+                .mime_type("text/plain")
+                .data(b"hello")
+                .protocol(protocol)
+                .build()
+                .send()
+                .await
+                .unwrap_err();
+            match err {
+                ApiRequestError::InvalidRequestError {
+                    message,
+                    status,
+                    http: Some(http),
+                    ..
+                } => {
+                    assert_eq!(message, "Permission denied");
+                    assert_eq!(status.as_deref(), Some("PERMISSION_DENIED"));
+                    assert_eq!(http.status, 403);
+                }
+                e => panic!("unexpected error for {protocol:?}: {e:?}"),
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_upload_errors_keep_http_details() {
+        for (protocol, missing) in [
+            (UploadProtocol::Multipart, "Missing file URI in response"),
+            (UploadProtocol::Resumable, "Missing upload URL in response"),
+        ] {
+            let gemini = Gemini::builder()
+                .api_key("test")
+                .transport(MockTransport::new(200, r#"{"unexpected": true}"#))
+                .build();
+            let err = gemini
+                .upload_file()
+                .data(b"hello")
+                .protocol(protocol)
+                .build()
+                .send()
+                .await
+                .unwrap_err();
+            match err {
+                ApiRequestError::InvalidRequestError {
+                    message,
+                    http: Some(http),
+                    ..
+                } => {
+                    assert_eq!(message, missing);
+                    assert_eq!(http.status, 200);
+                    assert_eq!(http.body, r#"{"unexpected": true}"#);
+                }
+                e => panic!("unexpected error for {protocol:?}: {e:?}"),
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_list_files_stream() {
+        // Three pages, the second one empty.
+        let transport = PagedTransport::new([
+            (
+                None,
+                json!({"files": [{"name": "files/a"}, {"name": "files/b"}], "nextPageToken": "2"}),
+            ),
+            (Some("2"), json!({"nextPageToken": "3"})),
+            (Some("3"), json!({"files": [{"name": "files/c"}]})),
+        ]);
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let mut files = std::pin::pin!(gemini.list_files_stream());
+        assert_eq!(files.next().await.unwrap().unwrap().name, "files/a");
+        assert_eq!(transport.page_tokens.lock().unwrap().len(), 1);
+
+        let rest: Vec<_> = files.map(|file| file.unwrap().name).collect().await;
+        assert_eq!(rest, ["files/b", "files/c"]);
+        assert_eq!(
+            *transport.page_tokens.lock().unwrap(),
+            [None, Some("2".to_string()), Some("3".to_string())]
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_list_files_stream_stops_on_error() {
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(MockTransport::new(500, "{}"))
+            .build();
+
+        let files: Vec<_> = gemini.list_files_stream().collect().await;
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_err());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_download_file() {
+        let transport = MockTransport::new(200, "generated bytes");
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let path = std::env::temp_dir().join(format!("gemini-ox-{}-download", std::process::id()));
+        let written = gemini
+            .download_file_to_path("files/abc-123", &path)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"generated bytes");
+        assert_eq!(written, 15);
+        std::fs::remove_file(&path).unwrap();
+
+        #[cfg(feature = "tokio")]
+        {
+            let mut out = Vec::new();
+            gemini
+                .download_file_to(
+                    "https://generativelanguage.googleapis.com/v1beta/files/abc-123",
+                    &mut out,
+                )
+                .await
+                .unwrap();
+            assert_eq!(out, b"generated bytes");
+        }
+
+        let requests = transport.requests.lock().unwrap();
+        for request in requests.iter() {
+            assert_eq!(
+                request.url().path(),
+                "/download/v1beta/files/abc-123:download"
+            );
+            assert_eq!(request.url().query(), Some("alt=media"));
+            assert_eq!(request.headers()[API_KEY_HEADER], "test");
+        }
+    }
+
     #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
     async fn test_file_upload_request_builder_with_data() {
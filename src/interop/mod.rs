@@ -0,0 +1,3 @@
+//! Conversions from and to other providers' request shapes, for porting existing code.
+
+pub mod openai;
@@ -0,0 +1,585 @@
+//! OpenAI chat-completions messages and tool definitions, mapped to Gemini [`Content`] and
+//! [`ToolBox`] declarations and back.
+//!
+//! ```
+//! use gemini_ox::interop::openai::{self, ChatMessage};
+//!
+//! let messages: Vec<ChatMessage> = serde_json::from_str(
+//!     r#"[
+//!         {"role": "system", "content": "Be brief."},
+//!         {"role": "user", "content": "Hello"}
+//!     ]"#,
+//! )
+//! .unwrap();
+//! let conversation = openai::to_contents(&messages).unwrap();
+//! assert!(conversation.system_instruction.is_some());
+//! assert_eq!(conversation.contents.len(), 1);
+//! ```
+//!
+//! Gemini function calls carry no ids, so [`from_contents`] makes them up as `call_<n>`;
+//! tool results are matched back to their call by id on the way in and by order on the way
+//! out.
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    messages::{
+        message::{Blob, Content, FileData, FunctionCall, FunctionResponse, Part, Role},
+        tools::{AnyTool, ToolBox, ToolMetadataInfo},
+    },
+    schema::SchemaError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum OpenAiError {
+    #[error("Tool message answers unknown tool call: {0}")]
+    UnknownToolCallId(String),
+    #[error("Invalid arguments for {name}: {source}")]
+    InvalidArguments {
+        name: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Invalid data URL: {0}")]
+    InvalidDataUrl(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "lowercase")]
+pub enum ChatMessage {
+    System {
+        content: MessageContent,
+    },
+    /// Newer models' name for system messages; mapped the same way.
+    Developer {
+        content: MessageContent,
+    },
+    User {
+        content: MessageContent,
+    },
+    Assistant {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<MessageContent>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tool_calls: Vec<ToolCall>,
+    },
+    Tool {
+        tool_call_id: String,
+        content: MessageContent,
+    },
+}
+
+/// Either a plain string or a list of typed parts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl MessageContent {
+    /// The text parts, joined.
+    #[must_use]
+    pub fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// A `data:` URL with base64 content, or a URL the API can fetch, such as a Files API URI.
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: ToolKind,
+    pub function: FunctionCallSpec,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolKind {
+    #[default]
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCallSpec {
+    pub name: String,
+    /// The arguments as a JSON string.
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatTool {
+    #[serde(rename = "type")]
+    pub kind: ToolKind,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+/// A chat converted to Gemini's shape.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conversation {
+    /// System and developer messages, concatenated.
+    pub system_instruction: Option<Content<'static>>,
+    pub contents: Vec<Content<'static>>,
+}
+
+/// Converts OpenAI chat messages. Consecutive tool results become one user turn of function
+/// responses, which Gemini expects after a turn of function calls.
+pub fn to_contents(messages: &[ChatMessage]) -> Result<Conversation, OpenAiError> {
+    let mut conversation = Conversation::default();
+    let mut call_names = HashMap::new();
+    let mut pending_responses: Vec<Part<'static>> = Vec::new();
+
+    for message in messages {
+        if !matches!(message, ChatMessage::Tool { .. }) && !pending_responses.is_empty() {
+            conversation.contents.push(
+                Content::builder()
+                    .role(Role::User)
+                    .parts(std::mem::take(&mut pending_responses))
+                    .build(),
+            );
+        }
+        match message {
+            ChatMessage::System { content } | ChatMessage::Developer { content } => {
+                let parts = content_parts(content)?;
+                match &mut conversation.system_instruction {
+                    Some(instruction) => instruction.parts.extend(parts),
+                    None => conversation.system_instruction = Some(Content::system(parts)),
+                }
+            }
+            ChatMessage::User { content } => conversation.contents.push(
+                Content::builder()
+                    .role(Role::User)
+                    .parts(content_parts(content)?)
+                    .build(),
+            ),
+            ChatMessage::Assistant {
+                content,
+                tool_calls,
+            } => {
+                let mut parts = match content {
+                    Some(content) => content_parts(content)?,
+                    None => Vec::new(),
+                };
+                for call in tool_calls {
+                    call_names.insert(call.id.as_str(), call.function.name.as_str());
+                    parts.push(Part::FunctionCall(function_call(&call.function)?));
+                }
+                conversation
+                    .contents
+                    .push(Content::builder().role(Role::Model).parts(parts).build());
+            }
+            ChatMessage::Tool {
+                tool_call_id,
+                content,
+            } => {
+                let name = call_names
+                    .get(tool_call_id.as_str())
+                    .ok_or_else(|| OpenAiError::UnknownToolCallId(tool_call_id.clone()))?;
+                let text = content.text();
+                pending_responses.push(Part::FunctionResponse(FunctionResponse {
+                    name: (*name).to_string(),
+                    response: serde_json::from_str(&text).unwrap_or(Value::String(text)),
+                }));
+            }
+        }
+    }
+    if !pending_responses.is_empty() {
+        conversation.contents.push(
+            Content::builder()
+                .role(Role::User)
+                .parts(pending_responses)
+                .build(),
+        );
+    }
+    Ok(conversation)
+}
+
+/// Converts Gemini contents to OpenAI chat messages, e.g. to hand a response back to
+/// OpenAI-shaped code. Function responses become tool messages answering the earliest
+/// unanswered call of the same name.
+#[must_use]
+pub fn from_contents(contents: &[Content<'_>]) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+    let mut unanswered: Vec<(String, String)> = Vec::new();
+    let mut next_id = 0;
+
+    for content in contents {
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        let mut tool_messages = Vec::new();
+        for part in content.iter() {
+            match part {
+                Part::Text(t) => text.push_str(&t.0),
+                Part::FunctionCall(call) => {
+                    let id = format!("call_{next_id}");
+                    next_id += 1;
+                    unanswered.push((id.clone(), call.name.clone()));
+                    tool_calls.push(ToolCall {
+                        id,
+                        kind: ToolKind::Function,
+                        function: FunctionCallSpec {
+                            name: call.name.clone(),
+                            arguments: call
+                                .args
+                                .as_ref()
+                                .map_or_else(|| "{}".to_string(), Value::to_string),
+                        },
+                    });
+                }
+                Part::FunctionResponse(response) => {
+                    let id = unanswered
+                        .iter()
+                        .position(|(_, name)| *name == response.name)
+                        .map_or_else(|| response.name.clone(), |index| unanswered.remove(index).0);
+                    tool_messages.push(ChatMessage::Tool {
+                        tool_call_id: id,
+                        content: MessageContent::Text(match &response.response {
+                            Value::String(text) => text.clone(),
+                            value => value.to_string(),
+                        }),
+                    });
+                }
+                Part::InlineData(_) | Part::FileData(_) => {}
+            }
+        }
+
+        messages.extend(tool_messages);
+        match content.role {
+            Role::Model => {
+                if !text.is_empty() || !tool_calls.is_empty() {
+                    messages.push(ChatMessage::Assistant {
+                        content: (!text.is_empty()).then_some(MessageContent::Text(text)),
+                        tool_calls,
+                    });
+                }
+            }
+            Role::User if !text.is_empty() => messages.push(ChatMessage::User {
+                content: MessageContent::Text(text),
+            }),
+            Role::System if !text.is_empty() => messages.push(ChatMessage::System {
+                content: MessageContent::Text(text),
+            }),
+            Role::User | Role::System => {}
+        }
+    }
+    messages
+}
+
+/// Collects OpenAI tool definitions into a [`ToolBox`] of declarations. The model can call
+/// them, but invoking them through the toolbox fails; execute the calls yourself.
+///
+/// Parameter schemas are fitted to Gemini's subset with [`crate::schema::sanitize`], so this
+/// fails for schemas it can't express, such as recursive ones.
+pub fn to_toolbox(tools: &[ChatTool]) -> Result<ToolBox, SchemaError> {
+    tools
+        .iter()
+        .map(|tool| {
+            let parameters = match tool.function.parameters.clone() {
+                Some(parameters) => crate::schema::sanitize(parameters)?,
+                None => Value::Null,
+            };
+            Ok(Arc::new(ToolMetadataInfo {
+                name: tool.function.name.clone(),
+                description: tool.function.description.clone(),
+                parameters,
+            }) as Arc<dyn AnyTool>)
+        })
+        .collect()
+}
+
+/// Describes the tools in `toolbox` as OpenAI tool definitions. Fails if a tool's input
+/// schema can't be generated.
+pub fn from_toolbox(toolbox: &ToolBox) -> Result<Vec<ChatTool>, SchemaError> {
+    Ok(toolbox
+        .declarations()?
+        .into_iter()
+        .map(|declaration| ChatTool {
+            kind: ToolKind::Function,
+            function: FunctionDefinition {
+                name: declaration.name,
+                description: declaration.description,
+                parameters: (!declaration.parameters.is_null()).then_some(declaration.parameters),
+            },
+        })
+        .collect())
+}
+
+fn content_parts(content: &MessageContent) -> Result<Vec<Part<'static>>, OpenAiError> {
+    match content {
+        MessageContent::Text(text) => Ok(vec![Part::from(text.clone())]),
+        MessageContent::Parts(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text } => Ok(Part::from(text.clone())),
+                ContentPart::ImageUrl { image_url } => image_part(&image_url.url),
+            })
+            .collect(),
+    }
+}
+
+fn image_part(url: &str) -> Result<Part<'static>, OpenAiError> {
+    let Some(data_url) = url.strip_prefix("data:") else {
+        return Ok(Part::FileData(FileData {
+            mime_type: None,
+            file_uri: url.to_string(),
+            video_metadata: None,
+        }));
+    };
+    let (mime_type, data) = data_url
+        .split_once(";base64,")
+        .ok_or_else(|| OpenAiError::InvalidDataUrl(url.chars().take(64).collect()))?;
+    Ok(Part::InlineData(Blob {
+        mime_type: mime_type.to_string(),
+        data: data.to_string().into(),
+    }))
+}
+
+fn function_call(function: &FunctionCallSpec) -> Result<FunctionCall, OpenAiError> {
+    let args = if function.arguments.trim().is_empty() {
+        None
+    } else {
+        Some(serde_json::from_str(&function.arguments).map_err(|source| {
+            OpenAiError::InvalidArguments {
+                name: function.name.clone(),
+                source,
+            }
+        })?)
+    };
+    Ok(FunctionCall {
+        name: function.name.clone(),
+        args,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    fn chat() -> Vec<ChatMessage> {
+        serde_json::from_value(json!([
+            {"role": "system", "content": "You are a weather bot."},
+            {"role": "user", "content": [
+                {"type": "text", "text": "Weather here?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+            ]},
+            {"role": "assistant", "content": null, "tool_calls": [
+                {"id": "a", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Oslo\"}"}},
+                {"id": "b", "type": "function", "function": {"name": "time", "arguments": ""}}
+            ]},
+            {"role": "tool", "tool_call_id": "b", "content": "12:00"},
+            {"role": "tool", "tool_call_id": "a", "content": "{\"celsius\": 3}"},
+            {"role": "assistant", "content": "3°C in Oslo at noon."}
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_to_contents() {
+        let conversation = to_contents(&chat()).unwrap();
+        assert_eq!(
+            conversation.system_instruction,
+            Some(Content::system(["You are a weather bot."]))
+        );
+        assert_eq!(
+            serde_json::to_value(&conversation.contents).unwrap(),
+            json!([
+                {"role": "user", "parts": [
+                    {"text": "Weather here?"},
+                    {"inlineData": {"mimeType": "image/png", "data": "AAAA"}}
+                ]},
+                {"role": "model", "parts": [
+                    {"functionCall": {"name": "weather", "args": {"city": "Oslo"}}},
+                    {"functionCall": {"name": "time", "args": null}}
+                ]},
+                {"role": "user", "parts": [
+                    {"functionResponse": {"name": "time", "response": "12:00"}},
+                    {"functionResponse": {"name": "weather", "response": {"celsius": 3}}}
+                ]},
+                {"role": "model", "parts": [{"text": "3°C in Oslo at noon."}]}
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_unknown_tool_call_id() {
+        let messages = vec![ChatMessage::Tool {
+            tool_call_id: "missing".to_string(),
+            content: "{}".into(),
+        }];
+        assert!(matches!(
+            to_contents(&messages),
+            Err(OpenAiError::UnknownToolCallId(id)) if id == "missing"
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_from_contents() {
+        let conversation = to_contents(&chat()).unwrap();
+        let messages = from_contents(&conversation.contents[1..]);
+        assert_eq!(
+            serde_json::to_value(&messages).unwrap(),
+            json!([
+                {"role": "assistant", "tool_calls": [
+                    {"id": "call_0", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Oslo\"}"}},
+                    {"id": "call_1", "type": "function", "function": {"name": "time", "arguments": "{}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "12:00"},
+                {"role": "tool", "tool_call_id": "call_0", "content": "{\"celsius\":3}"},
+                {"role": "assistant", "content": "3°C in Oslo at noon."}
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_toolbox_round_trip() {
+        let tools: Vec<ChatTool> = serde_json::from_value(json!([{
+            "type": "function",
+            "function": {
+                "name": "weather",
+                "description": "Current weather",
+                "parameters": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"],
+                    "additionalProperties": false
+                }
+            }
+        }]))
+        .unwrap();
+
+        let toolbox = to_toolbox(&tools).unwrap();
+        assert_eq!(
+            serde_json::to_value(&toolbox).unwrap(),
+            json!([{"function_declarations": [{
+                "name": "weather",
+                "description": "Current weather",
+                "parameters": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            }]}])
+        );
+
+        let back = from_toolbox(&toolbox).unwrap();
+        assert_eq!(back[0].function.name, "weather");
+        assert_eq!(
+            back[0].function.parameters.as_ref().unwrap()["required"],
+            json!(["city"])
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_to_toolbox_sanitizes_parameters() {
+        let tool = |parameters| ChatTool {
+            kind: ToolKind::Function,
+            function: FunctionDefinition {
+                name: "lookup".to_string(),
+                description: None,
+                parameters: Some(parameters),
+            },
+        };
+        let tools = [tool(json!({
+            "type": "object",
+            "properties": {
+                "strict": {"type": "boolean"},
+                "address": {"$ref": "#/$defs/Address"},
+                "note": {"anyOf": [{"type": "string"}, {"type": "null"}]},
+                "unit": {"type": ["string", "null"], "const": "celsius"}
+            },
+            "required": ["address"],
+            "additionalProperties": false,
+            "$defs": {
+                "Address": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "additionalProperties": false
+                }
+            }
+        }))];
+        let declarations = to_toolbox(&tools).unwrap().declarations().unwrap();
+        assert_eq!(
+            declarations[0].parameters,
+            json!({
+                "type": "object",
+                "properties": {
+                    "strict": {"type": "boolean"},
+                    "address": {
+                        "type": "object",
+                        "properties": {"city": {"type": "string"}}
+                    },
+                    "note": {"type": "string", "nullable": true},
+                    "unit": {"type": "string", "enum": ["celsius"], "nullable": true}
+                },
+                "required": ["address"]
+            })
+        );
+
+        let recursive = tool(json!({
+            "type": "object",
+            "properties": {"child": {"$ref": "#"}}
+        }));
+        assert!(matches!(
+            to_toolbox(&[recursive]),
+            Err(SchemaError::RecursiveType(_))
+        ));
+    }
+}
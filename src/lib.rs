@@ -1,7 +1,36 @@
+pub mod auth;
+pub mod batches;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod caching;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod circuit_breaker;
+pub mod corpora;
+pub mod document_qa;
+pub mod embeddings;
 pub mod files;
+pub mod interop;
+#[cfg(all(feature = "live", not(target_arch = "wasm32")))]
+pub mod live;
 pub mod messages;
+pub mod models;
+pub mod observer;
+pub mod operations;
+pub mod pricing;
+pub mod response_cache;
+pub mod schema;
+#[cfg(feature = "schemars1")]
+pub mod schemars_compat;
+#[cfg(feature = "tracing")]
+mod telemetry;
+pub mod tokens;
+pub mod transcription;
+pub mod transport;
+pub mod usage;
+pub mod vision;
 
 use core::fmt;
+use std::{collections::HashMap, ops::Deref, sync::Arc, time::Duration};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -11,6 +40,10 @@ use typed_builder::TypedBuilder;
 
 const BASE_URL: &str = "https://generativelanguage.googleapis.com";
 
+/// Carries the API key, keeping it out of URLs and thus out of proxy logs, error messages and
+/// traces.
+const API_KEY_HEADER: &str = "x-goog-api-key";
+
 #[derive(Debug, PartialEq, PartialOrd, strum::EnumString, strum::Display)]
 pub enum Model {
     #[strum(to_string = "gemini-1.5-flash")]
@@ -33,93 +66,651 @@ pub enum Model {
     TextEmbedding004,
 }
 
+/// Version of the API requests are sent to. Some endpoints, e.g. the Live API, only exist
+/// on particular versions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum ApiVersion {
+    V1,
+    #[default]
+    V1Beta,
+    V1Alpha,
+    /// Any other version path segment, e.g. `v1beta2`, built with [`ApiVersion::from_str`].
+    Custom(CustomApiVersion),
+}
+
+/// A version path segment [`ApiVersion::from_str`] accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CustomApiVersion(String);
+
+impl CustomApiVersion {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ApiVersion {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V1Beta => "v1beta",
+            ApiVersion::V1Alpha => "v1alpha",
+            ApiVersion::Custom(version) => version.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid API version {0:?}, expected e.g. v1, v1beta or v1alpha")]
+pub struct InvalidApiVersion(pub String);
+
+impl std::str::FromStr for ApiVersion {
+    type Err = InvalidApiVersion;
+
+    /// Parses the known versions and anything shaped like one (`v<major>` optionally followed
+    /// by `alpha` or `beta` and a number), so typos don't turn into 404s.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => return Ok(ApiVersion::V1),
+            "v1beta" => return Ok(ApiVersion::V1Beta),
+            "v1alpha" => return Ok(ApiVersion::V1Alpha),
+            _ => {}
+        }
+        let valid = s.strip_prefix('v').is_some_and(|rest| {
+            let stage = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            let major = &rest[..rest.len() - stage.len()];
+            let number = stage
+                .strip_prefix("alpha")
+                .or_else(|| stage.strip_prefix("beta"))
+                .unwrap_or(stage);
+            !major.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+        });
+        if valid {
+            Ok(ApiVersion::Custom(CustomApiVersion(s.to_string())))
+        } else {
+            Err(InvalidApiVersion(s.to_string()))
+        }
+    }
+}
+
 #[cfg(feature = "leaky-bucket")]
 pub use leaky_bucket::RateLimiter;
-#[cfg(feature = "leaky-bucket")]
-use std::sync::Arc;
 
-#[derive(Clone, TypedBuilder)]
+use auth::{ApiKeyPool, TokenSource};
+use observer::Observer;
+use schema::SchemaError;
+use transport::Transport;
+
+/// Client for the Gemini API.
+///
+/// Cloning is cheap: clones share one [`GeminiInner`], so every request can carry its own
+/// handle without duplicating the HTTP client, credentials or rate limiters.
+#[derive(Clone)]
 pub struct Gemini {
-    #[builder(setter(into))]
-    pub(crate) api_key: String,
-    #[builder(default)]
+    inner: Arc<GeminiInner>,
+}
+
+/// Configuration and connection state shared by all clones of a [`Gemini`] client.
+#[derive(TypedBuilder)]
+#[builder(
+    builder_method(vis = ""),
+    builder_type(name = GeminiBuilder),
+    build_method(into = Gemini)
+)]
+pub struct GeminiInner {
+    #[builder(default, setter(into, strip_option))]
+    pub(crate) api_key: Option<String>,
+    #[builder(default, setter(into, strip_option))]
+    pub(crate) api_key_pool: Option<Arc<ApiKeyPool>>,
+    #[builder(default, setter(transform = |source: impl TokenSource + 'static| Some(Arc::new(source) as Arc<dyn TokenSource>)))]
+    pub(crate) token_source: Option<Arc<dyn TokenSource>>,
+    /// An ephemeral token minted with [`Gemini::create_auth_token`], e.g. in a browser that
+    /// must not hold the API key. Only Live sessions accept it.
+    #[builder(default, setter(into, strip_option))]
+    pub(crate) ephemeral_token: Option<String>,
+    /// Proxies used by the HTTP client built by default, e.g.
+    /// `reqwest::Proxy::all("socks5://127.0.0.1:1080")`. Ignored when `client` is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    // The async client is built from the builder's copy; only the blocking client, built on
+    // first use, reads the stored one.
+    #[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+    #[builder(default, setter(transform = |proxies: impl IntoIterator<Item = reqwest::Proxy>| proxies.into_iter().collect()))]
+    pub(crate) proxies: Vec<reqwest::Proxy>,
+    /// Extra root certificates trusted by the HTTP client built by default, e.g. for a
+    /// corporate TLS-intercepting proxy. Ignored when `client` is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    // Stored for the blocking client only, like `proxies`.
+    #[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+    #[builder(default, setter(transform = |certificates: impl IntoIterator<Item = reqwest::Certificate>| certificates.into_iter().collect()))]
+    pub(crate) root_certificates: Vec<reqwest::Certificate>,
+    /// Maximum number of idle connections kept open per host by the HTTP client built by
+    /// default. Ignored when `client` is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default, setter(strip_option))]
+    pub(crate) pool_max_idle_per_host: Option<usize>,
+    /// Interval of HTTP/2 pings that keep idle connections from being dropped by proxies and
+    /// load balancers. Ignored when `client` is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default, setter(strip_option))]
+    pub(crate) http2_keep_alive_interval: Option<Duration>,
+    /// TCP keepalive interval of the HTTP client built by default. Ignored when `client` is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default, setter(strip_option))]
+    pub(crate) tcp_keepalive: Option<Duration>,
+    /// Bounds establishing a connection, unlike request timeouts which bound the whole call.
+    /// Ignored when `client` is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default, setter(strip_option))]
+    pub(crate) connect_timeout: Option<Duration>,
+    #[cfg_attr(
+        not(target_arch = "wasm32"),
+        builder(default = build_http_client(
+            &proxies,
+            &root_certificates,
+            pool_max_idle_per_host,
+            http2_keep_alive_interval,
+            tcp_keepalive,
+            connect_timeout,
+        ))
+    )]
+    #[cfg_attr(target_arch = "wasm32", builder(default))]
     pub(crate) client: reqwest::Client,
+    /// Executes every async HTTP request; defaults to `client`.
+    #[builder(default = Arc::new(client.clone()), setter(transform = |transport: impl Transport + 'static| Arc::new(transport) as Arc<dyn Transport>))]
+    pub(crate) transport: Arc<dyn Transport>,
+    /// Notified about every call, e.g. to export metrics.
+    #[builder(default, setter(transform = |observer: impl Observer + 'static| Some(Arc::new(observer) as Arc<dyn Observer>)))]
+    pub(crate) observer: Option<Arc<dyn Observer>>,
+    /// Answers identical `generateContent` requests from a local cache.
+    #[builder(default, setter(transform = |cache: impl response_cache::ResponseCache + 'static| Some(Arc::new(cache) as Arc<dyn response_cache::ResponseCache>)))]
+    pub(crate) response_cache: Option<Arc<dyn response_cache::ResponseCache>>,
+    /// Fails requests fast while the upstream keeps failing. Applies to async requests only.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default, setter(transform = |breaker: circuit_breaker::CircuitBreaker| Some(Arc::new(breaker))))]
+    pub(crate) circuit_breaker: Option<Arc<circuit_breaker::CircuitBreaker>>,
+    /// Built on first use, as creating a blocking client inside an async runtime panics.
+    #[cfg(feature = "blocking")]
+    #[builder(default, setter(skip))]
+    pub(crate) blocking_client: std::sync::OnceLock<reqwest::blocking::Client>,
     #[cfg(feature = "leaky-bucket")]
     #[builder(default, setter(strip_option))]
     pub(crate) leaky_bucket: Option<Arc<RateLimiter>>,
-    #[builder(default = "v1beta".to_string(), setter(into))]
-    pub(crate) api_version: String,
+    /// Rate limiters for individual models, as quotas differ widely between e.g. Flash and
+    /// Pro. Keyed by model name without the `models/` prefix.
+    #[cfg(feature = "leaky-bucket")]
+    #[builder(default, setter(transform = |limiters: impl IntoIterator<Item = (impl Into<String>, RateLimiter)>| {
+        limiters.into_iter().map(|(model, limiter)| (model.into(), Arc::new(limiter))).collect()
+    }))]
+    pub(crate) model_rate_limiters: HashMap<String, Arc<RateLimiter>>,
+    /// Tokens-per-minute budget shared by all requests of this client.
+    #[cfg(feature = "leaky-bucket")]
+    #[builder(default, setter(transform = |budget: usage::TokenBudget| Some(Arc::new(budget))))]
+    pub(crate) token_budget: Option<Arc<usage::TokenBudget>>,
+    /// Gzip-compresses JSON request bodies larger than this many bytes, saving upload
+    /// bandwidth on huge prompts. Applies to async requests only.
+    #[cfg(feature = "gzip")]
+    #[builder(default, setter(strip_option))]
+    pub(crate) gzip_threshold: Option<usize>,
+    #[builder(default)]
+    pub(crate) api_version: ApiVersion,
+    /// Refuses to send `generateContent` requests without a seed in their generation
+    /// config, so recorded prompts can be reproduced, see [`GenerationConfig::deterministic`].
+    #[builder(default)]
+    pub(crate) replayable: bool,
+    /// Model metadata fetched by [`Gemini::model_info`], keyed by model name without the
+    /// `models/` prefix.
+    #[builder(default, setter(skip))]
+    pub(crate) model_infos: std::sync::Mutex<HashMap<String, models::ModelInfo>>,
+}
+
+impl Gemini {
+    pub fn builder() -> GeminiBuilder {
+        GeminiInner::builder()
+    }
+}
+
+impl From<GeminiInner> for Gemini {
+    fn from(inner: GeminiInner) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl Deref for Gemini {
+    type Target = GeminiInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// The API encodes int64 fields as JSON strings.
+pub(crate) mod int64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[allow(clippy::trivially_copy_pass_by_ref)] // signature required by `serde(with)`
+    pub(crate) fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Int64 {
+            Number(u64),
+            String(String),
+        }
+        match Int64::deserialize(deserializer)? {
+            Int64::Number(n) => Ok(n),
+            Int64::String(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// RFC 3339 timestamps the API uses for times such as `expireTime`, e.g.
+/// `2024-10-01T12:05:00.123456Z`.
+pub(crate) mod timestamp {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// Formats `time` in UTC with nanosecond precision.
+    pub(crate) fn format(time: SystemTime) -> String {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days(i64::try_from(secs / 86_400).unwrap_or_default());
+        format!(
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:09}Z",
+            secs % 86_400 / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            since_epoch.subsec_nanos()
+        )
+    }
+
+    /// Parses a timestamp with a `Z` or `±HH:MM` offset; `None` if it is malformed or before
+    /// the Unix epoch.
+    pub(crate) fn parse(timestamp: &str) -> Option<SystemTime> {
+        let (date, time) = timestamp.split_once(['T', 't'])?;
+        let mut date = date.splitn(3, '-').map(|n| n.parse::<i64>().ok());
+        let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+        let (time, offset) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+            (time, 0)
+        } else {
+            let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let secs = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            (time, if offset.starts_with('-') { -secs } else { secs })
+        };
+        let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+        let mut time = time.splitn(3, ':').map(|n| n.parse::<i64>().ok());
+        let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+        if !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 60
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let nanos = format!("{:0<9}", &fraction[..fraction.len().min(9)])
+            .parse()
+            .ok()?;
+
+        let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second
+            - offset;
+        UNIX_EPOCH.checked_add(Duration::new(u64::try_from(secs).ok()?, nanos))
+    }
+
+    /// Days since 1970-01-01 of a proleptic Gregorian date.
+    fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    fn civil_from_days(days: i64) -> (i64, i64, i64) {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400;
+        (if month <= 2 { year + 1 } else { year }, month, day)
+    }
+}
+
+/// Bounds a request by `timeout`, from connecting until the response body has been read.
+/// reqwest has no request timeouts on wasm32, where it is ignored.
+pub(crate) fn with_timeout(
+    request: reqwest::RequestBuilder,
+    timeout: Option<Duration>,
+) -> reqwest::RequestBuilder {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(timeout) = timeout {
+        return request.timeout(timeout);
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = timeout;
+    request
+}
+
+/// Replaces a JSON body larger than `threshold` bytes by its gzip-compressed form.
+#[cfg(feature = "gzip")]
+fn gzip_body(
+    mut request: reqwest::Request,
+    threshold: Option<usize>,
+) -> Result<reqwest::Request, ApiRequestError> {
+    use std::io::Write;
+
+    use reqwest::header::{HeaderValue, CONTENT_ENCODING, CONTENT_TYPE};
+
+    let is_json = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if !is_json || request.headers().contains_key(CONTENT_ENCODING) {
+        return Ok(request);
+    }
+    let Some(body) = request
+        .body()
+        .and_then(reqwest::Body::as_bytes)
+        .filter(|body| threshold.is_some_and(|threshold| body.len() > threshold))
+    else {
+        return Ok(request);
+    };
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(body)?;
+    let compressed = encoder.finish()?;
+    request
+        .headers_mut()
+        .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    *request.body_mut() = Some(compressed.into());
+    Ok(request)
+}
+
+/// Builds the default HTTP client. Like `reqwest::Client::new`, this panics only if the TLS
+/// backend cannot be initialized.
+#[cfg(not(target_arch = "wasm32"))]
+fn build_http_client(
+    proxies: &[reqwest::Proxy],
+    root_certificates: &[reqwest::Certificate],
+    pool_max_idle_per_host: Option<usize>,
+    http2_keep_alive_interval: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    connect_timeout: Option<Duration>,
+) -> reqwest::Client {
+    let mut builder = proxies
+        .iter()
+        .cloned()
+        .fold(reqwest::Client::builder(), reqwest::ClientBuilder::proxy);
+    builder = root_certificates
+        .iter()
+        .cloned()
+        .fold(builder, reqwest::ClientBuilder::add_root_certificate)
+        .tcp_keepalive(tcp_keepalive);
+    if let Some(max) = pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(interval) = http2_keep_alive_interval {
+        builder = builder
+            .http2_keep_alive_interval(interval)
+            .http2_keep_alive_while_idle(true);
+    }
+    if let Some(timeout) = connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    builder.build().expect("Failed to build HTTP client")
 }
 
 impl fmt::Debug for Gemini {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Gemini")
-            .field("api_key", &"[REDACTED]")
-            .field("client", &self.client)
-            .field("api_version", &self.api_version)
+        let mut f = f.debug_struct("Gemini");
+        f.field("api_key", &"[REDACTED]")
+            .field(
+                "token_source",
+                &self.token_source.as_ref().map(|_| "[REDACTED]"),
+            )
+            .field(
+                "ephemeral_token",
+                &self.ephemeral_token.as_ref().map(|_| "[REDACTED]"),
+            )
+            .field("client", &self.client);
+        #[cfg(not(target_arch = "wasm32"))]
+        f.field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("http2_keep_alive_interval", &self.http2_keep_alive_interval)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .field("connect_timeout", &self.connect_timeout);
+        f.field("api_version", &self.api_version)
             .finish_non_exhaustive()
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct SafetySettings(Vec<SafetySetting>);
+impl Gemini {
+    /// Attaches credentials to a request: a bearer token when a token source is configured,
+    /// an API key from the pool or the single API key otherwise. Also returns the API key
+    /// used, so a rate limit can be reported back to the key pool.
+    pub(crate) async fn authorize_with_key(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<(reqwest::RequestBuilder, Option<String>), ApiRequestError> {
+        match self.credential().await? {
+            Credential::BearerToken(token) => Ok((request.bearer_auth(token), None)),
+            Credential::ApiKey(api_key) => {
+                Ok((request.header(API_KEY_HEADER, &api_key), Some(api_key)))
+            }
+            Credential::Anonymous => Ok((request, None)),
+        }
+    }
 
-impl Default for SafetySettings {
-    fn default() -> Self {
-        Self(Vec::default())
-            // .category(
-            //     HarmCategory::HarmCategoryUnspecified,
-            //     HarmBlockThreshold::BlockNone,
-            // )
-            // .category(
-            //     HarmCategory::HarmCategoryDerogatory,
-            //     HarmBlockThreshold::BlockNone,
-            // )
-            // .category(
-            //     HarmCategory::HarmCategoryToxicity,
-            //     HarmBlockThreshold::BlockNone,
-            // )
-            // .category(
-            //     HarmCategory::HarmCategoryViolence,
-            //     HarmBlockThreshold::BlockNone,
-            // )
-            // .category(
-            //     HarmCategory::HarmCategorySexual,
-            //     HarmBlockThreshold::BlockNone,
-            // )
-            // .category(
-            //     HarmCategory::HarmCategoryMedical,
-            //     HarmBlockThreshold::BlockNone,
-            // )
-            // .category(
-            //     HarmCategory::HarmCategoryDangerous,
-            //     HarmBlockThreshold::BlockNone,
-            // )
-            .with_category(
-                HarmCategory::HarmCategoryHarassment,
-                HarmBlockThreshold::BlockNone,
-            )
-            .with_category(
-                HarmCategory::HarmCategoryHateSpeech,
-                HarmBlockThreshold::BlockNone,
-            )
-            .with_category(
-                HarmCategory::HarmCategorySexuallyExplicit,
-                HarmBlockThreshold::BlockNone,
-            )
-            .with_category(
-                HarmCategory::HarmCategoryDangerousContent,
-                HarmBlockThreshold::BlockNone,
-            )
+    /// Sends a request through the configured transport.
+    pub(crate) async fn execute(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, ApiRequestError> {
+        let request = request.build()?;
+        #[cfg(feature = "gzip")]
+        let request = gzip_body(request, self.gzip_threshold)?;
+        #[cfg(feature = "tracing")]
+        telemetry::record_request(&request);
+        #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+        let started = std::time::Instant::now();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(breaker) = &self.circuit_breaker {
+            let changed = breaker.acquire()?;
+            self.report_circuit_state(changed);
+        }
+
+        let res = self.transport.execute(request).await;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(breaker) = &self.circuit_breaker {
+            let changed = breaker.record(circuit_breaker::Outcome::of(&res));
+            self.report_circuit_state(changed);
+        }
+        let res = res?;
+
+        #[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+        telemetry::record_status(&res, Some(started.elapsed().as_millis()));
+        #[cfg(all(feature = "tracing", target_arch = "wasm32"))]
+        telemetry::record_status(&res, None);
+        Ok(res)
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn report_circuit_state(&self, changed: Option<circuit_breaker::CircuitState>) {
+        if let (Some(state), Some(observer)) = (changed, &self.observer) {
+            observer.on_circuit_state_changed(state);
+        }
+    }
+
+    /// Authorizes and sends a resource request (batches, corpora, ...), turning
+    /// non-success statuses into errors.
+    pub(crate) async fn send_checked(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, ApiRequestError> {
+        #[cfg(feature = "leaky-bucket")]
+        self.acquire_rate_limit(None).await;
+        let (request, api_key) = self.authorize_with_key(request).await?;
+        let res = self.execute(request).await?;
+        self.check_status(res, api_key.as_deref()).await
+    }
+
+    /// Turns a non-success response into an error, reporting a rate limit against `api_key`.
+    pub(crate) async fn check_status(
+        &self,
+        res: reqwest::Response,
+        api_key: Option<&str>,
+    ) -> Result<reqwest::Response, ApiRequestError> {
+        match res.status().as_u16() {
+            200..=299 => Ok(res),
+            429 => {
+                self.report_rate_limit(api_key);
+                Err(ApiRequestError::RateLimit)
+            }
+            _ => Err(ApiRequestError::from_response(res).await),
+        }
+    }
+
+    /// Picks the credential for the next request.
+    pub(crate) async fn credential(&self) -> Result<Credential, ApiRequestError> {
+        if let Some(token_source) = &self.token_source {
+            return Ok(Credential::BearerToken(token_source.token().await?));
+        }
+        let api_key = self
+            .api_key_pool
+            .as_ref()
+            .and_then(|pool| pool.next_key())
+            .or(self.api_key.as_deref())
+            .map(String::from);
+        Ok(api_key.map_or(Credential::Anonymous, Credential::ApiKey))
+    }
+
+    /// The rate limiters a request has to pass: the client-wide one and the one of `model`.
+    #[cfg(feature = "leaky-bucket")]
+    pub(crate) fn rate_limiters(&self, model: Option<&str>) -> impl Iterator<Item = &RateLimiter> {
+        let model = model.map(|model| model.strip_prefix("models/").unwrap_or(model));
+        self.leaky_bucket
+            .iter()
+            .chain(model.and_then(|model| self.model_rate_limiters.get(model)))
+            .map(AsRef::as_ref)
+    }
+
+    /// Waits until every applicable rate limiter lets a request through.
+    #[cfg(feature = "leaky-bucket")]
+    pub(crate) async fn acquire_rate_limit(&self, model: Option<&str>) {
+        for limiter in self.rate_limiters(model) {
+            limiter.acquire_one().await;
+        }
+    }
+
+    /// Marks `api_key` as rate limited in the key pool, if one is configured.
+    pub(crate) fn report_rate_limit(&self, api_key: Option<&str>) {
+        if let (Some(pool), Some(api_key)) = (&self.api_key_pool, api_key) {
+            pool.report_rate_limit(api_key);
+        }
+    }
+}
+
+pub(crate) enum Credential {
+    BearerToken(String),
+    ApiKey(String),
+    Anonymous,
 }
 
+/// Per-category blocking thresholds. The default is empty, leaving every category at the
+/// API's own default threshold.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SafetySettings(Vec<SafetySetting>);
+
 impl SafetySettings {
+    /// The categories Gemini models filter on.
+    pub const CATEGORIES: [HarmCategory; 4] = [
+        HarmCategory::HarmCategoryHarassment,
+        HarmCategory::HarmCategoryHateSpeech,
+        HarmCategory::HarmCategorySexuallyExplicit,
+        HarmCategory::HarmCategoryDangerousContent,
+    ];
+
+    /// Uses the API's default thresholds, same as [`SafetySettings::default`].
+    #[must_use]
+    pub fn default_api() -> Self {
+        Self::default()
+    }
+
+    /// Applies `threshold` to every category in [`SafetySettings::CATEGORIES`].
+    #[must_use]
+    pub fn all(threshold: HarmBlockThreshold) -> Self {
+        Self::CATEGORIES
+            .into_iter()
+            .fold(Self::default(), |settings, category| {
+                settings.with_category(category, threshold)
+            })
+    }
+
+    /// Disables blocking in every category.
+    #[must_use]
+    pub fn block_none() -> Self {
+        Self::all(HarmBlockThreshold::BlockNone)
+    }
+
+    /// Blocks content with even a low probability of harm in every category.
+    #[must_use]
+    pub fn strict() -> Self {
+        Self::all(HarmBlockThreshold::BlockLowAndAbove)
+    }
+
+    /// Sets the threshold of `category`, replacing any previous one.
     #[must_use]
     pub fn with_category(mut self, category: HarmCategory, threshold: HarmBlockThreshold) -> Self {
-        self.0.push((category, threshold).into());
+        match self.0.iter_mut().find(|s| s.category == category) {
+            Some(setting) => setting.threshold = threshold,
+            None => self.0.push((category, threshold).into()),
+        }
         self
     }
+
+    /// Removes `category`, so it falls back to the API's default threshold.
+    #[must_use]
+    pub fn without_category(mut self, category: HarmCategory) -> Self {
+        self.0.retain(|s| s.category != category);
+        self
+    }
+
+    #[must_use]
+    pub fn threshold(&self, category: HarmCategory) -> Option<HarmBlockThreshold> {
+        self.0
+            .iter()
+            .find(|s| s.category == category)
+            .map(|s| s.threshold)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -137,10 +728,10 @@ impl From<(HarmCategory, HarmBlockThreshold)> for SafetySetting {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
 pub enum HarmCategory {
-    HarmCategoryUnspecified,
     HarmCategoryDerogatory,
     HarmCategoryToxicity,
     HarmCategoryViolence,
@@ -151,45 +742,99 @@ pub enum HarmCategory {
     HarmCategoryHateSpeech,
     HarmCategorySexuallyExplicit,
     HarmCategoryDangerousContent,
+    HarmCategoryCivicIntegrity,
+    /// Also used for categories this crate doesn't know yet.
+    #[serde(other)]
+    HarmCategoryUnspecified,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
 pub enum HarmBlockThreshold {
-    HarmBlockThresholdUnspecified,
     BlockLowAndAbove,
     BlockMediumAndAbove,
     BlockOnlyHigh,
     BlockNone,
+    /// Turns the safety filter off entirely, unlike `BlockNone` which still returns ratings.
+    Off,
+    /// Also used for thresholds this crate doesn't know yet.
+    #[serde(other)]
+    HarmBlockThresholdUnspecified,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct SafetyRating {
     pub category: HarmCategory,
-    pub probability: String,
+    pub probability: HarmProbability,
+    /// Whether the content was blocked because of this rating.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub blocked: bool,
+}
+
+/// Probability that content is harmful, ordered from least to most likely.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum HarmProbability {
+    HarmProbabilityUnspecified,
+    Negligible,
+    Low,
+    Medium,
+    High,
+    /// A probability this crate doesn't know yet. Sorts above `High`, so checks like
+    /// `>= Medium` err on the side of caution.
+    #[serde(other)]
+    Unknown,
 }
 
 pub struct ResponseSchema;
 
 impl ResponseSchema {
-    #[must_use]
-    pub fn from<T: JsonSchema>() -> Value {
+    /// Generates the schema of `T`, fitted to the subset of OpenAPI 3 Gemini accepts, see
+    /// [`schema::sanitize`]. Fails with [`SchemaError::RecursiveType`] if `T` contains
+    /// itself, e.g. a tree node with child nodes.
+    pub fn from<T: JsonSchema>() -> Result<Value, SchemaError> {
+        // Subschemas are inlined by `sanitize`, which unlike schemars detects cycles.
         let settings = schemars::gen::SchemaSettings::openapi3().with(|s| {
-            s.inline_subschemas = true;
             s.meta_schema = None;
         });
         let gen = schemars::gen::SchemaGenerator::new(settings);
         let root_schema = gen.into_root_schema_for::<T>();
-        let mut json_schema = serde_json::to_value(root_schema).unwrap();
+        let mut json_schema = schema::sanitize(serde_json::to_value(root_schema)?)?;
+        add_property_ordering(&mut json_schema);
 
-        json_schema
-            .as_object_mut()
-            .unwrap()
-            .remove("title")
-            .unwrap();
+        Ok(json_schema)
+    }
+}
 
-        json_schema
+/// Adds `propertyOrdering` to every object schema so Gemini generates properties in the
+/// order the fields are declared, instead of alphabetically.
+fn add_property_ordering(schema: &mut Value) {
+    let Value::Object(map) = schema else {
+        return;
+    };
+    if let Some(Value::Object(properties)) = map.get_mut("properties") {
+        let ordering = properties
+            .keys()
+            .map(|key| Value::String(key.clone()))
+            .collect();
+        properties.values_mut().for_each(add_property_ordering);
+        map.insert("propertyOrdering".to_string(), Value::Array(ordering));
+    }
+    // Only keys holding subschemas are visited, so a property named e.g. `properties` isn't
+    // taken for a schema.
+    for (key, value) in map.iter_mut() {
+        match (key.as_str(), value) {
+            ("items", value) => add_property_ordering(value),
+            ("anyOf" | "oneOf" | "allOf", Value::Array(schemas)) => {
+                schemas.iter_mut().for_each(add_property_ordering);
+            }
+            ("$defs" | "definitions", Value::Object(schemas)) => {
+                schemas.values_mut().for_each(add_property_ordering);
+            }
+            _ => {}
+        }
     }
 }
 
@@ -257,12 +902,114 @@ pub struct GenerationConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[builder(default)]
     pub top_k: Option<i32>,
+    /// Seed used in decoding. Repeated requests with the same seed and settings tend to
+    /// produce the same output. If unset, a random seed is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub seed: Option<i32>,
+}
+
+impl GenerationConfig {
+    /// Settings for reproducible output, e.g. for regression-testing prompts: greedy
+    /// decoding (temperature 0, topK 1), a single candidate and a fixed seed.
+    #[must_use]
+    pub fn deterministic() -> Self {
+        Self {
+            candidate_count: Some(1),
+            temperature: Some(0.0),
+            top_k: Some(1),
+            seed: Some(0),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the response schema generated from `T` together with the `application/json`
+    /// MIME type the API requires for it.
+    pub fn response_schema_for<T: JsonSchema>(mut self) -> Result<Self, SchemaError> {
+        self.response_schema = Some(ResponseSchema::from::<T>()?);
+        self.response_mime_type = Some("application/json".to_string());
+        Ok(self)
+    }
+
+    /// Constrains the output to one of the variants of the unit enum `T` using the
+    /// `text/x.enum` MIME type.
+    pub fn response_enum_for<T: JsonSchema>(mut self) -> Result<Self, SchemaError> {
+        self.response_schema = Some(ResponseSchema::from::<T>()?);
+        self.response_mime_type = Some("text/x.enum".to_string());
+        Ok(self)
+    }
+
+    /// Checks the config against the limits documented by the API, so mistakes are caught
+    /// before the network round trip.
+    pub fn validate(&self) -> Result<(), GenerationConfigError> {
+        if let Some(stop_sequences) = &self.stop_sequences {
+            if stop_sequences.len() > 5 {
+                return Err(GenerationConfigError::TooManyStopSequences(
+                    stop_sequences.len(),
+                ));
+            }
+        }
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(GenerationConfigError::TemperatureOutOfRange(temperature));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err(GenerationConfigError::TopPOutOfRange(top_p));
+            }
+        }
+        if let Some(top_k) = self.top_k {
+            if top_k < 1 {
+                return Err(GenerationConfigError::TopKOutOfRange(top_k));
+            }
+        }
+        if let Some(candidate_count) = self.candidate_count {
+            if !(1..=8).contains(&candidate_count) {
+                return Err(GenerationConfigError::UnsupportedCandidateCount(
+                    candidate_count,
+                ));
+            }
+        }
+        if let Some(max_output_tokens) = self.max_output_tokens {
+            if max_output_tokens < 1 {
+                return Err(GenerationConfigError::MaxOutputTokensOutOfRange(
+                    max_output_tokens,
+                ));
+            }
+        }
+        if self.response_schema.is_some()
+            && !matches!(
+                self.response_mime_type.as_deref(),
+                Some("application/json" | "text/x.enum")
+            )
+        {
+            return Err(GenerationConfigError::SchemaWithoutMimeType(
+                self.response_mime_type.clone(),
+            ));
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Deserialize, thiserror::Error)]
-#[error("{error}")]
-struct ErrorResponse {
-    error: ApiErrorDetail,
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum GenerationConfigError {
+    #[error("At most 5 stop sequences are allowed, got {0}")]
+    TooManyStopSequences(usize),
+    #[error("Temperature must be in [0.0, 2.0], got {0}")]
+    TemperatureOutOfRange(f32),
+    #[error("topP must be in [0.0, 1.0], got {0}")]
+    TopPOutOfRange(f32),
+    #[error("topK must be positive, got {0}")]
+    TopKOutOfRange(i32),
+    #[error("candidateCount must be in [1, 8], got {0}")]
+    UnsupportedCandidateCount(i32),
+    #[error("maxOutputTokens must be positive, got {0}")]
+    MaxOutputTokensOutOfRange(i32),
+    #[error("responseSchema requires responseMimeType application/json or text/x.enum, got {0:?}")]
+    SchemaWithoutMimeType(Option<String>),
+    #[error("Replayable clients only send requests with a seed in their generation config")]
+    MissingSeed,
 }
 
 #[derive(Debug, Deserialize)]
@@ -291,21 +1038,840 @@ impl fmt::Display for ApiErrorDetail {
 pub enum ApiRequestError {
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
+    #[cfg(all(feature = "live", not(target_arch = "wasm32")))]
+    #[error(transparent)]
+    WebSocketError(Box<tokio_tungstenite::tungstenite::Error>),
     #[error(transparent)]
     SerdeError(#[from] serde_json::Error),
     #[error("Invalid request error: {message}")]
     InvalidRequestError {
         code: Option<String>,
-        details: serde_json::Value,
+        details: Box<serde_json::Value>,
         message: String,
         status: Option<String>,
+        /// The HTTP response the error was read from, if it came from the API.
+        http: Option<Box<HttpDetails>>,
+    },
+    /// An error response without the API's JSON error body, e.g. from a proxy.
+    #[error("HTTP error {http}")]
+    HttpError { http: Box<HttpDetails> },
+    /// A successful response whose body doesn't have the expected shape.
+    #[error("Failed to decode response (HTTP {}): {source}", http.status)]
+    DecodeError {
+        http: Box<HttpDetails>,
+        #[source]
+        source: serde_json::Error,
     },
     #[error("Unexpected response from API: {response}")]
     UnexpectedResponse { response: String },
+    #[error("Failed to parse structured response: {source}")]
+    ResponseParseError {
+        text: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    InvalidGenerationConfig(#[from] GenerationConfigError),
+    #[error("Failed to generate schema: {0}")]
+    SchemaGenerationFailed(#[from] SchemaError),
+    #[error(transparent)]
+    MediaError(#[from] messages::media::MediaError),
     #[error("Invalid event data: {0}")]
     InvalidEventData(String),
+    #[error("Authentication failed: {0}")]
+    AuthenticationError(String),
     #[error("Rate limit exceeded")]
     RateLimit,
+    /// The circuit breaker is open after repeated upstream failures; nothing was sent.
+    #[error("Circuit breaker open, retry in {retry_after:?}")]
+    CircuitOpen { retry_after: Duration },
+    /// The prompt or every candidate was blocked, see
+    /// [`messages::GenerateContentResponse::error_for_block`].
+    #[error("Blocked: {reason}")]
+    Blocked {
+        reason: String,
+        safety_ratings: Vec<SafetyRating>,
+    },
+    #[error("Timed out waiting for {0}")]
+    OperationTimeout(String),
+    #[error(transparent)]
+    UnsupportedByModel(#[from] models::CapabilityError),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 }
+
+#[cfg(all(feature = "live", not(target_arch = "wasm32")))]
+impl From<tokio_tungstenite::tungstenite::Error> for ApiRequestError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        ApiRequestError::WebSocketError(Box::new(e))
+    }
+}
+
+impl ApiRequestError {
+    /// Builds an `InvalidRequestError` from the JSON error body returned by the API, or an
+    /// `HttpError` if the body isn't one.
+    pub(crate) fn from_error_body(http: HttpDetails, body: &str) -> Self {
+        let mut e = match serde_json::from_str::<Value>(body) {
+            Ok(e) if e["error"].is_object() => e,
+            _ => {
+                return ApiRequestError::HttpError {
+                    http: Box::new(http),
+                }
+            }
+        };
+        ApiRequestError::InvalidRequestError {
+            code: e["error"]["code"].as_str().map(String::from),
+            details: Box::new(e["error"]["details"].take()),
+            message: e["error"]["message"]
+                .as_str()
+                .map_or_else(|| "no message".to_string(), String::from),
+            status: e["error"]["status"].as_str().map(String::from),
+            http: Some(Box::new(http)),
+        }
+    }
+
+    /// Reads the body of an error response, see [`ApiRequestError::from_error_body`].
+    pub(crate) async fn from_response(res: reqwest::Response) -> Self {
+        let status = res.status().as_u16();
+        let headers = res.headers().clone();
+        match res.text().await {
+            Ok(body) => Self::from_error_body(HttpDetails::new(status, &headers, &body), &body),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Whether retrying the same request may succeed: rate limits, server-side failures and
+    /// timeouts or connection errors.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiRequestError::RateLimit => true,
+            #[cfg(not(target_arch = "wasm32"))]
+            ApiRequestError::ReqwestError(e) => e.is_timeout() || e.is_connect(),
+            #[cfg(target_arch = "wasm32")]
+            ApiRequestError::ReqwestError(e) => e.is_timeout(),
+            ApiRequestError::InvalidRequestError { status, .. } => matches!(
+                status.as_deref(),
+                Some("UNAVAILABLE" | "INTERNAL" | "RESOURCE_EXHAUSTED" | "DEADLINE_EXCEEDED")
+            ),
+            ApiRequestError::HttpError { http } => matches!(http.status, 408 | 429 | 500..=599),
+            _ => false,
+        }
+    }
+}
+
+/// Status, triage-relevant headers and the start of the body of an HTTP response, kept in
+/// errors so failures can be diagnosed from logs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpDetails {
+    pub status: u16,
+    /// Headers such as `content-type`, `retry-after` and Google's request ids.
+    pub headers: Vec<(String, String)>,
+    /// The body, cut after [`HttpDetails::MAX_BODY_BYTES`].
+    pub body: String,
+}
+
+impl HttpDetails {
+    pub const MAX_BODY_BYTES: usize = 4096;
+
+    pub(crate) fn new(status: u16, headers: &reqwest::header::HeaderMap, body: &str) -> Self {
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                matches!(name, "content-type" | "retry-after" | "x-request-id")
+                    || name.starts_with("x-goog-")
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let mut end = body.len().min(Self::MAX_BODY_BYTES);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        Self {
+            status,
+            headers,
+            body: body[..end].to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HttpDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.body)
+    }
+}
+
+/// Parses the JSON body of a successful response, keeping the status, headers and body in
+/// the error if it doesn't match `T`.
+pub(crate) async fn read_json<T: serde::de::DeserializeOwned>(
+    res: reqwest::Response,
+) -> Result<T, ApiRequestError> {
+    let status = res.status().as_u16();
+    let headers = res.headers().clone();
+    let body = res.text().await?;
+    decode_json(status, &headers, &body)
+}
+
+/// Streams the items of a paginated list, calling `fetch_page` with each page token (`None`
+/// for the first page) only once the previous page is consumed. Ends after the first error.
+pub(crate) fn paginate<'a, T, F, Fut>(
+    mut fetch_page: F,
+) -> impl futures::Stream<Item = Result<T, ApiRequestError>> + 'a
+where
+    T: 'a,
+    F: FnMut(Option<String>) -> Fut + 'a,
+    Fut: std::future::Future<Output = Result<(Vec<T>, Option<String>), ApiRequestError>> + 'a,
+{
+    use futures::TryStreamExt;
+
+    futures::stream::try_unfold(Some(None), move |page_token| {
+        let page = page_token.map(&mut fetch_page);
+        async move {
+            let Some(page) = page else {
+                return Ok(None);
+            };
+            let (items, next_page_token): (Vec<T>, _) = page.await?;
+            let next = next_page_token.filter(|token| !token.is_empty()).map(Some);
+            Ok::<_, ApiRequestError>(Some((
+                futures::stream::iter(items.into_iter().map(Ok)),
+                next,
+            )))
+        }
+    })
+    .try_flatten()
+}
+
+/// Runs blocking file IO on a thread of its own, so it doesn't stall the executor, whichever
+/// runtime that is.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn unblock<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.await.expect("blocking IO panicked")
+}
+
+/// A thread that runs blocking IO jobs one after another, for sequential work such as
+/// reading or writing a file chunk by chunk without a thread per chunk. The thread exits
+/// once the worker is dropped and its queued jobs are done.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct BlockingWorker {
+    jobs: std::sync::mpsc::Sender<Box<dyn FnOnce() + Send>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BlockingWorker {
+    pub(crate) fn new() -> Self {
+        let (jobs, rx) = std::sync::mpsc::channel::<Box<dyn FnOnce() + Send>>();
+        std::thread::spawn(move || {
+            for job in rx {
+                job();
+            }
+        });
+        Self { jobs }
+    }
+
+    /// Queues `f` and returns its result. The future doesn't borrow the worker.
+    pub(crate) fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce() -> T + Send + 'static,
+    ) -> impl std::future::Future<Output = T> + Send + 'static {
+        let (tx, rx) = futures::channel::oneshot::channel();
+        // If the thread is gone, the job and with it `tx` are dropped, failing `rx`.
+        let _ = self.jobs.send(Box::new(move || {
+            let _ = tx.send(f());
+        }));
+        async move { rx.await.expect("blocking IO panicked") }
+    }
+}
+
+/// There are no threads on wasm32, and no files to block on either.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn unblock<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+pub(crate) fn decode_json<T: serde::de::DeserializeOwned>(
+    status: u16,
+    headers: &reqwest::header::HeaderMap,
+    body: &str,
+) -> Result<T, ApiRequestError> {
+    serde_json::from_str(body).map_err(|source| ApiRequestError::DecodeError {
+        http: Box::new(HttpDetails::new(status, headers, body)),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_api_version() {
+        assert_eq!("v1".parse(), Ok(ApiVersion::V1));
+        assert_eq!("v1alpha".parse(), Ok(ApiVersion::V1Alpha));
+        assert_eq!("v1beta3".parse::<ApiVersion>().unwrap().as_str(), "v1beta3");
+        assert_eq!(
+            "v1bta".parse::<ApiVersion>(),
+            Err(InvalidApiVersion("v1bta".to_string()))
+        );
+        assert!("1beta".parse::<ApiVersion>().is_err());
+        assert!("v".parse::<ApiVersion>().is_err());
+        assert_eq!(ApiVersion::default().to_string(), "v1beta");
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_requests_go_through_proxy() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_url = format!("http://{}", listener.local_addr().unwrap());
+        let proxy = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .proxies([reqwest::Proxy::all(proxy_url).unwrap()])
+            .build();
+        let res = gemini
+            .client
+            .get("http://generativelanguage.invalid/")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), 200);
+        assert!(proxy
+            .join()
+            .unwrap()
+            .starts_with("GET http://generativelanguage.invalid/ HTTP/1.1"));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_http_tuning_options() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .pool_max_idle_per_host(4)
+            .http2_keep_alive_interval(Duration::from_secs(30))
+            .tcp_keepalive(Duration::from_mins(1))
+            .connect_timeout(Duration::from_secs(5))
+            .build();
+        assert_eq!(gemini.pool_max_idle_per_host, Some(4));
+        assert_eq!(gemini.connect_timeout, Some(Duration::from_secs(5)));
+
+        let res = gemini.client.get(url).send().await.unwrap();
+        assert_eq!(res.status(), 200);
+        server.join().unwrap();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_with_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let client = reqwest::Client::new();
+        let err = with_timeout(client.get(url), Some(Duration::from_millis(50)))
+            .send()
+            .await
+            .unwrap_err();
+
+        assert!(err.is_timeout());
+        drop(listener);
+    }
+
+    #[cfg(all(feature = "gzip", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_gzip_large_request_bodies() {
+        use std::io::Read;
+
+        let transport =
+            transport::tests::MockTransport::json(200, &serde_json::json!({"candidates": []}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .gzip_threshold(100)
+            .build();
+
+        for prompt in ["short", &"long ".repeat(100)] {
+            gemini
+                .generate_content()
+                .contents([prompt.to_string()])
+                .model("gemini-1.5-flash")
+                .build()
+                .send()
+                .await
+                .unwrap();
+        }
+
+        let requests = transport.requests.lock().unwrap();
+        assert!(!requests[0].headers().contains_key("content-encoding"));
+        assert_eq!(requests[1].headers()["content-encoding"], "gzip");
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(requests[1].body().unwrap().as_bytes().unwrap())
+            .read_to_string(&mut json)
+            .unwrap();
+        let body: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "long ".repeat(100));
+    }
+
+    #[cfg(all(feature = "leaky-bucket", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_model_rate_limiters() {
+        let limiter = |initial| {
+            RateLimiter::builder()
+                .initial(initial)
+                .max(initial)
+                .interval(Duration::from_hours(1))
+                .build()
+        };
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport::tests::MockTransport::json(
+                200,
+                &serde_json::json!({"candidates": []}),
+            ))
+            .model_rate_limiters([
+                ("gemini-1.5-pro", limiter(2)),
+                ("gemini-1.5-flash", limiter(2)),
+            ])
+            .build();
+
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-pro")
+            .build();
+        request.send().await.unwrap();
+
+        assert_eq!(gemini.model_rate_limiters["gemini-1.5-pro"].balance(), 1);
+        assert_eq!(gemini.model_rate_limiters["gemini-1.5-flash"].balance(), 2);
+    }
+
+    #[cfg(all(feature = "leaky-bucket", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_rate_limiter_applies_to_every_path() {
+        use futures::StreamExt;
+
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport::tests::MockTransport::json(
+                200,
+                &serde_json::json!({"candidates": []}),
+            ))
+            .leaky_bucket(Arc::new(
+                RateLimiter::builder()
+                    .initial(10)
+                    .max(10)
+                    .interval(Duration::from_hours(1))
+                    .build(),
+            ))
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-flash")
+            .build();
+
+        request.send().await.unwrap();
+        let _ = request.stream().await.collect::<Vec<_>>().await;
+        let _ = gemini.upload_file().data(b"data").build().send().await;
+        let _ = gemini.get_batch("batches/123").await;
+
+        assert_eq!(gemini.leaky_bucket.as_ref().unwrap().balance(), 6);
+    }
+
+    #[cfg(all(feature = "leaky-bucket", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_token_budget_settles_actual_usage() {
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport::tests::MockTransport::json(
+                200,
+                &serde_json::json!({
+                    "candidates": [],
+                    "usageMetadata": {"promptTokenCount": 3, "totalTokenCount": 10}
+                }),
+            ))
+            .token_budget(usage::TokenBudget::per_minute(1000))
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["Hello, world"])
+            .generation_config(
+                GenerationConfig::builder()
+                    .max_output_tokens(Some(97))
+                    .build(),
+            )
+            .model("gemini-1.5-flash")
+            .build();
+
+        request.send().await.unwrap();
+        let budget = gemini.token_budget.as_ref().unwrap();
+        assert_eq!(budget.available(), 900);
+
+        // The 90 tokens overestimated by the first request are credited to the second one.
+        request.send().await.unwrap();
+        assert_eq!(budget.available(), 890);
+    }
+
+    #[cfg(all(feature = "leaky-bucket", not(target_arch = "wasm32")))]
+    #[tokio::test]
+    async fn test_token_budget_refunds_unsettled_reservations() {
+        let failing = Gemini::builder()
+            .api_key("test")
+            .transport(transport::tests::MockTransport::json(
+                429,
+                &serde_json::json!({}),
+            ))
+            .token_budget(usage::TokenBudget::per_minute(1000))
+            .build();
+        let request = failing
+            .generate_content()
+            .contents(vec!["Hello, world"])
+            .model("gemini-1.5-flash")
+            .build();
+        let budget = failing.token_budget.as_ref().unwrap();
+
+        assert!(request.send().await.is_err());
+        assert_eq!(budget.available(), 997);
+        // The failed request's 3 tokens are credited to the next one.
+        assert!(request.send().await.is_err());
+        assert_eq!(budget.available(), 997);
+
+        // A stream without usage is refunded once it ends.
+        let streaming = Gemini::builder()
+            .api_key("test")
+            .transport(transport::tests::MockTransport::new(
+                200,
+                "data: {\"candidates\": []}\n\n",
+            ))
+            .token_budget(usage::TokenBudget::per_minute(1000))
+            .build();
+        let request = streaming
+            .generate_content()
+            .contents(vec!["Hello, world"])
+            .model("gemini-1.5-flash")
+            .build();
+        let budget = streaming.token_budget.as_ref().unwrap();
+        for _ in 0..2 {
+            let chunks: Vec<_> = futures::StreamExt::collect(request.stream().await).await;
+            assert_eq!(chunks.len(), 1);
+            assert_eq!(budget.available(), 997);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_safety_settings_presets() {
+        assert!(SafetySettings::default_api().is_empty());
+        assert_eq!(
+            serde_json::to_value(SafetySettings::default()).unwrap(),
+            serde_json::json!([])
+        );
+
+        let strict = SafetySettings::strict();
+        for category in SafetySettings::CATEGORIES {
+            assert_eq!(
+                strict.threshold(category),
+                Some(HarmBlockThreshold::BlockLowAndAbove)
+            );
+        }
+
+        let settings = SafetySettings::block_none()
+            .with_category(
+                HarmCategory::HarmCategoryHarassment,
+                HarmBlockThreshold::BlockOnlyHigh,
+            )
+            .without_category(HarmCategory::HarmCategoryHateSpeech);
+        assert_eq!(
+            serde_json::to_value(&settings).unwrap(),
+            serde_json::json!([
+                {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_ONLY_HIGH"},
+                {"category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "BLOCK_NONE"},
+                {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE"}
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_harm_enums_wire_format() {
+        let setting = SafetySetting::from((
+            HarmCategory::HarmCategoryCivicIntegrity,
+            HarmBlockThreshold::Off,
+        ));
+        assert_eq!(
+            serde_json::to_value(&setting).unwrap(),
+            serde_json::json!({"category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF"})
+        );
+
+        let unknown: SafetySetting = serde_json::from_value(
+            serde_json::json!({"category": "HARM_CATEGORY_FUTURE", "threshold": "BLOCK_FUTURE"}),
+        )
+        .unwrap();
+        assert_eq!(unknown.category, HarmCategory::HarmCategoryUnspecified);
+        assert_eq!(
+            unknown.threshold,
+            HarmBlockThreshold::HarmBlockThresholdUnspecified
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_safety_rating() {
+        let ratings: Vec<SafetyRating> = serde_json::from_value(serde_json::json!([
+            {"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"},
+            {"category": "HARM_CATEGORY_HATE_SPEECH", "probability": "HIGH", "blocked": true}
+        ]))
+        .unwrap();
+
+        assert_eq!(ratings[0].probability, HarmProbability::Negligible);
+        assert!(!ratings[0].blocked);
+        assert!(ratings[1].blocked);
+        let risky: Vec<_> = ratings
+            .iter()
+            .filter(|r| r.probability >= HarmProbability::Medium)
+            .map(|r| r.category)
+            .collect();
+        assert_eq!(risky, [HarmCategory::HarmCategoryHateSpeech]);
+        let unknown: SafetyRating = serde_json::from_value(serde_json::json!(
+            {"category": "HARM_CATEGORY_HARASSMENT", "probability": "VERY_HIGH"}
+        ))
+        .unwrap();
+        assert_eq!(unknown.probability, HarmProbability::Unknown);
+        assert!(unknown.probability > HarmProbability::High);
+        assert_eq!(
+            serde_json::to_value(&ratings[0]).unwrap(),
+            serde_json::json!({"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"})
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_response_schema_for_sets_mime_type() {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Answer {
+            value: i32,
+        }
+
+        let config = GenerationConfig::builder()
+            .temperature(Some(0.0))
+            .build()
+            .response_schema_for::<Answer>()
+            .unwrap();
+
+        assert_eq!(
+            config.response_mime_type.as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(
+            config.response_schema,
+            Some(ResponseSchema::from::<Answer>().unwrap())
+        );
+        assert_eq!(config.temperature, Some(0.0));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_replayable_requires_seed() {
+        let transport = transport::tests::MockTransport::json(200, &json!({"candidates": []}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .replayable(true)
+            .build();
+
+        let err = gemini
+            .generate_content()
+            .contents("hello")
+            .model("gemini-1.5-flash")
+            .build()
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ApiRequestError::InvalidGenerationConfig(GenerationConfigError::MissingSeed)
+        ));
+        assert!(transport.requests.lock().unwrap().is_empty());
+
+        gemini
+            .generate_content()
+            .contents("hello")
+            .model("gemini-1.5-flash")
+            .generation_config(GenerationConfig::deterministic())
+            .build()
+            .send()
+            .await
+            .unwrap();
+        let requests = transport.requests.lock().unwrap();
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body["generation_config"],
+            json!({"candidateCount": 1, "temperature": 0.0, "topK": 1, "seed": 0})
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_generation_config_validate() {
+        assert_eq!(GenerationConfig::default().validate(), Ok(()));
+        assert_eq!(
+            GenerationConfig::builder()
+                .temperature(Some(2.5))
+                .build()
+                .validate(),
+            Err(GenerationConfigError::TemperatureOutOfRange(2.5))
+        );
+        assert_eq!(
+            GenerationConfig::builder()
+                .top_p(Some(-0.1))
+                .build()
+                .validate(),
+            Err(GenerationConfigError::TopPOutOfRange(-0.1))
+        );
+        assert_eq!(
+            GenerationConfig::builder()
+                .stop_sequences(["a", "b", "c", "d", "e", "f"])
+                .build()
+                .validate(),
+            Err(GenerationConfigError::TooManyStopSequences(6))
+        );
+        assert_eq!(
+            GenerationConfig::builder()
+                .candidate_count(Some(0))
+                .build()
+                .validate(),
+            Err(GenerationConfigError::UnsupportedCandidateCount(0))
+        );
+        assert_eq!(
+            GenerationConfig::builder()
+                .response_schema(serde_json::json!({"type": "string"}))
+                .build()
+                .validate(),
+            Err(GenerationConfigError::SchemaWithoutMimeType(None))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_response_enum_for() {
+        #[derive(JsonSchema)]
+        #[serde(rename_all = "lowercase")]
+        #[allow(dead_code)]
+        enum Sentiment {
+            Positive,
+            Neutral,
+            Negative,
+        }
+
+        let config = GenerationConfig::default()
+            .response_enum_for::<Sentiment>()
+            .unwrap();
+
+        assert_eq!(config.response_mime_type.as_deref(), Some("text/x.enum"));
+        assert_eq!(
+            config.response_schema,
+            Some(serde_json::json!({
+                "type": "string",
+                "enum": ["positive", "neutral", "negative"]
+            }))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_response_schema_property_ordering() {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Author {
+            name: String,
+            born: i32,
+        }
+
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Book {
+            title: String,
+            author: Author,
+            isbn: String,
+        }
+
+        let schema = ResponseSchema::from::<Book>().unwrap();
+
+        assert_eq!(
+            schema["propertyOrdering"],
+            serde_json::json!(["title", "author", "isbn"])
+        );
+        assert_eq!(
+            schema["properties"]["author"]["propertyOrdering"],
+            serde_json::json!(["name", "born"])
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_response_schema_property_named_properties() {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Listing {
+            properties: Vec<String>,
+            items: String,
+        }
+
+        let schema = ResponseSchema::from::<Listing>().unwrap();
+
+        let fields: Vec<_> = schema["properties"].as_object().unwrap().keys().collect();
+        assert_eq!(fields, ["properties", "items"]);
+        assert_eq!(
+            schema["propertyOrdering"],
+            serde_json::json!(["properties", "items"])
+        );
+        assert!(schema["properties"]["items"]
+            .get("propertyOrdering")
+            .is_none());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_blocking_worker_reuses_its_thread() {
+        let worker = BlockingWorker::new();
+        let first = worker.run(|| std::thread::current().id()).await;
+        let second = worker.run(|| std::thread::current().id()).await;
+        assert_eq!(first, second);
+        assert_ne!(first, std::thread::current().id());
+    }
+}
@@ -0,0 +1,611 @@
+//! Low-latency voice and text conversations over the Live API's bidirectional WebSocket.
+//! Enabled with the `live` feature.
+//!
+//! Open a session with [`Gemini::live`], send turns or realtime audio and read the model's
+//! replies as [`LiveServerMessage`]s, or only its spoken audio with [`LiveSession::audio`].
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+use typed_builder::TypedBuilder;
+
+use crate::{
+    messages::{
+        media::MediaError,
+        message::{Blob, Content, Part},
+        serialize_system_instruction, UsageMetadata,
+    },
+    models::ModelResource,
+    ApiRequestError, Credential, Gemini, GenerationConfig, API_KEY_HEADER, BASE_URL,
+};
+
+/// Sample rate of the audio the Live API expects as input.
+pub const INPUT_SAMPLE_RATE: u32 = 16_000;
+/// Sample rate of the audio the Live API replies with.
+pub const OUTPUT_SAMPLE_RATE: u32 = 24_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Modality {
+    Text,
+    Audio,
+}
+
+/// The first message of a session, configuring it for its whole lifetime.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct LiveSetup {
+    /// Accepts bare model ids as well as resource names, see [`ModelResource`].
+    #[builder(setter(transform = |model: impl AsRef<str>| ModelResource::parse(model.as_ref()).to_string()))]
+    model: String,
+    /// Defaults to spoken replies.
+    #[builder(default = vec![Modality::Audio], setter(transform = |modalities: impl IntoIterator<Item = Modality>| modalities.into_iter().collect()))]
+    response_modalities: Vec<Modality>,
+    /// A prebuilt voice such as `"Puck"` or `"Kore"`.
+    #[builder(default, setter(into, strip_option))]
+    voice: Option<String>,
+    #[builder(default, setter(strip_option))]
+    generation_config: Option<GenerationConfig>,
+    #[builder(default, setter(transform = |parts: impl IntoIterator<Item = impl Into<Part<'static>>>|
+        Some(Content::system(parts))
+    ))]
+    system_instruction: Option<Content<'static>>,
+    /// Asks the server for [`SessionResumptionUpdate`]s, so the session can be
+    /// [reconnected](LiveSession::reconnect) after the connection drops.
+    #[builder(default)]
+    session_resumption: bool,
+    /// Resumes the session of an earlier connection; implies `session_resumption`.
+    #[builder(default, setter(into, strip_option))]
+    resume_handle: Option<String>,
+}
+
+impl Serialize for LiveSetup {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Setup<'a> {
+            model: &'a str,
+            generation_config: Value,
+            #[serde(
+                skip_serializing_if = "Option::is_none",
+                serialize_with = "serialize_system_instruction"
+            )]
+            system_instruction: &'a Option<Content<'static>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            session_resumption: Option<Value>,
+        }
+
+        // The Live API takes the modalities and voice as part of the generation config.
+        let mut generation_config =
+            serde_json::to_value(self.generation_config.clone().unwrap_or_default())
+                .map_err(serde::ser::Error::custom)?;
+        generation_config["responseModalities"] = json!(self.response_modalities);
+        if let Some(voice) = &self.voice {
+            generation_config["speechConfig"] =
+                json!({"voiceConfig": {"prebuiltVoiceConfig": {"voiceName": voice}}});
+        }
+        Setup {
+            model: &self.model,
+            generation_config,
+            system_instruction: &self.system_instruction,
+            session_resumption: (self.session_resumption || self.resume_handle.is_some())
+                .then(|| json!({ "handle": self.resume_handle })),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// A message from the server. Each carries one of the fields, sometimes together with the
+/// usage so far.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveServerMessage {
+    #[serde(default)]
+    pub setup_complete: Option<Value>,
+    #[serde(default)]
+    pub server_content: Option<ServerContent>,
+    /// The server will close the connection soon.
+    #[serde(default)]
+    pub go_away: Option<GoAway>,
+    #[serde(default)]
+    pub usage_metadata: Option<UsageMetadata>,
+    #[serde(default)]
+    pub session_resumption_update: Option<SessionResumptionUpdate>,
+}
+
+/// Incremental model output.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerContent {
+    #[serde(default)]
+    pub model_turn: Option<Content<'static>>,
+    /// The model finished its turn and waits for input.
+    #[serde(default)]
+    pub turn_complete: bool,
+    /// The user spoke over the model; audio queued for playback should be dropped.
+    #[serde(default)]
+    pub interrupted: bool,
+    #[serde(default)]
+    pub generation_complete: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoAway {
+    /// How long until the connection is closed, e.g. `"10s"`.
+    #[serde(default)]
+    pub time_left: Option<String>,
+}
+
+/// A new handle to resume the session from its current state.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResumptionUpdate {
+    #[serde(default)]
+    pub new_handle: Option<String>,
+    /// Whether the session can be resumed at this point; not e.g. while the model is
+    /// executing a function call.
+    #[serde(default)]
+    pub resumable: bool,
+}
+
+impl LiveServerMessage {
+    /// The audio the model spoke in this message.
+    pub fn audio_chunks(&self) -> impl Iterator<Item = AudioChunk> + '_ {
+        self.server_content
+            .iter()
+            .filter_map(|content| content.model_turn.as_ref())
+            .flat_map(Content::iter)
+            .filter_map(|part| match part {
+                Part::InlineData(blob) => AudioChunk::from_blob(blob).ok(),
+                _ => None,
+            })
+    }
+
+    fn ends_turn(&self) -> bool {
+        self.server_content
+            .as_ref()
+            .is_some_and(|content| content.turn_complete || content.interrupted)
+    }
+}
+
+/// Mono 16-bit little-endian PCM, the format the Live API sends and receives audio in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioChunk {
+    pub sample_rate: u32,
+    pub data: Bytes,
+}
+
+impl AudioChunk {
+    /// Encodes `samples` as 16-bit little-endian PCM.
+    #[must_use]
+    pub fn from_i16(sample_rate: u32, samples: &[i16]) -> Self {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        Self {
+            sample_rate,
+            data: Bytes::from(data),
+        }
+    }
+
+    /// Decodes an `audio/pcm` blob, taking the sample rate from its `rate` parameter, e.g.
+    /// `audio/pcm;rate=24000`.
+    pub fn from_blob(blob: &Blob<'_>) -> Result<Self, MediaError> {
+        let mut params = blob.mime_type.split(';').map(str::trim);
+        if params.next() != Some("audio/pcm") {
+            return Err(MediaError::UnsupportedMimeType(blob.mime_type.clone()));
+        }
+        let sample_rate = params
+            .find_map(|param| param.strip_prefix("rate="))
+            .and_then(|rate| rate.parse().ok())
+            .unwrap_or(OUTPUT_SAMPLE_RATE);
+        Ok(Self {
+            sample_rate,
+            data: Bytes::from(blob.decode()?),
+        })
+    }
+
+    #[must_use]
+    pub fn to_blob(&self) -> Blob<'static> {
+        Blob::new(format!("audio/pcm;rate={}", self.sample_rate), &self.data)
+    }
+
+    /// The samples, ready for playback crates that take interleaved i16 samples.
+    pub fn samples(&self) -> impl Iterator<Item = i16> + '_ {
+        self.data
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+    }
+
+    #[must_use]
+    pub fn to_i16(&self) -> Vec<i16> {
+        self.samples().collect()
+    }
+
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        let samples = (self.data.len() / 2) as u64;
+        Duration::from_micros(samples * 1_000_000 / u64::from(self.sample_rate.max(1)))
+    }
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// An open Live API connection.
+pub struct LiveSession {
+    socket: Socket,
+    gemini: Gemini,
+    url: String,
+    setup: LiveSetup,
+}
+
+impl std::fmt::Debug for LiveSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LiveSession")
+            .field("url", &self.url)
+            .field("resume_handle", &self.setup.resume_handle)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Gemini {
+    /// Opens a Live API session and waits until the server has accepted `setup`.
+    pub async fn live(&self, setup: LiveSetup) -> Result<LiveSession, ApiRequestError> {
+        LiveSession::connect(self.clone(), self.live_url(), setup).await
+    }
+
+    /// Sessions authenticated with an ephemeral token go to the constrained `v1alpha`
+    /// endpoint, which enforces the token's restrictions.
+    fn live_url(&self) -> String {
+        let ws_url = BASE_URL.replacen("https://", "wss://", 1);
+        if self.ephemeral_token.is_some() {
+            format!("{ws_url}/ws/google.ai.generativelanguage.v1alpha.GenerativeService.BidiGenerateContentConstrained")
+        } else {
+            format!(
+                "{ws_url}/ws/google.ai.generativelanguage.{}.GenerativeService.BidiGenerateContent",
+                self.api_version
+            )
+        }
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, ApiRequestError> {
+    HeaderValue::from_str(value)
+        .map_err(|e| ApiRequestError::AuthenticationError(format!("Invalid credential: {e}")))
+}
+
+impl LiveSession {
+    async fn connect(
+        gemini: Gemini,
+        url: String,
+        setup: LiveSetup,
+    ) -> Result<Self, ApiRequestError> {
+        let socket = Self::open(&gemini, &url, &setup).await?;
+        Ok(Self {
+            socket,
+            gemini,
+            url,
+            setup,
+        })
+    }
+
+    /// Connects and sends the setup, fetching fresh credentials on every connection.
+    async fn open(
+        gemini: &Gemini,
+        url: &str,
+        setup: &LiveSetup,
+    ) -> Result<Socket, ApiRequestError> {
+        let mut request = url.into_client_request()?;
+        let headers = request.headers_mut();
+        if let Some(token) = &gemini.ephemeral_token {
+            headers.insert("authorization", header_value(&format!("Token {token}"))?);
+        } else {
+            match gemini.credential().await? {
+                Credential::BearerToken(token) => {
+                    headers.insert("authorization", header_value(&format!("Bearer {token}"))?);
+                }
+                Credential::ApiKey(api_key) => {
+                    headers.insert(API_KEY_HEADER, header_value(&api_key)?);
+                }
+                Credential::Anonymous => {}
+            }
+        }
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+        let setup = serde_json::to_string(&json!({ "setup": setup }))?;
+        socket.send(Message::text(setup)).await?;
+        match read_message(&mut socket).await {
+            Some(Ok(message)) if message.setup_complete.is_some() => Ok(socket),
+            Some(Err(e)) => Err(e),
+            Some(Ok(message)) => Err(ApiRequestError::InvalidEventData(format!(
+                "expected setupComplete, got {message:?}"
+            ))),
+            None => Err(ApiRequestError::InvalidEventData(
+                "connection closed during setup".to_string(),
+            )),
+        }
+    }
+
+    /// The latest handle the session can be resumed with, if session resumption is enabled
+    /// and the server has sent one.
+    #[must_use]
+    pub fn resume_handle(&self) -> Option<&str> {
+        self.setup.resume_handle.as_deref()
+    }
+
+    /// Replaces the connection with a new one that resumes the session from the latest
+    /// [resume handle](LiveSession::resume_handle), e.g. after a network error or a
+    /// [`GoAway`] ahead of the connection time limit. Without a handle, a fresh session is
+    /// started.
+    pub async fn reconnect(&mut self) -> Result<(), ApiRequestError> {
+        let socket = Self::open(&self.gemini, &self.url, &self.setup).await?;
+        let mut old = std::mem::replace(&mut self.socket, socket);
+        // The old connection is usually gone already.
+        let _ = old.close(None).await;
+        Ok(())
+    }
+
+    async fn send_json(&mut self, message: &Value) -> Result<(), ApiRequestError> {
+        self.socket
+            .send(Message::text(serde_json::to_string(message)?))
+            .await?;
+        Ok(())
+    }
+
+    /// Sends a complete user turn, which the model answers right away.
+    pub async fn send_text(&mut self, text: impl Into<String>) -> Result<(), ApiRequestError> {
+        self.send_turns([Content::from(text.into())], true).await
+    }
+
+    /// Appends `turns` to the conversation; the model only answers once `turn_complete`.
+    pub async fn send_turns(
+        &mut self,
+        turns: impl IntoIterator<Item = Content<'static>>,
+        turn_complete: bool,
+    ) -> Result<(), ApiRequestError> {
+        let turns: Vec<_> = turns.into_iter().collect();
+        self.send_json(&json!({
+            "clientContent": {"turns": turns, "turnComplete": turn_complete}
+        }))
+        .await
+    }
+
+    /// Streams microphone audio; the server detects when the user stops speaking. Send
+    /// audio at [`INPUT_SAMPLE_RATE`].
+    pub async fn send_audio(&mut self, chunk: &AudioChunk) -> Result<(), ApiRequestError> {
+        self.send_json(&json!({"realtimeInput": {"audio": chunk.to_blob()}}))
+            .await
+    }
+
+    /// The next message from the server, or `None` once the connection is closed.
+    pub async fn next_message(&mut self) -> Option<Result<LiveServerMessage, ApiRequestError>> {
+        let message = read_message(&mut self.socket).await;
+        if let Some(Ok(LiveServerMessage {
+            session_resumption_update:
+                Some(SessionResumptionUpdate {
+                    new_handle: Some(handle),
+                    resumable: true,
+                }),
+            ..
+        })) = &message
+        {
+            self.setup.resume_handle = Some(handle.clone());
+        }
+        message
+    }
+
+    /// The audio of the model's current reply, ending when its turn is complete or the
+    /// user interrupts it. Other messages of the turn are skipped.
+    pub fn audio(&mut self) -> impl Stream<Item = Result<AudioChunk, ApiRequestError>> + '_ {
+        futures::stream::unfold(Some(self), |session| async move {
+            let session = session?;
+            let message = match session.next_message().await? {
+                Ok(message) => message,
+                Err(e) => return Some((vec![Err(e)], None)),
+            };
+            let chunks = message.audio_chunks().map(Ok).collect();
+            let session = (!message.ends_turn()).then_some(session);
+            Some((chunks, session))
+        })
+        .flat_map(futures::stream::iter)
+    }
+
+    /// Closes the connection.
+    pub async fn close(mut self) -> Result<(), ApiRequestError> {
+        self.socket.close(None).await?;
+        Ok(())
+    }
+}
+
+async fn read_message(socket: &mut Socket) -> Option<Result<LiveServerMessage, ApiRequestError>> {
+    loop {
+        let json = match socket.next().await? {
+            Ok(Message::Text(text)) => Bytes::from(text),
+            // The server sends its JSON messages as binary frames.
+            Ok(Message::Binary(data)) => data,
+            Ok(Message::Close(_)) => return None,
+            Ok(_) => continue,
+            Err(e) => return Some(Err(e.into())),
+        };
+        return Some(serde_json::from_slice(&json).map_err(ApiRequestError::SerdeError));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serves one connection per entry of `connections`: accepts the setup, answers the
+    /// first client message with the entry's server messages and closes the connection.
+    /// Returns the setup and client message received on each connection.
+    async fn serve(
+        connections: Vec<Vec<Value>>,
+    ) -> (String, tokio::task::JoinHandle<Vec<(Value, Value)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let read = |message: Message| -> Value {
+                serde_json::from_str(message.to_text().unwrap()).unwrap()
+            };
+            let mut received = Vec::new();
+            for replies in connections {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let setup = read(socket.next().await.unwrap().unwrap());
+                socket
+                    .send(Message::binary(r#"{"setupComplete": {}}"#))
+                    .await
+                    .unwrap();
+                let message = read(socket.next().await.unwrap().unwrap());
+                for reply in replies {
+                    socket
+                        .send(Message::binary(reply.to_string()))
+                        .await
+                        .unwrap();
+                }
+                socket.close(None).await.unwrap();
+                received.push((setup, message));
+            }
+            received
+        });
+        (url, server)
+    }
+
+    fn audio_reply(samples: &[i16]) -> Value {
+        let blob = AudioChunk::from_i16(OUTPUT_SAMPLE_RATE, samples).to_blob();
+        json!({"serverContent": {"modelTurn": {"role": "model", "parts": [{"inlineData": blob}]}}})
+    }
+
+    #[test]
+    fn test_audio_chunk() {
+        let chunk = AudioChunk::from_i16(16_000, &[1, -2, i16::MAX]);
+        assert_eq!(chunk.data.len(), 6);
+        assert_eq!(chunk.to_i16(), [1, -2, i16::MAX]);
+        assert_eq!(chunk.to_blob().mime_type, "audio/pcm;rate=16000");
+        assert_eq!(AudioChunk::from_blob(&chunk.to_blob()).unwrap(), chunk);
+        assert_eq!(
+            AudioChunk::from_i16(24_000, &[0; 2400]).duration(),
+            Duration::from_millis(100)
+        );
+        assert!(AudioChunk::from_blob(&Blob::new("audio/wav", b"RIFF")).is_err());
+    }
+
+    #[test]
+    fn test_ephemeral_token_url() {
+        let gemini = Gemini::builder().api_key("key").build();
+        assert!(gemini
+            .live_url()
+            .ends_with("v1beta.GenerativeService.BidiGenerateContent"));
+
+        let gemini = Gemini::builder().ephemeral_token("auth_tokens/abc").build();
+        assert_eq!(
+            gemini.live_url(),
+            "wss://generativelanguage.googleapis.com/ws/google.ai.generativelanguage.v1alpha.GenerativeService.BidiGenerateContentConstrained"
+        );
+    }
+
+    #[test]
+    fn test_setup_serialization() {
+        let setup = LiveSetup::builder()
+            .model("gemini-2.0-flash-live-001")
+            .voice("Kore")
+            .system_instruction(["Be brief."])
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(&setup).unwrap(),
+            json!({
+                "model": "models/gemini-2.0-flash-live-001",
+                "generationConfig": {
+                    "responseModalities": ["AUDIO"],
+                    "speechConfig": {"voiceConfig": {"prebuiltVoiceConfig": {"voiceName": "Kore"}}}
+                },
+                "systemInstruction": {"parts": [{"text": "Be brief."}]}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audio_stream() {
+        let (url, server) = serve(vec![vec![
+            audio_reply(&[1, 2]),
+            json!({"usageMetadata": {"promptTokenCount": 1, "totalTokenCount": 3}}),
+            audio_reply(&[3]),
+            json!({"serverContent": {"turnComplete": true}}),
+        ]])
+        .await;
+        let setup = LiveSetup::builder()
+            .model("gemini-2.0-flash-live-001")
+            .build();
+        let mut session = LiveSession::connect(Gemini::builder().build(), url, setup)
+            .await
+            .unwrap();
+
+        session.send_text("Hi").await.unwrap();
+        let chunks: Vec<_> = session.audio().map(Result::unwrap).collect().await;
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].sample_rate, OUTPUT_SAMPLE_RATE);
+        assert_eq!(
+            chunks
+                .iter()
+                .flat_map(AudioChunk::samples)
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        let (setup, message) = &server.await.unwrap()[0];
+        assert_eq!(setup["setup"]["model"], "models/gemini-2.0-flash-live-001");
+        assert_eq!(
+            *message,
+            json!({"clientContent": {
+                "turns": [{"role": "user", "parts": [{"text": "Hi"}]}],
+                "turnComplete": true
+            }})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_resumption() {
+        let (url, server) = serve(vec![
+            vec![
+                json!({"sessionResumptionUpdate": {"newHandle": "handle-1", "resumable": true}}),
+                json!({"sessionResumptionUpdate": {"newHandle": "handle-2", "resumable": false}}),
+            ],
+            vec![json!({"serverContent": {"turnComplete": true}})],
+        ])
+        .await;
+        let setup = LiveSetup::builder()
+            .model("gemini-2.0-flash-live-001")
+            .session_resumption(true)
+            .build();
+        let mut session = LiveSession::connect(Gemini::builder().build(), url, setup)
+            .await
+            .unwrap();
+
+        session.send_text("Hi").await.unwrap();
+        while let Some(message) = session.next_message().await {
+            message.unwrap();
+        }
+        assert_eq!(session.resume_handle(), Some("handle-1"));
+        session.reconnect().await.unwrap();
+        session.send_text("Are you still there?").await.unwrap();
+        let chunks: Vec<_> = session.audio().collect().await;
+        assert!(chunks.is_empty());
+
+        let received = server.await.unwrap();
+        assert_eq!(
+            received[0].0["setup"]["sessionResumption"],
+            json!({"handle": null})
+        );
+        assert_eq!(
+            received[1].0["setup"]["sessionResumption"],
+            json!({"handle": "handle-1"})
+        );
+    }
+}
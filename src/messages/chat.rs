@@ -0,0 +1,483 @@
+//! Multi-turn chats that keep their history between calls and trim it to fit the context
+//! window.
+//!
+//! ```no_run
+//! # async fn run(gemini: gemini_ox::Gemini) -> Result<(), gemini_ox::ApiRequestError> {
+//! use gemini_ox::messages::chat::HistoryPolicy;
+//!
+//! let mut chat = gemini
+//!     .chat("gemini-1.5-flash")
+//!     .with_system_instruction(["Answer in one sentence."])
+//!     .with_history_policy(HistoryPolicy::TokenBudget(100_000));
+//! let answer = chat.send("What is a monad?").await?.text();
+//! let follow_up = chat.send("And a functor?").await?.text();
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{
+    message::{Content, Part, Role},
+    transcript::render_markdown,
+    GenerateContentResponse,
+};
+use crate::{
+    tokens::{HeuristicEstimator, TokenEstimator},
+    usage::UsageTracker,
+    ApiRequestError, Gemini, GenerationConfig,
+};
+
+/// Which part of a chat's history is sent with the next call.
+///
+/// Turns are dropped oldest first and the kept history always starts at a user message, so
+/// function calls are never separated from their responses. The latest turn is always kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryPolicy {
+    /// Sends the whole history.
+    #[default]
+    Unbounded,
+    /// Keeps at most this many of the latest turns.
+    LastTurns(usize),
+    /// Sliding window: keeps the latest turns whose estimated size fits the budget.
+    TokenBudget(u32),
+}
+
+impl HistoryPolicy {
+    /// Drops the turns of `history` that don't fit. `reserved` tokens of a
+    /// [`HistoryPolicy::TokenBudget`] are taken by parts of the prompt that are always sent,
+    /// such as the system instruction.
+    pub fn apply(
+        &self,
+        history: &mut Vec<Content<'_>>,
+        reserved: u32,
+        estimator: &impl TokenEstimator,
+    ) {
+        let start = match *self {
+            HistoryPolicy::Unbounded => 0,
+            HistoryPolicy::LastTurns(turns) => history.len().saturating_sub(turns.max(1)),
+            HistoryPolicy::TokenBudget(budget) => {
+                let mut used = reserved;
+                let fitting = history
+                    .iter()
+                    .rev()
+                    .take_while(|content| {
+                        used = used.saturating_add(estimator.estimate_content(content));
+                        used <= budget
+                    })
+                    .count();
+                history.len() - fitting.max(1).min(history.len())
+            }
+        };
+        if start == 0 {
+            return;
+        }
+        let start = (start..history.len())
+            .find(|&i| starts_turn(&history[i]))
+            .unwrap_or(history.len().saturating_sub(1));
+        history.drain(..start);
+    }
+}
+
+/// Whether a history may start at `content`: a user message that isn't a function response.
+fn starts_turn(content: &Content<'_>) -> bool {
+    content.role == Role::User
+        && !content
+            .iter()
+            .any(|part| matches!(part, Part::FunctionResponse(_)))
+}
+
+/// Opt-in summarization of old turns, see [`ChatSession::with_compaction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compaction {
+    /// Model writing the summary, typically a cheaper one than the chat's.
+    pub model: String,
+    /// Compacts once the estimated size of the history exceeds this many tokens.
+    pub threshold: u32,
+    /// Latest turns kept verbatim after the summary.
+    pub keep_turns: usize,
+    /// Instruction given to the summarizing model, followed by the turns to summarize.
+    pub instruction: String,
+}
+
+impl Compaction {
+    pub fn new(model: impl Into<String>, threshold: u32) -> Self {
+        Self {
+            model: model.into(),
+            threshold,
+            keep_turns: 4,
+            instruction: "Summarize the following conversation. Keep every fact, decision and \
+                          open question needed to continue it."
+                .to_string(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_keep_turns(mut self, keep_turns: usize) -> Self {
+        self.keep_turns = keep_turns;
+        self
+    }
+
+    #[must_use]
+    pub fn with_instruction(mut self, instruction: impl Into<String>) -> Self {
+        self.instruction = instruction.into();
+        self
+    }
+}
+
+/// A conversation with one model. Each [`ChatSession::send`] appends the message and the
+/// model's reply to the history, which is trimmed by the [`HistoryPolicy`] before each call.
+#[derive(Debug, Clone)]
+pub struct ChatSession {
+    gemini: Gemini,
+    model: String,
+    system_instruction: Option<Content<'static>>,
+    generation_config: Option<GenerationConfig>,
+    history: Vec<Content<'static>>,
+    history_policy: HistoryPolicy,
+    compaction: Option<Compaction>,
+    usage: UsageTracker,
+}
+
+impl Gemini {
+    pub fn chat(&self, model: impl Into<String>) -> ChatSession {
+        ChatSession {
+            gemini: self.clone(),
+            model: model.into(),
+            system_instruction: None,
+            generation_config: None,
+            history: Vec::new(),
+            history_policy: HistoryPolicy::default(),
+            compaction: None,
+            usage: UsageTracker::new(),
+        }
+    }
+}
+
+impl ChatSession {
+    #[must_use]
+    pub fn with_system_instruction(
+        mut self,
+        parts: impl IntoIterator<Item = impl Into<Part<'static>>>,
+    ) -> Self {
+        self.system_instruction = Some(Content::system(parts));
+        self
+    }
+
+    #[must_use]
+    pub fn with_generation_config(mut self, config: GenerationConfig) -> Self {
+        self.generation_config = Some(config);
+        self
+    }
+
+    #[must_use]
+    pub fn with_history_policy(mut self, policy: HistoryPolicy) -> Self {
+        self.history_policy = policy;
+        self
+    }
+
+    /// Replaces old turns by a summary once the history grows past the threshold of
+    /// `compaction`. Runs before the [`HistoryPolicy`] is applied.
+    #[must_use]
+    pub fn with_compaction(mut self, compaction: Compaction) -> Self {
+        self.compaction = Some(compaction);
+        self
+    }
+
+    /// Continues an earlier conversation.
+    #[must_use]
+    pub fn with_history(mut self, history: impl IntoIterator<Item = Content<'static>>) -> Self {
+        self.history = history.into_iter().collect();
+        self
+    }
+
+    #[must_use]
+    pub fn history(&self) -> &[Content<'static>] {
+        &self.history
+    }
+
+    #[must_use]
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Tokens used by this session per model, including the summaries of compaction.
+    #[must_use]
+    pub fn usage(&self) -> &UsageTracker {
+        &self.usage
+    }
+
+    /// Sends `message` with the history that fits the [`HistoryPolicy`] and records the
+    /// reply. On failure the history is left as it was before the call.
+    pub async fn send(
+        &mut self,
+        message: impl Into<Content<'static>>,
+    ) -> Result<GenerateContentResponse, ApiRequestError> {
+        // Compaction and the policy work on a copy, which replaces the history only once the
+        // reply is in.
+        let mut history = self.history.clone();
+        history.push(message.into());
+        self.compact(&mut history).await?;
+        self.apply_history_policy(&mut history);
+
+        let mut request = self
+            .gemini
+            .generate_content()
+            .contents(history)
+            .model(self.model.clone())
+            .build();
+        request
+            .system_instruction
+            .clone_from(&self.system_instruction);
+        request
+            .generation_config
+            .clone_from(&self.generation_config);
+
+        let response = request.send().await?;
+        if let Some(usage) = &response.usage_metadata {
+            self.usage.record(self.model.clone(), usage);
+        }
+        self.history = request.contents;
+        if let Some(reply) = response.content() {
+            self.history.push(reply.clone());
+        }
+        Ok(response)
+    }
+
+    /// Summarizes the turns of `history` before the last `keep_turns` into a single content,
+    /// if it exceeds the compaction threshold.
+    async fn compact(
+        &mut self,
+        history: &mut Vec<Content<'static>>,
+    ) -> Result<(), ApiRequestError> {
+        let Some(compaction) = &self.compaction else {
+            return Ok(());
+        };
+        let estimator = HeuristicEstimator::default();
+        if estimator.estimate_contents(history) <= compaction.threshold {
+            return Ok(());
+        }
+        let Some(split) = (history.len().saturating_sub(compaction.keep_turns.max(1))
+            ..history.len())
+            .find(|&i| i > 0 && starts_turn(&history[i]))
+        else {
+            return Ok(());
+        };
+
+        let prompt = format!(
+            "{}\n\n{}",
+            compaction.instruction,
+            render_markdown(&history[..split])
+        );
+        let response = self
+            .gemini
+            .generate_content()
+            .contents(prompt)
+            .model(compaction.model.clone())
+            .build()
+            .send()
+            .await?
+            .error_for_block()?;
+        if let Some(usage) = &response.usage_metadata {
+            self.usage.record(compaction.model.clone(), usage);
+        }
+        let summary = Content::from(format!(
+            "Summary of the conversation so far:\n\n{}",
+            response.text()
+        ));
+        history.splice(..split, [summary]);
+        Ok(())
+    }
+
+    fn apply_history_policy(&self, history: &mut Vec<Content<'static>>) {
+        let estimator = HeuristicEstimator::default();
+        let reserved = self
+            .system_instruction
+            .as_ref()
+            .map_or(0, |content| estimator.estimate_content(content));
+        self.history_policy.apply(history, reserved, &estimator);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{messages::message::FunctionResponse, transport::tests::MockTransport};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    fn model(text: &'static str) -> Content<'static> {
+        Content::builder().role(Role::Model).parts([text]).build()
+    }
+
+    fn history() -> Vec<Content<'static>> {
+        vec![
+            Content::from("first question"),
+            model("first answer"),
+            Content::from("second question"),
+            model("second answer"),
+            Content::from("third question"),
+        ]
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_last_turns() {
+        let estimator = HeuristicEstimator::default();
+        let mut contents = history();
+        HistoryPolicy::LastTurns(3).apply(&mut contents, 0, &estimator);
+        assert_eq!(contents, history()[2..]);
+
+        // Cutting at a model turn moves on to the next user message.
+        let mut contents = history();
+        HistoryPolicy::LastTurns(2).apply(&mut contents, 0, &estimator);
+        assert_eq!(contents, history()[4..]);
+
+        let mut contents = history();
+        HistoryPolicy::Unbounded.apply(&mut contents, 0, &estimator);
+        assert_eq!(contents, history());
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_token_budget() {
+        let estimator = HeuristicEstimator::default();
+        // Every turn is estimated at 3 or 4 tokens.
+        let mut contents = history();
+        HistoryPolicy::TokenBudget(12).apply(&mut contents, 0, &estimator);
+        assert_eq!(contents, history()[2..]);
+
+        let mut contents = history();
+        HistoryPolicy::TokenBudget(12).apply(&mut contents, 5, &estimator);
+        assert_eq!(contents, history()[4..]);
+
+        // The latest turn is kept even if it exceeds the budget on its own.
+        let mut contents = history();
+        HistoryPolicy::TokenBudget(1).apply(&mut contents, 0, &estimator);
+        assert_eq!(contents, history()[4..]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_function_responses_stay_with_their_calls() {
+        let response = Content::builder()
+            .role(Role::User)
+            .parts([Part::FunctionResponse(FunctionResponse {
+                name: "weather".to_string(),
+                response: json!({"celsius": 3}),
+            })])
+            .build();
+        let mut contents = vec![
+            Content::from("weather?"),
+            model("calling weather"),
+            response,
+            model("3°C"),
+            Content::from("thanks"),
+        ];
+        HistoryPolicy::LastTurns(3).apply(&mut contents, 0, &HeuristicEstimator::default());
+        assert_eq!(contents, [Content::from("thanks")]);
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_compaction() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": "They talked."}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }],
+                "usageMetadata": {"promptTokenCount": 10, "totalTokenCount": 13}
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let mut chat = gemini
+            .chat("gemini-1.5-pro")
+            .with_history(history()[..4].to_vec())
+            .with_compaction(Compaction::new("gemini-1.5-flash-8b", 10).with_keep_turns(1));
+
+        chat.send("third question").await.unwrap();
+
+        assert_eq!(
+            chat.history(),
+            [
+                Content::from("Summary of the conversation so far:\n\nThey talked."),
+                Content::from("third question"),
+                model("They talked."),
+            ]
+        );
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].url().path(),
+            "/v1beta/models/gemini-1.5-flash-8b:generateContent"
+        );
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        let prompt = body["contents"][0]["parts"][0]["text"].as_str().unwrap();
+        assert!(prompt.contains("second answer"));
+        assert!(!prompt.contains("third question"));
+        assert_eq!(chat.usage().for_model("gemini-1.5-flash-8b").requests, 1);
+        assert_eq!(chat.usage().total().prompt_tokens, 20);
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_chat_session_records_history() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": "Hi!"}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }]
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let mut chat = gemini
+            .chat("gemini-1.5-flash")
+            .with_system_instruction(["Be brief."])
+            .with_history_policy(HistoryPolicy::LastTurns(2));
+
+        chat.send("hello").await.unwrap();
+        chat.send("hello again").await.unwrap();
+
+        assert_eq!(chat.history(), [Content::from("hello again"), model("Hi!")]);
+        let requests = transport.requests.lock().unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[1].body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body["contents"],
+            json!([{"role": "user", "parts": [{"text": "hello again"}]}])
+        );
+        assert_eq!(
+            body["system_instruction"],
+            json!({"parts": [{"text": "Be brief."}]})
+        );
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_failed_send_keeps_history() {
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(MockTransport::json(500, &json!({})))
+            .build();
+        let mut chat = gemini
+            .chat("gemini-1.5-flash")
+            .with_history(history())
+            .with_history_policy(HistoryPolicy::LastTurns(2));
+
+        assert!(chat.send("third question").await.is_err());
+        assert_eq!(chat.history(), history());
+    }
+}
@@ -0,0 +1,295 @@
+//! Prompt assembly that keeps the parts shared between requests at the front.
+//!
+//! Gemini caches prompt prefixes implicitly: a request starting with the same tokens as a
+//! recent one is billed at the cached-input rate for that prefix. [`PromptLayout`] puts the
+//! system instruction, few-shot examples and documents first, in that order, and the
+//! conversation history and the new question last, so only the end of the prompt changes
+//! between requests.
+
+use thiserror::Error;
+
+use super::{
+    message::{Content, Contents, Part, Role},
+    GenerateContentRequest,
+};
+use crate::tokens::{HeuristicEstimator, TokenEstimator};
+
+/// Shortest prefix implicit caching applies to on Flash models; Pro models need more.
+pub const IMPLICIT_CACHE_MIN_TOKENS: u32 = 1024;
+
+/// The static sections of a [`PromptLayout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum Section {
+    #[strum(to_string = "system instruction")]
+    System,
+    #[strum(to_string = "examples")]
+    Examples,
+    #[strum(to_string = "documents")]
+    Documents,
+}
+
+/// Why a layout is unlikely to hit the implicit cache.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LayoutWarning {
+    #[error("Static prefix of about {estimated} tokens is below the {min} tokens implicit caching needs")]
+    ShortStaticPrefix { estimated: u32, min: u32 },
+    /// Dates and times change from request to request, so the prefix does too.
+    #[error("The {0} contain a date or time, which changes the prefix between requests")]
+    TimestampInStaticPrefix(Section),
+}
+
+/// Orders a prompt for implicit caching, see the [module docs](self).
+///
+/// ```
+/// # let gemini = gemini_ox::Gemini::builder().api_key("key").build();
+/// use gemini_ox::messages::layout::PromptLayout;
+///
+/// let layout = PromptLayout::new()
+///     .query("Does the contract allow subletting?")
+///     .system(["Answer questions about the contract below."])
+///     .document("CONTRACT ...");
+/// let request = gemini
+///     .generate_content()
+///     .model("gemini-2.5-flash")
+///     .build()
+///     .with_layout(layout);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PromptLayout {
+    system: Vec<Part<'static>>,
+    examples: Vec<Content<'static>>,
+    documents: Vec<Content<'static>>,
+    history: Vec<Content<'static>>,
+    query: Vec<Content<'static>>,
+    min_cached_tokens: u32,
+}
+
+impl Default for PromptLayout {
+    fn default() -> Self {
+        Self {
+            system: Vec::new(),
+            examples: Vec::new(),
+            documents: Vec::new(),
+            history: Vec::new(),
+            query: Vec::new(),
+            min_cached_tokens: IMPLICIT_CACHE_MIN_TOKENS,
+        }
+    }
+}
+
+impl PromptLayout {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends to the system instruction.
+    #[must_use]
+    pub fn system(mut self, parts: impl IntoIterator<Item = impl Into<Part<'static>>>) -> Self {
+        self.system.extend(parts.into_iter().map(Into::into));
+        self
+    }
+
+    /// Adds a few-shot example: a user turn and the model's answer to it.
+    #[must_use]
+    pub fn example(
+        mut self,
+        input: impl Into<Content<'static>>,
+        output: impl Into<Content<'static>>,
+    ) -> Self {
+        let mut output = output.into();
+        output.role = Role::Model;
+        self.examples.extend([input.into(), output]);
+        self
+    }
+
+    /// Adds a document, e.g. a file reference or retrieved text, as a user turn.
+    #[must_use]
+    pub fn document(mut self, document: impl Into<Content<'static>>) -> Self {
+        self.documents.push(document.into());
+        self
+    }
+
+    /// Appends to the conversation so far, which only grows at its end between requests.
+    #[must_use]
+    pub fn history(mut self, history: impl Into<Contents>) -> Self {
+        self.history.extend(history.into());
+        self
+    }
+
+    /// Appends to what changes with every request, e.g. the user's question.
+    #[must_use]
+    pub fn query(mut self, query: impl Into<Contents>) -> Self {
+        self.query.extend(query.into());
+        self
+    }
+
+    /// Sets the prefix length below which [`LayoutWarning::ShortStaticPrefix`] is reported,
+    /// e.g. for Pro models.
+    #[must_use]
+    pub fn with_min_cached_tokens(mut self, min_cached_tokens: u32) -> Self {
+        self.min_cached_tokens = min_cached_tokens;
+        self
+    }
+
+    /// The system instruction, `None` if no parts were added.
+    #[must_use]
+    pub fn system_instruction(&self) -> Option<Content<'static>> {
+        (!self.system.is_empty()).then(|| Content::system(self.system.clone()))
+    }
+
+    /// Examples, documents, history and query, in that order.
+    #[must_use]
+    pub fn contents(&self) -> Vec<Content<'static>> {
+        [&self.examples, &self.documents, &self.history, &self.query]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Estimated size of the system instruction, examples and documents.
+    pub fn static_prefix_tokens(&self, estimator: &impl TokenEstimator) -> u32 {
+        self.system
+            .iter()
+            .map(|part| estimator.estimate_part(part))
+            .sum::<u32>()
+            + estimator.estimate_contents(&self.examples)
+            + estimator.estimate_contents(&self.documents)
+    }
+
+    /// Problems that keep requests with this layout from hitting the implicit cache.
+    #[must_use]
+    pub fn warnings(&self) -> Vec<LayoutWarning> {
+        let mut warnings = Vec::new();
+        let estimated = self.static_prefix_tokens(&HeuristicEstimator::default());
+        if estimated < self.min_cached_tokens {
+            warnings.push(LayoutWarning::ShortStaticPrefix {
+                estimated,
+                min: self.min_cached_tokens,
+            });
+        }
+        let sections = [
+            (Section::System, self.system.iter().collect::<Vec<_>>()),
+            (
+                Section::Examples,
+                self.examples.iter().flat_map(Content::parts).collect(),
+            ),
+            (
+                Section::Documents,
+                self.documents.iter().flat_map(Content::parts).collect(),
+            ),
+        ];
+        for (section, parts) in sections {
+            let has_timestamp = parts
+                .into_iter()
+                .any(|part| matches!(part, Part::Text(text) if contains_timestamp(&text.0)));
+            if has_timestamp {
+                warnings.push(LayoutWarning::TimestampInStaticPrefix(section));
+            }
+        }
+        warnings
+    }
+}
+
+/// Whether `text` contains an ISO date (`2024-10-01`) or a time with seconds (`12:05:00`).
+fn contains_timestamp(text: &str) -> bool {
+    let matches = |window: &[u8], pattern: &[u8]| {
+        window.iter().zip(pattern).all(|(&c, &p)| match p {
+            b'd' => c.is_ascii_digit(),
+            p => c == p,
+        })
+    };
+    let bytes = text.as_bytes();
+    bytes
+        .windows(10)
+        .any(|window| matches(window, b"dddd-dd-dd"))
+        || bytes.windows(8).any(|window| matches(window, b"dd:dd:dd"))
+}
+
+impl GenerateContentRequest {
+    /// Replaces the system instruction, if the layout has one, and the contents with those of
+    /// `layout`.
+    #[must_use]
+    pub fn with_layout(mut self, layout: PromptLayout) -> Self {
+        if !layout.system.is_empty() {
+            self.system_instruction = Some(Content::system(layout.system));
+        }
+        self.contents = [
+            layout.examples,
+            layout.documents,
+            layout.history,
+            layout.query,
+        ]
+        .concat();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Gemini;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_layout_order() {
+        let layout = PromptLayout::new()
+            .query("What changed?")
+            .history(["Summarize the report.", "It covers Q3."])
+            .document("REPORT")
+            .example("Summarize: a", "A.")
+            .system(["Be brief."]);
+
+        let request = Gemini::builder()
+            .api_key("test")
+            .build()
+            .generate_content()
+            .model("gemini-2.5-flash")
+            .build()
+            .with_layout(layout);
+
+        let texts: Vec<_> = request.contents.iter().map(Content::text).collect();
+        assert_eq!(
+            texts,
+            [
+                "Summarize: a",
+                "A.",
+                "REPORT",
+                "Summarize the report.",
+                "It covers Q3.",
+                "What changed?"
+            ]
+        );
+        assert_eq!(request.contents[1].role, Role::Model);
+        assert_eq!(request.system_instruction.unwrap().text(), "Be brief.");
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_layout_warnings() {
+        let layout = PromptLayout::new()
+            .system(["Today is 2024-10-01."])
+            .query("Hi");
+        assert_eq!(
+            layout.warnings(),
+            [
+                LayoutWarning::ShortStaticPrefix {
+                    estimated: 5,
+                    min: IMPLICIT_CACHE_MIN_TOKENS
+                },
+                LayoutWarning::TimestampInStaticPrefix(Section::System),
+            ]
+        );
+
+        let layout = PromptLayout::new()
+            .document("x".repeat(4096))
+            .query("Asked at 12:05:00")
+            .with_min_cached_tokens(1024);
+        assert!(layout.warnings().is_empty());
+        assert!(!contains_timestamp("version 1.2.3, 10:30"));
+    }
+}
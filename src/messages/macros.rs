@@ -0,0 +1,166 @@
+//! `parts!` and `content!`, shorthands for multimodal prompts that take care of reading
+//! files, MIME types and base64 encoding.
+
+/// Builds a `Vec<Part>` from a list of items, failing with a
+/// [`MediaError`](crate::messages::media::MediaError) if a file can't be read or its type
+/// is unsupported. Besides anything convertible into a [`Part`](crate::messages::message::Part),
+/// items can be:
+///
+/// - `image(path)`, an image file sent inline, see `Part::image_from_path`;
+/// - `audio(path)`, an audio file sent inline, see `Part::audio_from_path`;
+/// - `file(uri)` or `file(uri, mime_type)`, a file uploaded with the Files API;
+/// - `youtube(url)`, a public YouTube video.
+///
+/// ```no_run
+/// # fn main() -> Result<(), gemini_ox::messages::media::MediaError> {
+/// let parts = gemini_ox::parts!["Compare these:", image("a.png"), image("b.png")]?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! parts {
+    ($($items:tt)*) => {
+        $crate::__parts!([] $($items)*)
+    };
+}
+
+/// Like [`parts!`], but wraps the parts in a user [`Content`](crate::messages::message::Content).
+///
+/// ```no_run
+/// # fn main() -> Result<(), gemini_ox::messages::media::MediaError> {
+/// let content = gemini_ox::content!["Describe this:", image("photo.jpg")]?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! content {
+    ($($items:tt)*) => {
+        $crate::parts!($($items)*).map(|parts| {
+            $crate::messages::message::Content::builder()
+                .role($crate::messages::message::Role::User)
+                .parts(parts)
+                .build()
+        })
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __parts {
+    ([$($done:expr,)*]) => {
+        [$($done,)*].into_iter().collect::<::std::result::Result<
+            ::std::vec::Vec<$crate::messages::message::Part<'_>>,
+            $crate::messages::media::MediaError,
+        >>()
+    };
+    ([$($done:expr,)*] image($path:expr) $(, $($rest:tt)*)?) => {
+        $crate::__parts!(
+            [$($done,)* $crate::messages::message::Part::image_from_path($path),]
+            $($($rest)*)?
+        )
+    };
+    ([$($done:expr,)*] audio($path:expr) $(, $($rest:tt)*)?) => {
+        $crate::__parts!(
+            [$($done,)* $crate::messages::message::Part::audio_from_path($path),]
+            $($($rest)*)?
+        )
+    };
+    ([$($done:expr,)*] file($uri:expr, $mime_type:expr) $(, $($rest:tt)*)?) => {
+        $crate::__parts!(
+            [$($done,)* ::std::result::Result::Ok($crate::messages::message::Part::FileData(
+                $crate::messages::message::FileData {
+                    mime_type: ::std::option::Option::Some(::std::string::String::from($mime_type)),
+                    file_uri: ::std::string::String::from($uri),
+                    video_metadata: ::std::option::Option::None,
+                },
+            )),]
+            $($($rest)*)?
+        )
+    };
+    ([$($done:expr,)*] file($uri:expr) $(, $($rest:tt)*)?) => {
+        $crate::__parts!(
+            [$($done,)* ::std::result::Result::Ok($crate::messages::message::Part::FileData(
+                $crate::messages::message::FileData {
+                    mime_type: ::std::option::Option::None,
+                    file_uri: ::std::string::String::from($uri),
+                    video_metadata: ::std::option::Option::None,
+                },
+            )),]
+            $($($rest)*)?
+        )
+    };
+    ([$($done:expr,)*] youtube($url:expr) $(, $($rest:tt)*)?) => {
+        $crate::__parts!(
+            [$($done,)* $crate::messages::message::Part::youtube($url),]
+            $($($rest)*)?
+        )
+    };
+    ([$($done:expr,)*] $part:expr $(, $($rest:tt)*)?) => {
+        $crate::__parts!(
+            [$($done,)* ::std::result::Result::Ok($crate::messages::message::Part::from($part)),]
+            $($($rest)*)?
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::messages::{
+        media::MediaError,
+        message::{Content, FileData, Part, Role},
+    };
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_parts_macro() {
+        let uri = "https://generativelanguage.googleapis.com/v1beta/files/abc";
+        let parts = parts![
+            "Summarize",
+            file(uri, "application/pdf"),
+            file(uri),
+            youtube("https://youtu.be/9hE5-98ZeCg"),
+        ]
+        .unwrap();
+
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], Part::from("Summarize"));
+        assert_eq!(
+            parts[1],
+            Part::FileData(FileData {
+                mime_type: Some("application/pdf".to_string()),
+                file_uri: uri.to_string(),
+                video_metadata: None,
+            })
+        );
+        assert!(matches!(&parts[2], Part::FileData(f) if f.mime_type.is_none()));
+        assert!(parts![].unwrap().is_empty());
+        assert!(matches!(
+            parts!["Describe", youtube("https://example.com")],
+            Err(MediaError::InvalidYouTubeUrl(_))
+        ));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_content_macro() {
+        let path = std::env::temp_dir().join(format!("gemini-ox-macro-{}.png", std::process::id()));
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let content = content!["Describe this:", image(&path)].unwrap();
+
+        assert_eq!(content.role, Role::User);
+        assert_eq!(content.parts()[0], Part::from("Describe this:"));
+        assert!(
+            matches!(&content.parts()[1], Part::InlineData(blob) if blob.mime_type == "image/png")
+        );
+        assert!(matches!(
+            content!["Listen", audio("missing.mp3")],
+            Err(MediaError::Io(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+        let _: Content<'_> = content!["text only"].unwrap();
+    }
+}
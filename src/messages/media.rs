@@ -0,0 +1,414 @@
+//! Constructors for media parts, so multimodal prompts don't need hand-written base64 and
+//! MIME type boilerplate.
+
+use std::{path::Path, time::Duration};
+
+use thiserror::Error;
+
+use super::message::{Blob, FileData, Part, VideoMetadata};
+
+/// Image MIME types accepted as inline data.
+pub const SUPPORTED_IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "image/heic",
+    "image/heif",
+];
+
+/// Audio MIME types accepted as inline data.
+pub const SUPPORTED_AUDIO_TYPES: &[&str] = &[
+    "audio/wav",
+    "audio/mp3",
+    "audio/aiff",
+    "audio/aac",
+    "audio/ogg",
+    "audio/flac",
+];
+
+#[derive(Debug, Error)]
+pub enum MediaError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Unsupported MIME type: {0}")]
+    UnsupportedMimeType(String),
+    #[error("Could not detect the media type")]
+    UnknownFormat,
+    #[error("Not a YouTube video URL: {0}")]
+    InvalidYouTubeUrl(String),
+    #[error("Invalid base64 data: {0}")]
+    InvalidBase64(#[from] base64::DecodeError),
+    #[cfg(feature = "image")]
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+}
+
+impl Part<'static> {
+    /// Builds an inline image part, detecting the format from the data.
+    pub fn image_from_bytes(data: impl AsRef<[u8]>) -> Result<Self, MediaError> {
+        let data = data.as_ref();
+        let mime_type = sniff_image(data).ok_or(MediaError::UnknownFormat)?;
+        Ok(inline_data(mime_type, data))
+    }
+
+    /// Reads an image file into an inline part. The format is taken from the file extension,
+    /// or detected from the contents if the extension is missing or unknown.
+    pub fn image_from_path(path: impl AsRef<Path>) -> Result<Self, MediaError> {
+        from_path(
+            path.as_ref(),
+            image_type_from_extension,
+            sniff_image,
+            SUPPORTED_IMAGE_TYPES,
+        )
+    }
+
+    /// Builds an inline audio part (WAV, MP3, AIFF, AAC, OGG or FLAC), detecting the format
+    /// from the data.
+    pub fn audio_from_bytes(data: impl AsRef<[u8]>) -> Result<Self, MediaError> {
+        let data = data.as_ref();
+        let mime_type = sniff_audio(data).ok_or(MediaError::UnknownFormat)?;
+        Ok(inline_data(mime_type, data))
+    }
+
+    /// Reads an audio file into an inline part. The format is taken from the file extension,
+    /// or detected from the contents if the extension is missing or unknown.
+    pub fn audio_from_path(path: impl AsRef<Path>) -> Result<Self, MediaError> {
+        from_path(
+            path.as_ref(),
+            audio_type_from_extension,
+            sniff_audio,
+            SUPPORTED_AUDIO_TYPES,
+        )
+    }
+}
+
+impl Part<'static> {
+    /// References a public YouTube video, e.g. `https://www.youtube.com/watch?v=9hE5-98ZeCg`
+    /// or `https://youtu.be/9hE5-98ZeCg`.
+    pub fn youtube(url: &str) -> Result<Self, MediaError> {
+        let video_id =
+            youtube_video_id(url).ok_or_else(|| MediaError::InvalidYouTubeUrl(url.to_string()))?;
+        Ok(Part::FileData(FileData {
+            mime_type: None,
+            file_uri: format!("https://www.youtube.com/watch?v={video_id}"),
+            video_metadata: None,
+        }))
+    }
+
+    /// Like [`Part::youtube`], restricted to the clip between `start` and `end`.
+    pub fn youtube_clip(
+        url: &str,
+        start: Option<Duration>,
+        end: Option<Duration>,
+    ) -> Result<Self, MediaError> {
+        let mut part = Self::youtube(url)?;
+        if let Part::FileData(file_data) = &mut part {
+            file_data.video_metadata = Some(VideoMetadata {
+                start_offset: start.map(offset),
+                end_offset: end.map(offset),
+            });
+        }
+        Ok(part)
+    }
+}
+
+/// Formats an offset as a protobuf JSON duration.
+fn offset(duration: Duration) -> String {
+    if duration.subsec_nanos() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}s", duration.as_secs_f64())
+    }
+}
+
+fn youtube_video_id(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    let video_id = match url.host_str()? {
+        "youtu.be" => segments.next()?.to_string(),
+        "youtube.com" | "www.youtube.com" | "m.youtube.com" => match segments.next()? {
+            "watch" => url
+                .query_pairs()
+                .find_map(|(key, value)| (key == "v").then(|| value.into_owned()))?,
+            "shorts" | "embed" | "live" => segments.next()?.to_string(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let valid = video_id.len() == 11
+        && video_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(video_id)
+}
+
+#[cfg(feature = "image")]
+impl Part<'static> {
+    /// Encodes `image` as `format` (PNG, JPEG or WebP) into an inline part.
+    pub fn from_image(
+        image: &image::DynamicImage,
+        format: image::ImageFormat,
+    ) -> Result<Self, MediaError> {
+        let mime_type = format.to_mime_type();
+        if !SUPPORTED_IMAGE_TYPES.contains(&mime_type) {
+            return Err(MediaError::UnsupportedMimeType(mime_type.to_string()));
+        }
+        let mut data = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut data, format)?;
+        Ok(inline_data(mime_type, data.get_ref()))
+    }
+}
+
+fn from_path(
+    path: &Path,
+    type_from_extension: fn(&Path) -> Option<String>,
+    sniff: fn(&[u8]) -> Option<&'static str>,
+    supported: &[&str],
+) -> Result<Part<'static>, MediaError> {
+    let data = std::fs::read(path)?;
+    let mime_type = match type_from_extension(path) {
+        Some(mime_type) => mime_type,
+        None => sniff(&data).ok_or(MediaError::UnknownFormat)?.to_string(),
+    };
+    if !supported.contains(&mime_type.as_str()) {
+        return Err(MediaError::UnsupportedMimeType(mime_type));
+    }
+    Ok(inline_data(&mime_type, &data))
+}
+
+fn image_type_from_extension(path: &Path) -> Option<String> {
+    mime_guess::from_path(path)
+        .first()
+        .filter(|mime| mime.type_() == mime::IMAGE)
+        .map(|mime| mime.essence_str().to_string())
+}
+
+/// The API expects its own names for some audio types (e.g. `audio/mp3` rather than
+/// `audio/mpeg`), so they are not taken from `mime_guess`.
+pub(crate) fn audio_type_from_extension(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let mime_type = match extension.as_str() {
+        "wav" => "audio/wav",
+        "mp3" => "audio/mp3",
+        "aif" | "aiff" => "audio/aiff",
+        "aac" => "audio/aac",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "flac" => "audio/flac",
+        _ => {
+            return mime_guess::from_ext(&extension)
+                .first()
+                .filter(|mime| mime.type_() == mime::AUDIO)
+                .map(|mime| mime.essence_str().to_string())
+        }
+    };
+    Some(mime_type.to_string())
+}
+
+/// The MIME type of any media, e.g. for the Files API: taken from the extension of `path`,
+/// or detected from the contents if there is none or it is unknown.
+pub(crate) fn detect_mime_type(path: Option<&Path>, data: &[u8]) -> Option<String> {
+    path.and_then(|path| {
+        audio_type_from_extension(path).or_else(|| {
+            mime_guess::from_path(path)
+                .first()
+                .map(|mime| mime.essence_str().to_string())
+        })
+    })
+    .or_else(|| {
+        sniff_image(data)
+            .or_else(|| sniff_audio(data))
+            .map(String::from)
+    })
+}
+
+fn inline_data(mime_type: &str, data: &[u8]) -> Part<'static> {
+    Part::InlineData(Blob::new(mime_type, data))
+}
+
+/// Detects supported image formats by their magic bytes.
+fn sniff_image(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if brand.starts_with(b"heic") => {
+            Some("image/heic")
+        }
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..]
+            if brand.starts_with(b"mif1") || brand.starts_with(b"heif") =>
+        {
+            Some("image/heif")
+        }
+        _ => None,
+    }
+}
+
+/// Detects supported audio formats by their magic bytes.
+pub(crate) fn sniff_audio(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("audio/wav"),
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', ..] => Some("audio/aiff"),
+        [b'f', b'L', b'a', b'C', ..] => Some("audio/flac"),
+        [b'O', b'g', b'g', b'S', ..] => Some("audio/ogg"),
+        [b'I', b'D', b'3', ..] => Some("audio/mp3"),
+        // ADTS frame sync with layer bits 00 is AAC, other layers are MPEG audio.
+        [0xFF, second, ..] if second & 0xF6 == 0xF0 => Some("audio/aac"),
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some("audio/mp3"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_image_from_bytes() {
+        let part = Part::image_from_bytes(PNG).unwrap();
+        let blob = part.expect_inline_data();
+        assert_eq!(blob.mime_type, "image/png");
+        assert_eq!(blob.data, "iVBORw0KGgo=");
+
+        assert!(matches!(
+            Part::image_from_bytes(b"plain text"),
+            Err(MediaError::UnknownFormat)
+        ));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_image_from_path() {
+        let dir = std::env::temp_dir();
+        let png = dir.join(format!("gemini-ox-image-{}", std::process::id()));
+        std::fs::write(&png, PNG).unwrap();
+        let gif = dir.join(format!("gemini-ox-image-{}.gif", std::process::id()));
+        std::fs::write(&gif, b"GIF89a").unwrap();
+
+        let part = Part::image_from_path(&png).unwrap();
+        assert_eq!(part.expect_inline_data().mime_type, "image/png");
+        assert!(matches!(
+            Part::image_from_path(&gif),
+            Err(MediaError::UnsupportedMimeType(mime)) if mime == "image/gif"
+        ));
+
+        std::fs::remove_file(png).unwrap();
+        std::fs::remove_file(gif).unwrap();
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_from_image() {
+        let image = image::DynamicImage::new_rgb8(2, 2);
+
+        let part = Part::from_image(&image, image::ImageFormat::Png).unwrap();
+        let blob = part.expect_inline_data();
+        assert_eq!(blob.mime_type, "image/png");
+        assert!(blob.decode().unwrap().starts_with(PNG));
+
+        assert!(matches!(
+            Part::from_image(&image, image::ImageFormat::Bmp),
+            Err(MediaError::UnsupportedMimeType(mime)) if mime == "image/bmp"
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_youtube() {
+        for url in [
+            "https://www.youtube.com/watch?v=9hE5-98ZeCg&t=42",
+            "https://youtu.be/9hE5-98ZeCg",
+            "https://m.youtube.com/shorts/9hE5-98ZeCg",
+        ] {
+            let part = Part::youtube(url).unwrap();
+            assert_eq!(
+                part.expect_file_data().file_uri,
+                "https://www.youtube.com/watch?v=9hE5-98ZeCg"
+            );
+        }
+        for url in [
+            "https://vimeo.com/9hE5-98ZeCg",
+            "https://www.youtube.com/watch?v=short",
+            "https://www.youtube.com/channel/UC123",
+            "not a url",
+        ] {
+            assert!(matches!(
+                Part::youtube(url),
+                Err(MediaError::InvalidYouTubeUrl(_))
+            ));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_youtube_clip_serialization() {
+        let part = Part::youtube_clip(
+            "https://youtu.be/9hE5-98ZeCg",
+            Some(Duration::from_secs(40)),
+            Some(Duration::from_millis(80_500)),
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&part).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "fileData": {
+                    "mimeType": null,
+                    "fileUri": "https://www.youtube.com/watch?v=9hE5-98ZeCg"
+                },
+                "videoMetadata": {"startOffset": "40s", "endOffset": "80.5s"}
+            })
+        );
+        assert_eq!(serde_json::from_value::<Part>(json).unwrap(), part);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_audio_from_bytes() {
+        let cases: [(&[u8], &str); 5] = [
+            (b"RIFF\x24\x00\x00\x00WAVEfmt ", "audio/wav"),
+            (b"ID3\x04\x00", "audio/mp3"),
+            (&[0xFF, 0xFB, 0x90, 0x00], "audio/mp3"),
+            (b"fLaC\x00\x00", "audio/flac"),
+            (b"OggS\x00\x02", "audio/ogg"),
+        ];
+        for (data, mime_type) in cases {
+            let part = Part::audio_from_bytes(data).unwrap();
+            assert_eq!(part.expect_inline_data().mime_type, mime_type);
+        }
+        assert!(matches!(
+            Part::audio_from_bytes(PNG),
+            Err(MediaError::UnknownFormat)
+        ));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_audio_from_path() {
+        let dir = std::env::temp_dir();
+        let mp3 = dir.join(format!("gemini-ox-audio-{}.MP3", std::process::id()));
+        std::fs::write(&mp3, b"ID3").unwrap();
+        let midi = dir.join(format!("gemini-ox-audio-{}.mid", std::process::id()));
+        std::fs::write(&midi, b"MThd").unwrap();
+
+        let part = Part::audio_from_path(&mp3).unwrap();
+        assert_eq!(part.expect_inline_data().mime_type, "audio/mp3");
+        assert!(matches!(
+            Part::audio_from_path(&midi),
+            Err(MediaError::UnsupportedMimeType(_))
+        ));
+
+        std::fs::remove_file(mp3).unwrap();
+        std::fs::remove_file(midi).unwrap();
+    }
+}
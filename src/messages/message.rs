@@ -1,9 +1,12 @@
 use std::{borrow::Cow, fmt};
 
-use serde::{Deserialize, Serialize};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 use typed_builder::TypedBuilder;
 
+use super::media::MediaError;
+
 /// Represents the role of a message sender in a conversation.
 ///
 /// This enum distinguishes between messages sent by the user and
@@ -13,6 +16,8 @@ use typed_builder::TypedBuilder;
 pub enum Role {
     User,
     Model,
+    /// Content meant for `system_instruction`; it is not a valid role for conversation turns.
+    System,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, TypedBuilder)]
@@ -26,6 +31,11 @@ pub struct Content<'a> {
 }
 
 impl<'a> Content<'a> {
+    /// Builds a system instruction, for `GenerateContentRequest::system_instruction`.
+    pub fn system(parts: impl IntoIterator<Item = impl Into<Part<'a>>>) -> Self {
+        Self::builder().role(Role::System).parts(parts).build()
+    }
+
     #[must_use]
     pub fn parts(&self) -> &Vec<Part<'a>> {
         &self.parts
@@ -76,6 +86,41 @@ impl<'a> Content<'a> {
         self.parts.iter()
     }
 
+    /// The text parts.
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        self.parts
+            .iter()
+            .filter_map(Part::as_text)
+            .map(|t| t.0.as_ref())
+    }
+
+    /// Concatenated text parts, skipping any other part.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.texts().collect()
+    }
+
+    /// The inline media parts.
+    pub fn inline_datas(&self) -> impl Iterator<Item = &Blob<'a>> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::InlineData(blob) => Some(blob),
+            _ => None,
+        })
+    }
+
+    pub fn function_calls(&self) -> impl Iterator<Item = &FunctionCall> {
+        self.parts.iter().filter_map(Part::as_function_call)
+    }
+
+    pub fn function_responses(&self) -> impl Iterator<Item = &FunctionResponse> {
+        self.parts.iter().filter_map(Part::as_function_response)
+    }
+
+    /// The parts referencing files uploaded with the Files API.
+    pub fn file_datas(&self) -> impl Iterator<Item = &FileData> {
+        self.parts.iter().filter_map(Part::as_file_data)
+    }
+
     #[must_use]
     pub fn to_owned(&self) -> Content<'static> {
         Content {
@@ -109,7 +154,7 @@ impl<'a> From<&'a str> for Content<'a> {
     }
 }
 
-impl<'a> From<String> for Content<'a> {
+impl From<String> for Content<'_> {
     fn from(value: String) -> Self {
         Content::builder()
             .role(Role::User)
@@ -118,6 +163,124 @@ impl<'a> From<String> for Content<'a> {
     }
 }
 
+impl<'a> From<&'a String> for Content<'a> {
+    fn from(value: &'a String) -> Self {
+        Content::from(value.as_str())
+    }
+}
+
+impl<'a> From<Part<'a>> for Content<'a> {
+    fn from(value: Part<'a>) -> Self {
+        Content::builder().role(Role::User).parts([value]).build()
+    }
+}
+
+impl<'a> From<Vec<Part<'a>>> for Content<'a> {
+    fn from(value: Vec<Part<'a>>) -> Self {
+        Content::builder().role(Role::User).parts(value).build()
+    }
+}
+
+/// The turns of a conversation, as taken by `GenerateContentRequest::contents`.
+///
+/// Converts from a single [`Content`] or prompt as well as from vectors and arrays of
+/// anything convertible to [`Content`], so both representations can be passed directly.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Contents(pub Vec<Content<'static>>);
+
+impl Contents {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Vec<Content<'static>> {
+        self.0
+    }
+}
+
+impl std::ops::Deref for Contents {
+    type Target = Vec<Content<'static>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Contents {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Content<'_>> for Contents {
+    fn from(content: Content<'_>) -> Self {
+        Self(vec![content.to_owned()])
+    }
+}
+
+impl From<&str> for Contents {
+    fn from(prompt: &str) -> Self {
+        Self::from(Content::from(prompt))
+    }
+}
+
+impl From<String> for Contents {
+    fn from(prompt: String) -> Self {
+        Self(vec![Content::from(prompt)])
+    }
+}
+
+impl<T: Into<Content<'static>>> From<Vec<T>> for Contents {
+    fn from(contents: Vec<T>) -> Self {
+        contents.into_iter().collect()
+    }
+}
+
+impl<T: Into<Content<'static>>, const N: usize> From<[T; N]> for Contents {
+    fn from(contents: [T; N]) -> Self {
+        contents.into_iter().collect()
+    }
+}
+
+impl From<Contents> for Vec<Content<'static>> {
+    fn from(contents: Contents) -> Self {
+        contents.0
+    }
+}
+
+impl<T: Into<Content<'static>>> FromIterator<T> for Contents {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Content<'static>>> Extend<T> for Contents {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(Into::into));
+    }
+}
+
+impl IntoIterator for Contents {
+    type Item = Content<'static>;
+    type IntoIter = std::vec::IntoIter<Content<'static>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'c> IntoIterator for &'c Contents {
+    type Item = &'c Content<'static>;
+    type IntoIter = std::slice::Iter<'c, Content<'static>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 impl<'a> Extend<Part<'a>> for Content<'a> {
     fn extend<T: IntoIterator<Item = Part<'a>>>(&mut self, iter: T) {
         self.parts_mut().extend(iter);
@@ -129,8 +292,7 @@ impl<'a> Extend<Part<'a>> for Content<'a> {
 /// A datatype containing media that is part of a multi-part Content message.
 /// A Part consists of data which has an associated datatype. A Part can only contain one of the accepted types in Part.data.
 /// A Part must have a fixed IANA MIME type identifying the type and subtype of the media if the inlineData field is filled with raw bytes.
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Part<'a> {
     /// Inline text.
     Text(Text<'a>),
@@ -144,10 +306,65 @@ pub enum Part<'a> {
     FileData(FileData),
 }
 
+/// On the wire a part is an object with one data field (`text`, `inlineData`, ...) next to
+/// optional per-part fields such as `videoMetadata`, which an externally tagged enum can't
+/// express.
+impl Serialize for Part<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Part::Text(text) => map.serialize_entry("text", text)?,
+            Part::InlineData(blob) => map.serialize_entry("inlineData", blob)?,
+            Part::FunctionCall(call) => map.serialize_entry("functionCall", call)?,
+            Part::FunctionResponse(response) => {
+                map.serialize_entry("functionResponse", response)?;
+            }
+            Part::FileData(file_data) => {
+                map.serialize_entry("fileData", file_data)?;
+                if let Some(video_metadata) = &file_data.video_metadata {
+                    map.serialize_entry("videoMetadata", video_metadata)?;
+                }
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Part<'_> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct PartRepr<'a> {
+            text: Option<Text<'a>>,
+            inline_data: Option<Blob<'a>>,
+            function_call: Option<FunctionCall>,
+            function_response: Option<FunctionResponse>,
+            file_data: Option<FileData>,
+            video_metadata: Option<VideoMetadata>,
+        }
+
+        let repr = PartRepr::deserialize(deserializer)?;
+        if let Some(text) = repr.text {
+            Ok(Part::Text(text))
+        } else if let Some(blob) = repr.inline_data {
+            Ok(Part::InlineData(blob))
+        } else if let Some(call) = repr.function_call {
+            Ok(Part::FunctionCall(call))
+        } else if let Some(response) = repr.function_response {
+            Ok(Part::FunctionResponse(response))
+        } else if let Some(mut file_data) = repr.file_data {
+            file_data.video_metadata = repr.video_metadata;
+            Ok(Part::FileData(file_data))
+        } else {
+            Err(de::Error::custom("part has no data field"))
+        }
+    }
+}
+
 impl<'a> Part<'a> {
     /// If the `Part` is a `Text` variant, return `Some(Text)`, otherwise return `None`.
     #[must_use]
-    pub fn as_text(&self) -> Option<&Text> {
+    pub fn as_text(&self) -> Option<&Text<'a>> {
         match self {
             Part::Text(text) => Some(text),
             _ => None,
@@ -155,19 +372,19 @@ impl<'a> Part<'a> {
     }
 
     #[must_use]
-    pub fn expect_text(&self) -> &Text {
+    pub fn expect_text(&self) -> &Text<'a> {
         self.as_text().expect("Expected Part to be Text")
     }
     /// If the `Part` is a `InlineData` variant, return `Some(InlineData)`, otherwise return `None`.
     #[must_use]
-    pub fn as_inline_data(&self) -> Option<&Blob> {
+    pub fn as_inline_data(&self) -> Option<&Blob<'a>> {
         match self {
             Part::InlineData(inline_data) => Some(inline_data),
             _ => None,
         }
     }
     #[must_use]
-    pub fn expect_inline_data(&self) -> &Blob {
+    pub fn expect_inline_data(&self) -> &Blob<'a> {
         self.as_inline_data()
             .expect("Expected Part to be InlineData")
     }
@@ -225,7 +442,7 @@ impl<'a> Part<'a> {
     }
 }
 
-impl<'a> From<String> for Part<'a> {
+impl From<String> for Part<'_> {
     fn from(value: String) -> Self {
         Part::Text(value.into())
     }
@@ -237,6 +454,24 @@ impl<'a> From<&'a str> for Part<'a> {
     }
 }
 
+impl<'a> From<&'a String> for Part<'a> {
+    fn from(value: &'a String) -> Self {
+        Part::Text(value.as_str().into())
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for Part<'a> {
+    fn from(value: Cow<'a, str>) -> Self {
+        Part::Text(value.into())
+    }
+}
+
+impl From<fmt::Arguments<'_>> for Part<'_> {
+    fn from(value: fmt::Arguments<'_>) -> Self {
+        Part::Text(value.into())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Text<'a>(pub Cow<'a, str>);
 
@@ -246,13 +481,37 @@ impl<'a> From<&'a str> for Text<'a> {
     }
 }
 
-impl<'a> From<String> for Text<'a> {
+impl From<String> for Text<'_> {
     fn from(value: String) -> Self {
         Self(Cow::Owned(value))
     }
 }
 
-impl<'a> fmt::Display for Text<'a> {
+impl<'a> From<&'a String> for Text<'a> {
+    fn from(value: &'a String) -> Self {
+        Self(Cow::Borrowed(value))
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for Text<'a> {
+    fn from(value: Cow<'a, str>) -> Self {
+        Self(value)
+    }
+}
+
+/// Formats the arguments, e.g. `Text::from(format_args!("Translate {word}"))`, borrowing
+/// them instead if they are a plain string literal.
+impl From<fmt::Arguments<'_>> for Text<'_> {
+    fn from(value: fmt::Arguments<'_>) -> Self {
+        Self(
+            value
+                .as_str()
+                .map_or_else(|| Cow::Owned(value.to_string()), Cow::Borrowed),
+        )
+    }
+}
+
+impl fmt::Display for Text<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
     }
@@ -270,19 +529,19 @@ impl<'a> From<Blob<'a>> for Part<'a> {
     }
 }
 
-impl<'a> From<FunctionCall> for Part<'a> {
+impl From<FunctionCall> for Part<'_> {
     fn from(function_call: FunctionCall) -> Self {
         Self::FunctionCall(function_call)
     }
 }
 
-impl<'a> From<FunctionResponse> for Part<'a> {
+impl From<FunctionResponse> for Part<'_> {
     fn from(function_response: FunctionResponse) -> Self {
         Self::FunctionResponse(function_response)
     }
 }
 
-impl<'a> From<FileData> for Part<'a> {
+impl From<FileData> for Part<'_> {
     fn from(file_data: FileData) -> Self {
         Self::FileData(file_data)
     }
@@ -304,6 +563,23 @@ pub struct Blob<'a> {
     pub data: Cow<'a, str>,
 }
 
+impl Blob<'static> {
+    /// Base64-encodes `data`.
+    pub fn new(mime_type: impl Into<String>, data: impl AsRef<[u8]>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            data: Cow::Owned(STANDARD.encode(data)),
+        }
+    }
+}
+
+impl Blob<'_> {
+    /// The raw bytes, e.g. of an image or audio returned by the model.
+    pub fn decode(&self) -> Result<Vec<u8>, MediaError> {
+        Ok(STANDARD.decode(self.data.as_bytes())?)
+    }
+}
+
 /// FunctionCall
 ///
 /// A predicted FunctionCall returned from the model that contains a string representing the FunctionDeclaration.name with the arguments and their values.
@@ -338,6 +614,23 @@ pub struct FileData {
     pub mime_type: Option<String>,
     /// Required. URI.
     pub file_uri: String,
+    /// Optional. Clip of a video to use; sent as the part's `videoMetadata`.
+    #[serde(skip)]
+    pub video_metadata: Option<VideoMetadata>,
+}
+
+/// VideoMetadata
+///
+/// Metadata describing the input video content.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoMetadata {
+    /// Optional. The start offset of the video, e.g. `"10s"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_offset: Option<String>,
+    /// Optional. The end offset of the video, e.g. `"20s"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_offset: Option<String>,
 }
 
 #[cfg(test)]
@@ -509,4 +802,96 @@ mod tests {
         let result: Content = serde_json::from_value(input).unwrap();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_content_from_runtime_strings() {
+        let name = String::from("Ada");
+        let borrowed = Content::from(&name);
+        let owned = Content::from(format!("Hello, {name}"));
+        assert_eq!(borrowed.parts()[0].expect_text().to_string(), "Ada");
+        assert_eq!(owned.parts()[0].expect_text().to_string(), "Hello, Ada");
+        assert_eq!(borrowed.role, Role::User);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_text_from_borrowed_and_formatted() {
+        let word = String::from("cat");
+        let prompt = Cow::from(word.as_str());
+
+        assert_eq!(Part::from(prompt), Part::from("cat"));
+        assert_eq!(Text::from(&word).0, "cat");
+        assert_eq!(
+            Part::from(format_args!("Translate {word}")),
+            Part::from("Translate cat")
+        );
+        assert!(matches!(
+            Text::from(format_args!("Hi")).0,
+            Cow::Borrowed("Hi")
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_content_part_iterators() {
+        let content = Content::from(vec![
+            Part::from("Here is "),
+            Part::from(Blob::new("image/png", b"png")),
+            Part::from(FunctionCall {
+                name: "get_weather".to_string(),
+                args: Some(json!({"city": "Paris"})),
+            }),
+            Part::from("the weather."),
+            Part::from(FileData {
+                mime_type: None,
+                file_uri: "files/abc".to_string(),
+                video_metadata: None,
+            }),
+        ]);
+
+        assert_eq!(
+            content.texts().collect::<Vec<_>>(),
+            ["Here is ", "the weather."]
+        );
+        assert_eq!(content.text(), "Here is the weather.");
+        assert_eq!(
+            content.inline_datas().next().unwrap().mime_type,
+            "image/png"
+        );
+        assert_eq!(content.function_calls().next().unwrap().name, "get_weather");
+        assert_eq!(content.function_responses().count(), 0);
+        assert_eq!(content.file_datas().next().unwrap().file_uri, "files/abc");
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_content_from_parts() {
+        let image = Part::from(Blob::new("image/png", b"png"));
+        let single = Content::from(image.clone());
+        let mixed = Content::from(vec![Part::from("Describe"), image]);
+
+        assert_eq!(single.role, Role::User);
+        assert_eq!(single.parts().len(), 1);
+        assert_eq!(mixed.role, Role::User);
+        assert_eq!(mixed.parts()[0], Part::from("Describe"));
+        assert_eq!(mixed.parts().len(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_blob_round_trip() {
+        let blob = Blob::new("audio/wav", b"RIFF");
+        assert_eq!(blob.data, "UklGRg==");
+        assert_eq!(blob.decode().unwrap(), b"RIFF");
+
+        let invalid = Blob {
+            mime_type: "audio/wav".to_string(),
+            data: "not base64!".into(),
+        };
+        assert!(matches!(
+            invalid.decode(),
+            Err(MediaError::InvalidBase64(_))
+        ));
+    }
 }
@@ -1,195 +1,954 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use futures::{Stream, StreamExt};
-use message::{Content, FunctionCall, Part};
-use serde::{Deserialize, Serialize};
+use message::{Content, Contents, FunctionCall, Part};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
-use tools::ToolBox;
+use tools::{ToolBox, ToolConfig};
 use typed_builder::TypedBuilder;
 
-use crate::{ApiRequestError, Gemini, GenerationConfig, SafetyRating, SafetySettings, BASE_URL};
-
+#[cfg(feature = "tracing")]
+use crate::telemetry;
+use crate::{
+    models::{CapabilityError, ModelInfo, ModelResource},
+    observer::{Operation, RequestInfo},
+    response_cache::cache_key,
+    tokens::{CountTokensResponse, HeuristicEstimator, TokenEstimator},
+    with_timeout, ApiRequestError, Gemini, GenerationConfig, GenerationConfigError, SafetyRating,
+    SafetySettings,
+};
+
+pub mod chat;
+pub mod layout;
+mod macros;
+pub mod media;
 pub mod message;
+mod sse;
+pub mod stream;
 pub mod tools;
-
-#[derive(Debug, Serialize, TypedBuilder)]
-pub struct GenerateContentRequest<'a, 'b> {
-    #[builder(default, setter(transform = |v: impl IntoIterator<Item = impl Into<Content<'a>>>|
-        v.into_iter().map(Into::into).collect::<Vec<_>>()
-    ))]
-    contents: Vec<Content<'a>>,
+pub mod transcript;
+
+#[derive(Debug, Clone, Serialize, TypedBuilder)]
+pub struct GenerateContentRequest {
+    #[builder(
+        via_mutators,
+        mutators(
+            /// Replaces the contents; accepts [`Contents`], a single [`Content`] or prompt, or
+            /// a vector or array of them.
+            pub fn contents(&mut self, contents: impl Into<Contents>) {
+                self.contents = contents.into().into_inner();
+            }
+            /// Appends to the contents instead of replacing them, so a conversation can be built
+            /// up turn by turn.
+            pub fn content(&mut self, content: impl Into<Contents>) {
+                self.contents.extend(content.into());
+            }
+        )
+    )]
+    contents: Vec<Content<'static>>,
     #[builder(default)]
     #[serde(skip_serializing_if = "ToolBox::is_empty")]
     tools: ToolBox,
-    #[builder(default)]
+    #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    safety_settings: Option<SafetySettings>,
+    tool_config: Option<ToolConfig>,
     #[builder(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
-    system_instruction: Option<Content<'b>>,
+    safety_settings: Option<SafetySettings>,
+    /// Accepts any number of parts, e.g. a text together with a file reference, or a
+    /// [`Content`] whose parts are used as is.
+    #[builder(default, setter(transform = |parts: impl IntoIterator<Item = impl Into<Part<'static>>>|
+        Some(Content::system(parts))
+    ))]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_system_instruction"
+    )]
+    system_instruction: Option<Content<'static>>,
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     generation_config: Option<GenerationConfig>,
+    /// Name of a cached content (`cachedContents/*`) to use as the prompt prefix, see
+    /// [`Gemini::create_cached_content`].
+    #[builder(default, setter(into))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_content: Option<String>,
     #[builder(setter(into))]
     model: String,
+    /// Bounds the whole call, independent of any client-level timeout.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip)]
+    timeout: Option<Duration>,
+    /// How often [`GenerateContentRequest::stream`] re-sends the request after the stream
+    /// breaks off, skipping the text already yielded. Resuming relies on the model repeating
+    /// itself, so it works best with a low temperature.
+    #[builder(default)]
+    #[serde(skip)]
+    stream_resumes: u32,
+    /// Checks the request against the model's [`ModelInfo`], fetched once per client, before
+    /// sending it, see [`GenerateContentRequest::check_capabilities`]. Applies to async
+    /// requests only.
+    #[builder(default)]
+    #[serde(skip)]
+    validate_capabilities: bool,
+    #[builder(default, setter(skip))]
+    #[serde(skip)]
+    fallbacks: Vec<String>,
     #[serde(skip)]
     gemini: Gemini,
 }
 
+/// System instructions carry no role, so only their parts are sent.
+#[allow(clippy::ref_option)] // signature required by `serde(serialize_with)`
+pub(crate) fn serialize_system_instruction<S: serde::Serializer>(
+    content: &Option<Content<'_>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct SystemInstruction<'c, 'p> {
+        parts: &'c [Part<'p>],
+    }
+    content
+        .as_ref()
+        .map(|content| SystemInstruction {
+            parts: content.parts(),
+        })
+        .serialize(serializer)
+}
+
+/// Whether `part` is an image or video, which needs a model with vision support.
+fn is_visual(part: &Part<'_>) -> bool {
+    let mime_type = match part {
+        Part::InlineData(blob) => Some(blob.mime_type.as_str()),
+        Part::FileData(file_data) => file_data.mime_type.as_deref(),
+        _ => None,
+    };
+    mime_type
+        .is_some_and(|mime_type| mime_type.starts_with("image/") || mime_type.starts_with("video/"))
+}
+
+/// Whether another model may succeed where the requested one failed.
+fn should_fall_back(error: &ApiRequestError) -> bool {
+    match error {
+        ApiRequestError::RateLimit => true,
+        ApiRequestError::InvalidRequestError { status, .. } => matches!(
+            status.as_deref(),
+            Some("UNAVAILABLE" | "RESOURCE_EXHAUSTED" | "NOT_FOUND")
+        ),
+        ApiRequestError::HttpError { http } => matches!(http.status, 404 | 429 | 503),
+        _ => false,
+    }
+}
+
+/// Builder returned by [`Gemini::generate_content`].
+pub type GenerateContentBuilder = GenerateContentRequestBuilder<(
+    (Vec<Content<'static>>,),
+    (),
+    (),
+    (),
+    (),
+    (),
+    (),
+    (),
+    (),
+    (),
+    (),
+    (Gemini,),
+)>;
+
 impl Gemini {
-    pub fn generate_content(
-        &self,
-    ) -> GenerateContentRequestBuilder<'_, '_, ((), (), (), (), (), (), (Gemini,))> {
+    pub fn generate_content(&self) -> GenerateContentBuilder {
         GenerateContentRequest::builder().gemini(self.clone())
     }
+
+    /// Sends `requests` with at most `concurrency` of them in flight, yielding the results in
+    /// input order. Every request goes through [`GenerateContentRequest::send`], so rate
+    /// limiting, observers and tracing apply as usual.
+    pub fn generate_many<'a>(
+        requests: impl IntoIterator<Item = GenerateContentRequest> + 'a,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<GenerateContentResponse, ApiRequestError>> + 'a {
+        futures::stream::iter(requests)
+            .map(|request| async move { request.send().await })
+            .buffered(concurrency.max(1))
+    }
 }
 
-impl<'a, 'b> GenerateContentRequest<'a, 'b> {
+impl GenerateContentRequest {
+    /// Sends the request, moving on to the next of the [fallback
+    /// models](GenerateContentRequest::with_fallbacks) while the model is rate limited,
+    /// overloaded or not found.
     pub async fn send(&self) -> Result<GenerateContentResponse, ApiRequestError> {
-        let url = format!(
-            "{}/{}/models/{}:generateContent?key={}",
-            BASE_URL, self.gemini.api_version, self.model, self.gemini.api_key
-        );
-        let res = self.gemini.client.post(&url).json(self).send().await?;
+        let mut result = self.send_once().await;
+        for model in &self.fallbacks {
+            match &result {
+                Err(e) if should_fall_back(e) => {}
+                _ => break,
+            }
+            let request = Self {
+                model: model.clone(),
+                fallbacks: Vec::new(),
+                ..self.clone()
+            };
+            result = request.send_once().await;
+        }
+        result
+    }
+
+    async fn send_once(&self) -> Result<GenerateContentResponse, ApiRequestError> {
+        let Some(cache) = &self.gemini.response_cache else {
+            return self.send_uncached().await;
+        };
+        let key = cache_key(&self.model, &serde_json::to_string(self)?);
+        if let Some(response) = cache.get(&key).await {
+            return Ok(GenerateContentResponse {
+                served_by: Some(self.model.clone()),
+                ..response
+            });
+        }
+        let response = self.send_uncached().await?;
+        cache.put(&key, &response).await;
+        Ok(response)
+    }
+
+    async fn send_uncached(&self) -> Result<GenerateContentResponse, ApiRequestError> {
+        let info = RequestInfo {
+            operation: Operation::GenerateContent,
+            model: Some(&self.model),
+        };
+        let future = self.send_untraced();
+        #[cfg(feature = "tracing")]
+        let span = telemetry::generate_content_span("generate_content", &self.model);
+        #[cfg(feature = "tracing")]
+        let future = telemetry::traced(span.clone(), future);
+
+        let response = self.gemini.observed(info, future).await?;
+        #[cfg(feature = "tracing")]
+        telemetry::record_response(&span, &response);
+        self.gemini
+            .observe_usage(&info, response.usage_metadata.as_ref());
+        Ok(GenerateContentResponse {
+            served_by: Some(self.model.clone()),
+            ..response
+        })
+    }
+
+    /// Models tried in order after the request's own model fails with a rate limit, an
+    /// overloaded model or a model that doesn't exist; see [`GenerateContentRequest::send`].
+    /// The model that answered is recorded in [`GenerateContentResponse::served_by`].
+    #[must_use]
+    pub fn with_fallbacks(mut self, models: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.fallbacks = models.into_iter().map(Into::into).collect();
+        self
+    }
+
+    async fn send_untraced(&self) -> Result<GenerateContentResponse, ApiRequestError> {
+        self.validate_config()?;
+        self.validate_against_model().await?;
+        #[cfg(feature = "leaky-bucket")]
+        let reservation = {
+            self.gemini.acquire_rate_limit(Some(&self.model)).await;
+            self.gemini.reserve_tokens(self.budget_estimate()).await
+        };
+        let url = self.gemini.model_url(&self.model, "generateContent");
+        let (request, api_key) = self
+            .gemini
+            .authorize_with_key(self.gemini.client.post(&url))
+            .await?;
+        let res = self
+            .gemini
+            .execute(with_timeout(request, self.timeout).json(self))
+            .await?;
 
         match res.status().as_u16() {
             200 | 201 => {
-                let data: GenerateContentResponse = res.json().await?;
+                let data: GenerateContentResponse = crate::read_json(res).await?;
+                #[cfg(feature = "leaky-bucket")]
+                reservation.settle(data.usage_metadata.as_ref());
                 Ok(data)
             }
-            429 => Err(ApiRequestError::RateLimit),
-            _ => {
-                let mut e: Value = res.json().await?;
-                Err(ApiRequestError::InvalidRequestError {
-                    code: e["error"]["code"].as_str().map(String::from),
-                    details: e["error"]["details"].take(),
-                    message: e["error"]["message"]
-                        .as_str()
-                        .map_or_else(|| "no message".to_string(), String::from),
-                    status: e["error"]["status"].as_str().map(String::from),
+            429 => {
+                self.gemini.report_rate_limit(api_key.as_deref());
+                Err(ApiRequestError::RateLimit)
+            }
+            _ => Err(ApiRequestError::from_response(res).await),
+        }
+    }
+
+    /// Sends the request to its own model and every model in `alternates` at once, returning
+    /// the first successful response together with the model that produced it. The requests
+    /// still in flight are dropped, which cancels them. Fails with the last error if every
+    /// model fails. The [fallbacks](GenerateContentRequest::with_fallbacks) are not tried;
+    /// list them among `alternates` to race them too.
+    pub async fn race(
+        &self,
+        alternates: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<(String, GenerateContentResponse), ApiRequestError> {
+        let requests = std::iter::once(self.model.clone())
+            .chain(alternates.into_iter().map(Into::into))
+            .map(|model| {
+                let request = Self {
+                    model,
+                    fallbacks: Vec::new(),
+                    ..self.clone()
+                };
+                Box::pin(async move {
+                    let response = request.send().await?;
+                    Ok::<_, ApiRequestError>((request.model, response))
                 })
+            });
+        futures::future::select_ok(requests)
+            .await
+            .map(|(winner, _)| winner)
+    }
+
+    /// Blocking variant of [`GenerateContentRequest::send`], for callers without an async
+    /// runtime.
+    #[cfg(feature = "blocking")]
+    pub fn send_blocking(&self) -> Result<GenerateContentResponse, ApiRequestError> {
+        self.validate_config()?;
+        #[cfg(feature = "leaky-bucket")]
+        let reservation = {
+            self.gemini.acquire_rate_limit_blocking(Some(&self.model));
+            self.gemini.reserve_tokens_blocking(self.budget_estimate())
+        };
+        let url = self.gemini.model_url(&self.model, "generateContent");
+        let (mut request, api_key) = self
+            .gemini
+            .authorize_blocking(self.gemini.blocking_client().post(&url))?;
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        let res = request.json(self).send()?;
+
+        match res.status().as_u16() {
+            200 | 201 => {
+                let data: GenerateContentResponse = crate::blocking::read_json(res)?;
+                #[cfg(feature = "leaky-bucket")]
+                reservation.settle(data.usage_metadata.as_ref());
+                Ok(data)
+            }
+            429 => {
+                self.gemini.report_rate_limit(api_key.as_deref());
+                Err(ApiRequestError::RateLimit)
             }
+            _ => Err(crate::blocking::error_from_response(res)),
+        }
+    }
+
+    /// Requests structured output matching `T` and parses the first candidate into it.
+    ///
+    /// The response schema and `application/json` MIME type are derived from `T`, replacing
+    /// any schema already present in the generation config for this call only; the request
+    /// itself is left unchanged.
+    pub async fn send_as<T: DeserializeOwned + JsonSchema>(&self) -> Result<T, ApiRequestError> {
+        let config = self.generation_config.clone().unwrap_or_default();
+        let request = Self {
+            generation_config: Some(config.response_schema_for::<T>()?),
+            ..self.clone()
+        };
+
+        let text = request.send_for_text().await?;
+        serde_json::from_str(&text)
+            .map_err(|source| ApiRequestError::ResponseParseError { text, source })
+    }
+
+    /// Constrains the output to one of the variants of the unit enum `T` (`text/x.enum`) and
+    /// parses the answer back into it. Like [`GenerateContentRequest::send_as`], this leaves
+    /// the request unchanged.
+    pub async fn send_enum<T: DeserializeOwned + JsonSchema>(&self) -> Result<T, ApiRequestError> {
+        let config = self.generation_config.clone().unwrap_or_default();
+        let request = Self {
+            generation_config: Some(config.response_enum_for::<T>()?),
+            ..self.clone()
+        };
+
+        let text = request.send_for_text().await?;
+        serde_json::from_value(Value::String(text.trim().to_string()))
+            .map_err(|source| ApiRequestError::ResponseParseError { text, source })
+    }
+
+    /// Sends the request and concatenates the text parts of the first candidate.
+    async fn send_for_text(&self) -> Result<String, ApiRequestError> {
+        let response = self.send().await?.error_for_block()?;
+        let text = response.text();
+        if text.is_empty() {
+            return Err(ApiRequestError::UnexpectedResponse {
+                response: serde_json::to_string(&response)?,
+            });
         }
+        Ok(text)
     }
 
     pub async fn stream(
         &self,
-    ) -> impl Stream<Item = Result<GenerateContentResponse<'static>, ApiRequestError>> {
-        let url = format!(
-            "{}/{}/models/{}:streamGenerateContent?alt=sse&key={}",
-            BASE_URL, self.gemini.api_version, self.model, self.gemini.api_key
-        );
-        let stream = self
-            .gemini
-            .client
-            .post(&url)
-            .json(self)
-            .send()
-            .await
-            .unwrap()
-            .bytes_stream();
-
-        stream.filter_map(|chunk| async move {
-            match chunk {
-                Ok(bytes) => {
-                    let data = String::from_utf8(bytes.to_vec()).unwrap();
-                    match data.as_str() {
-                        "" => None,
-                        s if s.starts_with("data: ") => {
-                            let json_data = s.trim_start_matches("data: ");
-                            Some(
-                                serde_json::from_str::<GenerateContentResponse>(json_data)
-                                    .map_err(ApiRequestError::SerdeError),
-                            )
+    ) -> impl Stream<Item = Result<GenerateContentResponse, ApiRequestError>> + '_ {
+        if self.stream_resumes == 0 {
+            self.stream_once().await.left_stream()
+        } else {
+            self.resumable_stream().right_stream()
+        }
+    }
+
+    /// Streams the response into `writer`, writing the text of the first candidate as it
+    /// arrives and flushing after every chunk. Returns the complete text, or the first
+    /// stream or write error.
+    #[cfg(feature = "tokio")]
+    pub async fn stream_to<W>(&self, writer: &mut W) -> Result<String, ApiRequestError>
+    where
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        use stream::GenerateContentStreamExt;
+        use tokio::io::AsyncWriteExt;
+
+        let mut text = String::new();
+        // Boxed, as the stream's state would otherwise make this future several KiB large.
+        let mut deltas = Box::pin(Box::pin(self.stream()).await.text_deltas());
+        while let Some(delta) = deltas.next().await {
+            let delta = delta?;
+            writer.write_all(delta.as_bytes()).await?;
+            writer.flush().await?;
+            text.push_str(&delta);
+        }
+        Ok(text)
+    }
+
+    /// Streams like [`GenerateContentRequest::stream`], but reads the response eagerly on
+    /// a background task, buffering up to `capacity` chunks. A slow consumer, e.g. one
+    /// playing back speech, then doesn't stall the connection into an idle disconnect. Once
+    /// the returned stream is dropped, the task stops at its next chunk.
+    ///
+    /// Must be called within a Tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn buffered_stream(
+        &self,
+        capacity: usize,
+    ) -> impl Stream<Item = Result<GenerateContentResponse, ApiRequestError>> + 'static {
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity.max(1));
+        let request = self.clone();
+        tokio::spawn(async move {
+            let mut stream = std::pin::pin!(request.stream().await);
+            while let Some(chunk) = stream.next().await {
+                if tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        })
+    }
+
+    /// Streams like [`GenerateContentRequest::stream_once`], re-sending the request when the
+    /// stream fails with a network or transient error. The replayed text of the first
+    /// candidate is compared against what was already yielded and only the new text is
+    /// passed on.
+    fn resumable_stream(
+        &self,
+    ) -> impl Stream<Item = Result<GenerateContentResponse, ApiRequestError>> + '_ {
+        struct State<'a, S> {
+            request: &'a GenerateContentRequest,
+            inner: Option<Pin<Box<S>>>,
+            resumes_left: u32,
+            /// Text of the first candidate yielded so far.
+            yielded: String,
+            /// Bytes of the first candidate's text received since the last (re)start.
+            replayed: usize,
+        }
+
+        let state: State<'_, _> = State {
+            request: self,
+            inner: None,
+            resumes_left: self.stream_resumes,
+            yielded: String::new(),
+            replayed: 0,
+        };
+        futures::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            loop {
+                let inner = match &mut state.inner {
+                    Some(inner) => inner,
+                    None => state
+                        .inner
+                        .insert(Box::pin(state.request.stream_once().await)),
+                };
+                let error = match inner.next().await {
+                    None => return None,
+                    Some(Err(e)) => e,
+                    Some(Ok(mut response)) => {
+                        let text = response.text();
+                        let start = state.replayed;
+                        state.replayed += text.len();
+                        // The part of the yielded text this chunk should repeat.
+                        let expected = state.yielded.as_bytes().get(start..).unwrap_or_default();
+                        if !expected.is_empty() && state.replayed <= state.yielded.len() {
+                            if expected.starts_with(text.as_bytes()) {
+                                continue;
+                            }
+                        } else if text.as_bytes().starts_with(expected) {
+                            let skip = expected.len();
+                            response.skip_text(skip);
+                            state.yielded.push_str(&text[skip..]);
+                            return Some((Ok(response), Some(state)));
                         }
-                        _ => Some(Err(ApiRequestError::InvalidEventData(data.to_string()))),
+                        let error = ApiRequestError::UnexpectedResponse {
+                            response: "resumed stream diverged from the text already received"
+                                .to_string(),
+                        };
+                        return Some((Err(error), None));
                     }
+                };
+                let resumable =
+                    matches!(error, ApiRequestError::ReqwestError(_)) || error.is_transient();
+                if !resumable || state.resumes_left == 0 {
+                    return Some((Err(error), None));
                 }
-                Err(e) => Some(Err(ApiRequestError::ReqwestError(e))),
+                state.resumes_left -= 1;
+                state.inner = None;
+                state.replayed = 0;
             }
         })
     }
 
-    pub fn add_content<T: Into<Content<'a>>>(&mut self, content: T) {
+    async fn stream_once(
+        &self,
+    ) -> impl Stream<Item = Result<GenerateContentResponse, ApiRequestError>> {
+        let info = RequestInfo {
+            operation: Operation::StreamGenerateContent,
+            model: Some(&self.model),
+        };
+        // Reserved before the stream is opened, so the reservation is refunded if opening fails.
+        #[cfg(feature = "leaky-bucket")]
+        let reservation = self.gemini.reserve_tokens(self.budget_estimate()).await;
+        let future = self.open_stream();
+        #[cfg(feature = "tracing")]
+        let span = telemetry::generate_content_span("stream_generate_content", &self.model);
+        #[cfg(feature = "tracing")]
+        let future = telemetry::traced(span.clone(), future);
+        let res = match self.gemini.observed(info, future).await {
+            Ok(res) => res,
+            Err(e) => return futures::stream::once(async { Err(e) }).left_stream(),
+        };
+
+        // Every chunk carries the usage so far, so only the last one is reported.
+        let last_usage = Arc::new(Mutex::new(None));
+        let gemini = self.gemini.clone();
+        let model = self.model.clone();
+        let report_usage = {
+            let last_usage = Arc::clone(&last_usage);
+            futures::stream::once(async move {
+                let usage = last_usage.lock().unwrap().take();
+                let info = RequestInfo {
+                    operation: Operation::StreamGenerateContent,
+                    model: Some(&model),
+                };
+                gemini.observe_usage(&info, usage.as_ref());
+                #[cfg(feature = "leaky-bucket")]
+                reservation.settle(usage.as_ref());
+                None
+            })
+        };
+
+        sse::events(res.bytes_stream())
+            .map(move |event| {
+                let response = serde_json::from_str::<GenerateContentResponse>(&event?)
+                    .map_err(ApiRequestError::SerdeError)?;
+                #[cfg(feature = "tracing")]
+                telemetry::record_response(&span, &response);
+                if let Some(usage) = &response.usage_metadata {
+                    *last_usage.lock().unwrap() = Some(usage.clone());
+                }
+                Ok(response)
+            })
+            .map(Some)
+            .chain(report_usage)
+            .filter_map(futures::future::ready)
+            .right_stream()
+    }
+
+    async fn open_stream(&self) -> Result<reqwest::Response, ApiRequestError> {
+        self.validate_config()?;
+        self.validate_against_model().await?;
+        #[cfg(feature = "leaky-bucket")]
+        self.gemini.acquire_rate_limit(Some(&self.model)).await;
+        let url = self
+            .gemini
+            .model_url(&self.model, "streamGenerateContent?alt=sse");
+        let (request, api_key) = self
+            .gemini
+            .authorize_with_key(self.gemini.client.post(&url))
+            .await?;
+        let res = self
+            .gemini
+            .execute(with_timeout(request, self.timeout).json(self))
+            .await?;
+
+        match res.status().as_u16() {
+            200 | 201 => Ok(res),
+            429 => {
+                self.gemini.report_rate_limit(api_key.as_deref());
+                Err(ApiRequestError::RateLimit)
+            }
+            _ => Err(ApiRequestError::from_response(res).await),
+        }
+    }
+
+    /// Checks the request against what `model` supports. The prompt size is estimated
+    /// offline, see [`GenerateContentRequest::estimate_tokens`].
+    pub fn check_capabilities(&self, model: &ModelInfo) -> Result<(), CapabilityError> {
+        let id = model.id();
+        if !model.supported_generation_methods.is_empty()
+            && !model.supports_method("generateContent")
+        {
+            return Err(CapabilityError::UnsupportedMethod {
+                model: id.to_string(),
+                method: "generateContent".to_string(),
+            });
+        }
+        if !self.tools.is_empty() && !model.supports_tools() {
+            return Err(CapabilityError::ToolsUnsupported(id.to_string()));
+        }
+        let has_media = self.contents.iter().flat_map(Content::iter).any(is_visual);
+        if has_media && !model.supports_vision() {
+            return Err(CapabilityError::VisionUnsupported(id.to_string()));
+        }
+        if let Some(requested) = self
+            .generation_config
+            .as_ref()
+            .and_then(|config| config.candidate_count)
+        {
+            let max = model.max_candidate_count();
+            if requested > max {
+                return Err(CapabilityError::UnsupportedCandidateCount {
+                    model: id.to_string(),
+                    requested,
+                    max,
+                });
+            }
+        }
+        let estimated = self.estimate_tokens();
+        if model.input_token_limit > 0 && estimated > model.input_token_limit {
+            return Err(CapabilityError::InputTokenLimitExceeded {
+                model: id.to_string(),
+                estimated,
+                limit: model.input_token_limit,
+            });
+        }
+        Ok(())
+    }
+
+    fn validate_config(&self) -> Result<(), GenerationConfigError> {
+        if let Some(config) = &self.generation_config {
+            config.validate()?;
+        }
+        let seeded = self
+            .generation_config
+            .as_ref()
+            .is_some_and(|config| config.seed.is_some());
+        if self.gemini.replayable && !seeded {
+            return Err(GenerationConfigError::MissingSeed);
+        }
+        Ok(())
+    }
+
+    async fn validate_against_model(&self) -> Result<(), ApiRequestError> {
+        if self.validate_capabilities {
+            let model = self.gemini.model_info(&self.model).await?;
+            self.check_capabilities(&model)?;
+        }
+        Ok(())
+    }
+
+    pub fn add_content<T: Into<Content<'static>>>(&mut self, content: T) {
         self.contents.push(content.into());
     }
+
+    /// Counts the prompt tokens with `countTokens`. The whole request is sent, so the
+    /// system instruction, tool declarations and media count exactly as they would for
+    /// [`GenerateContentRequest::send`].
+    pub async fn count_tokens(&self) -> Result<CountTokensResponse, ApiRequestError> {
+        let mut request = serde_json::to_value(self)?;
+        request["model"] = Value::String(ModelResource::parse(&self.model).to_string());
+        let mut body = serde_json::Map::new();
+        body.insert("generateContentRequest".to_string(), request);
+
+        let url = self.gemini.model_url(&self.model, "countTokens");
+        let res = self
+            .gemini
+            .send_checked(with_timeout(self.gemini.client.post(&url), self.timeout).json(&body))
+            .await?;
+        crate::read_json(res).await
+    }
+
+    /// Estimates the prompt size offline with [`HeuristicEstimator`], counting contents,
+    /// the system instruction and tool declarations.
+    #[must_use]
+    pub fn estimate_tokens(&self) -> u32 {
+        self.estimate_tokens_with(&HeuristicEstimator::default())
+    }
+
+    #[must_use]
+    pub fn estimate_tokens_with(&self, estimator: &impl TokenEstimator) -> u32 {
+        let tools = if self.tools.is_empty() {
+            0
+        } else {
+            serde_json::to_string(&self.tools).map_or(0, |json| estimator.estimate_text(&json))
+        };
+        estimator.estimate_contents(&self.contents)
+            + self
+                .system_instruction
+                .as_ref()
+                .map_or(0, |content| estimator.estimate_content(content))
+            + tools
+    }
+
+    /// Tokens reserved from the token budget: the prompt estimate plus the output limit.
+    #[cfg(feature = "leaky-bucket")]
+    fn budget_estimate(&self) -> u32 {
+        let max_output_tokens = self
+            .generation_config
+            .as_ref()
+            .and_then(|config| config.max_output_tokens)
+            .and_then(|tokens| u32::try_from(tokens).ok())
+            .unwrap_or_default();
+        self.estimate_tokens() + max_output_tokens
+    }
+
+    /// Restricts the request to the named tools: only their declarations are sent and
+    /// `allowedFunctionNames` is set in the tool config.
+    pub fn allowed_tools(
+        &mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> &mut Self {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        self.tools = self.tools.filter_by_names(&names);
+        self.tool_config = Some(
+            self.tool_config
+                .take()
+                .unwrap_or_default()
+                .allowed_function_names(names),
+        );
+        self
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
-pub struct GenerateContentResponse<'a> {
-    pub candidates: Vec<ResponseCandidate<'a>>,
+pub struct GenerateContentResponse {
+    pub candidates: Vec<ResponseCandidate>,
     pub prompt_feedback: Option<PromptFeedback>,
     pub usage_metadata: Option<UsageMetadata>,
+    /// Concrete model version that answered, e.g. `gemini-1.5-flash-002`.
+    pub model_version: Option<String>,
+    /// Identifies the response, e.g. in support requests.
+    pub response_id: Option<String>,
+    /// Model the response was requested from, set by [`GenerateContentRequest::send`].
+    /// Differs from the request's model when a fallback model answered.
+    #[serde(skip)]
+    pub served_by: Option<String>,
 }
 
-impl<'a> GenerateContentResponse<'a> {
+impl GenerateContentResponse {
+    /// Content of the first candidate.
     #[must_use]
-    pub fn content(&'a self) -> Option<&'a Content<'a>> {
+    pub fn content(&self) -> Option<&Content<'static>> {
         self.candidates.first().map(|c| &c.content)
     }
 
+    #[must_use]
+    pub fn first_candidate(&self) -> Option<&ResponseCandidate> {
+        self.candidates.first()
+    }
+
+    /// Whether the response has no candidates, e.g. because the prompt was blocked.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Share of the prompt tokens served from cached content, explicit or implicit; `None`
+    /// if the response carries no usage.
+    #[must_use]
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        self.usage_metadata
+            .as_ref()
+            .map(UsageMetadata::cache_hit_ratio)
+    }
+
+    /// Concatenated text parts of the first candidate; empty if there is none.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.first_candidate()
+            .map(ResponseCandidate::text)
+            .unwrap_or_default()
+    }
+
+    /// Drops the first `len` bytes of the first candidate's text, across its text parts.
+    fn skip_text(&mut self, mut len: usize) {
+        let Some(candidate) = self.candidates.first_mut() else {
+            return;
+        };
+        for part in candidate.content.parts_mut() {
+            if len == 0 {
+                break;
+            }
+            if let Part::Text(text) = part {
+                let cut = len.min(text.0.len());
+                text.0.to_mut().drain(..cut);
+                len -= cut;
+            }
+        }
+        candidate
+            .content
+            .parts_mut()
+            .retain(|part| !matches!(part, Part::Text(text) if text.0.is_empty()));
+    }
+
+    /// Why the first candidate stopped generating.
+    #[must_use]
+    pub fn finish_reason(&self) -> Option<FinishReason> {
+        self.first_candidate().map(|c| c.finish_reason)
+    }
+
+    /// Whether the first candidate was cut off by `max_output_tokens`.
+    #[must_use]
+    pub fn was_truncated(&self) -> bool {
+        self.finish_reason() == Some(FinishReason::MaxTokens)
+    }
+
+    /// All candidates, when more than one was requested with `candidate_count`.
+    pub fn candidates(&self) -> impl Iterator<Item = &ResponseCandidate> {
+        self.candidates.iter()
+    }
+
+    /// The candidate with the given `index`.
+    #[must_use]
+    pub fn candidate(&self, index: u32) -> Option<&ResponseCandidate> {
+        self.candidates.iter().find(|c| c.index == index)
+    }
+
+    /// Concatenated text parts of the candidate with the given `index`.
+    #[must_use]
+    pub fn nth_text(&self, index: u32) -> Option<String> {
+        self.candidate(index).map(ResponseCandidate::text)
+    }
+
+    /// Concatenated text parts of every candidate, in candidate order.
+    #[must_use]
+    pub fn texts(&self) -> Vec<String> {
+        self.candidates
+            .iter()
+            .map(ResponseCandidate::text)
+            .collect()
+    }
+
+    /// Function calls of the first candidate.
     #[must_use]
     pub fn get_function_calls(&self) -> Vec<&FunctionCall> {
-        self.content()
-            .map(|c| {
-                c.parts()
-                    .iter()
-                    .filter_map(|p| p.as_function_call())
-                    .collect()
-            })
+        self.candidates
+            .first()
+            .map(ResponseCandidate::function_calls)
             .unwrap_or_default()
     }
 
+    /// Invokes the function calls of the first candidate.
     #[must_use]
-    pub async fn invoke_functions(&'a self, tools: &ToolBox) -> Option<Content<'static>> {
-        let function_calls = self.get_function_calls();
-        if function_calls.is_empty() {
-            return None;
+    pub async fn invoke_functions(&self, tools: &ToolBox) -> Option<Content<'static>> {
+        match self.candidates.first() {
+            Some(candidate) => candidate.invoke_functions(tools).await,
+            None => None,
         }
+    }
 
-        let mut content = Content::builder().role(message::Role::User).build();
-        for fc in function_calls {
-            let result = tools.invoke(fc.clone()).await;
-            content.push(result);
+    /// Invokes the function calls of the candidate with the given `index`.
+    #[must_use]
+    pub async fn invoke_functions_at(
+        &self,
+        index: u32,
+        tools: &ToolBox,
+    ) -> Option<Content<'static>> {
+        self.candidate(index)?.invoke_functions(tools).await
+    }
+
+    /// Turns a blocked prompt, or a response whose candidates were all blocked, into
+    /// [`ApiRequestError::Blocked`], so it isn't mistaken for an empty answer.
+    pub fn error_for_block(self) -> Result<Self, ApiRequestError> {
+        if let Some(feedback) = &self.prompt_feedback {
+            if let Some(reason) = feedback.block_reason {
+                return Err(ApiRequestError::Blocked {
+                    reason: reason.to_string(),
+                    safety_ratings: feedback.safety_ratings.clone(),
+                });
+            }
+        }
+        match self.candidates.first() {
+            Some(candidate) if self.candidates.iter().all(|c| c.finish_reason.is_blocked()) => {
+                Err(ApiRequestError::Blocked {
+                    reason: candidate.finish_reason.to_string(),
+                    safety_ratings: candidate.safety_ratings.clone().unwrap_or_default(),
+                })
+            }
+            _ => Ok(self),
         }
+    }
 
-        (!content.is_empty()).then_some(content)
+    /// Content of the candidate with the given `index` as a model turn, e.g. to continue the
+    /// conversation with the candidate that was picked.
+    #[must_use]
+    pub fn into_content(self, index: u32) -> Option<Content<'static>> {
+        self.candidates
+            .into_iter()
+            .find(|c| c.index == index)
+            .map(|c| model_content(&c.content))
     }
 
-    pub fn to_owned(&self) -> GenerateContentResponse<'static> {
-        GenerateContentResponse {
-            candidates: self
-                .candidates
-                .iter()
-                .map(ResponseCandidate::to_owned)
-                .collect(),
-            prompt_feedback: self.prompt_feedback.clone(),
-            usage_metadata: self.usage_metadata.clone(),
-        }
+    #[deprecated(note = "responses no longer borrow, use `clone` instead")]
+    #[must_use]
+    pub fn to_owned(&self) -> Self {
+        self.clone()
     }
 }
 
-impl<'a> From<GenerateContentResponse<'a>> for Content<'static> {
-    fn from(value: GenerateContentResponse<'a>) -> Self {
-        let parts = value.candidates[0].content.parts().clone();
-        Content::builder()
-            .role(message::Role::Model)
-            .parts(parts)
-            .build()
-            .to_owned()
+/// Converts the first candidate; empty if there is none. Use
+/// [`GenerateContentResponse::into_content`] to pick another candidate.
+impl From<GenerateContentResponse> for Content<'static> {
+    fn from(value: GenerateContentResponse) -> Self {
+        value.candidates.first().map_or_else(
+            || Content::builder().role(message::Role::Model).build(),
+            |c| model_content(&c.content),
+        )
     }
 }
 
-impl<'a> From<GenerateContentResponse<'a>> for Vec<Part<'static>> {
-    fn from(value: GenerateContentResponse<'a>) -> Self {
-        value.candidates[0]
-            .content
-            .parts()
-            .iter()
-            .map(message::Part::to_owned)
-            .collect()
+/// Converts the parts of the first candidate; empty if there is none.
+impl From<GenerateContentResponse> for Vec<Part<'static>> {
+    fn from(value: GenerateContentResponse) -> Self {
+        value
+            .candidates
+            .first()
+            .map(|c| {
+                c.content
+                    .parts()
+                    .iter()
+                    .map(message::Part::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 }
 
+fn model_content(content: &Content<'_>) -> Content<'static> {
+    Content::builder()
+        .role(message::Role::Model)
+        .parts(content.parts().iter().map(message::Part::to_owned))
+        .build()
+}
+
 #[derive(
     Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::Display,
 )]
@@ -209,33 +968,76 @@ pub enum FinishReason {
     MalformedFunctionCall,
 }
 
+impl FinishReason {
+    /// Whether generation was stopped by a content filter rather than finishing normally.
+    #[must_use]
+    pub fn is_blocked(self) -> bool {
+        matches!(
+            self,
+            Self::Safety
+                | Self::Recitation
+                | Self::Blocklist
+                | Self::ProhibitedContent
+                | Self::Spii
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
-pub struct ResponseCandidate<'a> {
-    pub content: Content<'a>,
+pub struct ResponseCandidate {
+    pub content: Content<'static>,
     pub finish_reason: FinishReason,
     pub index: u32,
     pub safety_ratings: Option<Vec<SafetyRating>>,
 }
 
-impl<'a> ResponseCandidate<'a> {
+impl ResponseCandidate {
+    /// Concatenated text parts.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.content.text()
+    }
+
     #[must_use]
-    pub fn to_owned(&self) -> ResponseCandidate<'static> {
-        ResponseCandidate {
-            content: self.content.to_owned(),
-            finish_reason: self.finish_reason.clone(),
-            index: self.index,
-            safety_ratings: self.safety_ratings.clone(),
+    pub fn function_calls(&self) -> Vec<&FunctionCall> {
+        self.content.function_calls().collect()
+    }
+
+    #[must_use]
+    pub async fn invoke_functions(&self, tools: &ToolBox) -> Option<Content<'static>> {
+        let function_calls = self.function_calls();
+        if function_calls.is_empty() {
+            return None;
+        }
+
+        let mut content = Content::builder().role(message::Role::User).build();
+        for fc in function_calls {
+            let result = tools.invoke(fc.clone()).await;
+            content.push(result);
         }
+
+        (!content.is_empty()).then_some(content)
+    }
+
+    #[deprecated(note = "candidates no longer borrow, use `clone` instead")]
+    #[must_use]
+    pub fn to_owned(&self) -> Self {
+        self.clone()
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::Display,
+)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum BlockReason {
     BlockReasonUnspecified,
     Safety,
     Other,
+    Blocklist,
+    ProhibitedContent,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -249,10 +1051,34 @@ pub struct PromptFeedback {
 #[serde(rename_all = "camelCase")]
 pub struct UsageMetadata {
     pub prompt_token_count: u32,
+    /// Number of prompt tokens served from cached content.
+    pub cached_content_token_count: Option<u32>,
     pub candidates_token_count: Option<u32>,
+    /// Number of tokens spent on thinking, for thinking models.
+    pub thoughts_token_count: Option<u32>,
     pub total_token_count: u32,
 }
 
+impl UsageMetadata {
+    /// Share of the prompt tokens served from cached content, between 0 and 1.
+    #[must_use]
+    pub fn cache_hit_ratio(&self) -> f64 {
+        cache_hit_ratio(
+            u64::from(self.cached_content_token_count.unwrap_or_default()),
+            u64::from(self.prompt_token_count),
+        )
+    }
+}
+
+#[allow(clippy::cast_precision_loss)] // token counts are far below 2^52
+pub(crate) fn cache_hit_ratio(cached_tokens: u64, prompt_tokens: u64) -> f64 {
+    if prompt_tokens == 0 {
+        0.0
+    } else {
+        cached_tokens as f64 / prompt_tokens as f64
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::{atomic::AtomicBool, Arc};
@@ -298,31 +1124,14 @@ mod tests {
 
         pin_mut!(stream);
 
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            let mut responses = Vec::new();
-            while let Some(Ok(item)) = stream.next().await {
-                let content = item.candidates[0].content.parts()[0].clone();
-                if let Some(text) = content.as_text() {
-                    responses.push(text.to_string());
-                }
+        let mut responses = Vec::new();
+        while let Some(Ok(item)) = stream.next().await {
+            let content = &item.candidates[0].content.parts()[0];
+            if let Some(text) = content.as_text() {
+                responses.push(text.to_string());
             }
-            assert!(!responses.is_empty());
-        }
-
-        #[cfg(target_arch = "wasm32")]
-        {
-            wasm_bindgen_futures::spawn_local(async move {
-                let mut responses = Vec::new();
-                while let Some(Ok(response)) = stream.next().await {
-                    let content = &response.candidates[0].content.parts()[0];
-                    if let Some(text) = content.as_text() {
-                        responses.push(text.to_string());
-                    }
-                }
-                assert!(!responses.is_empty());
-            });
         }
+        assert!(!responses.is_empty());
     }
 
     #[derive(Clone)]
@@ -369,6 +1178,701 @@ mod tests {
         }
     }
 
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_count_tokens_sends_whole_request() {
+        let transport = crate::transport::tests::MockTransport::json(
+            200,
+            &json!({
+                "totalTokens": 320,
+                "promptTokensDetails": [
+                    {"modality": "TEXT", "tokenCount": 62},
+                    {"modality": "IMAGE", "tokenCount": 258}
+                ]
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let tools = ToolBox::default();
+        tools.add(ToolOne);
+        let request = gemini
+            .generate_content()
+            .contents(Content::from(vec![
+                Part::from("What is in this picture?"),
+                Part::from(message::Blob::new("image/png", b"png")),
+            ]))
+            .system_instruction(["Answer briefly."])
+            .tools(tools)
+            .model("gemini-1.5-flash")
+            .build();
+
+        let count = request.count_tokens().await.unwrap();
+
+        assert_eq!(count.total_tokens, 320);
+        assert_eq!(count.prompt_tokens_details[1].token_count, 258);
+        let requests = transport.requests.lock().unwrap();
+        assert!(requests[0]
+            .url()
+            .path()
+            .ends_with("/models/gemini-1.5-flash:countTokens"));
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        let sent = &body["generateContentRequest"];
+        assert_eq!(sent["model"], "models/gemini-1.5-flash");
+        assert_eq!(
+            sent["contents"][0]["parts"][1]["inlineData"]["mimeType"],
+            "image/png"
+        );
+        assert!(sent["system_instruction"].is_object());
+        assert_eq!(
+            sent["tools"][0]["function_declarations"][0]["name"],
+            "tool_one"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_allowed_tools() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let tools = ToolBox::default();
+        tools.add(ToolOne);
+        tools.add(ToolTwo);
+
+        let mut request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-flash")
+            .tools(tools.clone())
+            .build();
+        request.allowed_tools(["tool_one"]);
+
+        assert_eq!(tools.len(), 2);
+        let json = serde_json::to_value(&request).unwrap();
+        let declarations = json["tools"][0]["function_declarations"]
+            .as_array()
+            .unwrap();
+        assert_eq!(declarations.len(), 1);
+        assert_eq!(declarations[0]["name"], "tool_one");
+        assert_eq!(
+            json["tool_config"],
+            json!({
+                "functionCallingConfig": {
+                    "mode": "ANY",
+                    "allowedFunctionNames": ["tool_one"]
+                }
+            })
+        );
+    }
+
+    /// Echoes the prompt back, yielding first so that concurrent requests overlap.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[derive(Default)]
+    struct EchoTransport {
+        in_flight: std::sync::atomic::AtomicUsize,
+        max_in_flight: std::sync::atomic::AtomicUsize,
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_trait]
+    impl crate::transport::Transport for Arc<EchoTransport> {
+        async fn execute(
+            &self,
+            request: reqwest::Request,
+        ) -> Result<reqwest::Response, ApiRequestError> {
+            use std::sync::atomic::Ordering;
+
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            let body: Value =
+                serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+            let text = body["contents"][0]["parts"][0]["text"].clone();
+            for _ in 0..text.as_str().unwrap().len() {
+                tokio::task::yield_now().await;
+            }
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            let response = json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": text}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }]
+            });
+            Ok(http::Response::new(response.to_string()).into())
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_generate_many() {
+        let transport = Arc::new(EchoTransport::default());
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(Arc::clone(&transport))
+            .build();
+        let prompts = ["long prompt", "short", "a", "medium one", "xy"];
+        let requests = prompts.iter().map(|prompt| {
+            gemini
+                .generate_content()
+                .contents(vec![*prompt])
+                .model("gemini-1.5-flash")
+                .build()
+        });
+
+        let responses: Vec<_> = Gemini::generate_many(requests, 2).collect().await;
+
+        let texts: Vec<String> = responses
+            .into_iter()
+            .map(|res| {
+                res.unwrap().content().unwrap().parts()[0]
+                    .expect_text()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(texts, prompts);
+        assert_eq!(
+            transport
+                .max_in_flight
+                .load(std::sync::atomic::Ordering::SeqCst),
+            2
+        );
+    }
+
+    /// Answers as the model in the request path, after yielding `delay` times per model so
+    /// that concurrent requests overlap; models without a delay fail with `UNAVAILABLE`.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[derive(Default)]
+    struct ModelsTransport {
+        delays: std::collections::HashMap<&'static str, usize>,
+        requested: Mutex<Vec<String>>,
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_trait]
+    impl crate::transport::Transport for Arc<ModelsTransport> {
+        async fn execute(
+            &self,
+            request: reqwest::Request,
+        ) -> Result<reqwest::Response, ApiRequestError> {
+            let path = request.url().path();
+            let model = path
+                .strip_prefix("/v1beta/models/")
+                .and_then(|rest| rest.split(':').next())
+                .unwrap()
+                .to_string();
+            self.requested.lock().unwrap().push(model.clone());
+            let Some(&delay) = self.delays.get(model.as_str()) else {
+                let error = json!({"error": {"code": 503, "message": "overloaded", "status": "UNAVAILABLE"}});
+                return Ok(http::Response::builder()
+                    .status(503)
+                    .body(error.to_string())
+                    .unwrap()
+                    .into());
+            };
+            for _ in 0..delay {
+                tokio::task::yield_now().await;
+            }
+            let response = json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": model}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }]
+            });
+            Ok(http::Response::new(response.to_string()).into())
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_race() {
+        let transport = Arc::new(ModelsTransport {
+            delays: [("gemini-1.5-pro", 50), ("gemini-1.5-flash", 1)].into(),
+            ..ModelsTransport::default()
+        });
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(Arc::clone(&transport))
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents("hello")
+            .model("gemini-1.5-pro")
+            .build();
+
+        let (model, response) = request
+            .race(["gemini-1.5-flash", "gemini-1.0-pro"])
+            .await
+            .unwrap();
+        assert_eq!(model, "gemini-1.5-flash");
+        assert_eq!(response.text(), "gemini-1.5-flash");
+        assert_eq!(transport.requested.lock().unwrap().len(), 3);
+
+        // A failing model doesn't end the race while another may still succeed.
+        let (model, _) = request.race(["gemini-1.0-pro"]).await.unwrap();
+        assert_eq!(model, "gemini-1.5-pro");
+
+        let err = gemini
+            .generate_content()
+            .contents("hello")
+            .model("gemini-1.0-pro")
+            .build()
+            .race(["gemini-1.0-ultra"])
+            .await
+            .unwrap_err();
+        assert!(err.is_transient());
+
+        // Racers don't walk the fallbacks, which would misreport the winning model.
+        transport.requested.lock().unwrap().clear();
+        let err = gemini
+            .generate_content()
+            .contents("hello")
+            .model("gemini-1.0-pro")
+            .build()
+            .with_fallbacks(["gemini-1.5-flash"])
+            .race(["gemini-1.0-ultra"])
+            .await
+            .unwrap_err();
+        assert!(err.is_transient());
+        assert_eq!(transport.requested.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_fallbacks() {
+        let transport = Arc::new(ModelsTransport {
+            delays: [("gemini-2.0-flash", 0)].into(),
+            ..ModelsTransport::default()
+        });
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(Arc::clone(&transport))
+            .build();
+
+        let response = gemini
+            .generate_content()
+            .contents("hello")
+            .model("gemini-2.5-pro")
+            .build()
+            .with_fallbacks(["gemini-2.5-flash", "gemini-2.0-flash", "gemini-1.5-flash"])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.served_by.as_deref(), Some("gemini-2.0-flash"));
+        assert_eq!(
+            *transport.requested.lock().unwrap(),
+            ["gemini-2.5-pro", "gemini-2.5-flash", "gemini-2.0-flash"]
+        );
+    }
+
+    /// Breaks the first stream off after "Hello, " and serves the whole answer afterwards.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[derive(Default)]
+    struct FlakyStreamTransport {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_trait]
+    impl crate::transport::Transport for Arc<FlakyStreamTransport> {
+        async fn execute(
+            &self,
+            _request: reqwest::Request,
+        ) -> Result<reqwest::Response, ApiRequestError> {
+            fn event(text: &str, finish_reason: &str) -> String {
+                let response = json!({
+                    "candidates": [{
+                        "content": {"role": "model", "parts": [{"text": text}]},
+                        "finishReason": finish_reason,
+                        "index": 0
+                    }]
+                });
+                format!("data: {response}\n\n")
+            }
+
+            let call = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let events = if call == 0 {
+                vec![
+                    Ok(event("Hello, ", "FINISH_REASON_UNSPECIFIED")),
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionReset,
+                        "connection reset",
+                    )),
+                ]
+            } else {
+                vec![
+                    Ok(event("Hel", "FINISH_REASON_UNSPECIFIED")),
+                    Ok(event("lo, wor", "FINISH_REASON_UNSPECIFIED")),
+                    Ok(event("ld", "FINISH_REASON_UNSPECIFIED")),
+                    Ok(event("", "STOP")),
+                ]
+            };
+            let body = reqwest::Body::wrap_stream(futures::stream::iter(events));
+            Ok(http::Response::new(body).into())
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_stream_resumes_after_network_failure() {
+        let transport = Arc::new(FlakyStreamTransport::default());
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(Arc::clone(&transport))
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["Say hello"])
+            .model("gemini-1.5-flash")
+            .stream_resumes(1)
+            .build();
+
+        let responses: Vec<_> = request.stream().await.map(Result::unwrap).collect().await;
+        let deltas: Vec<_> = responses
+            .iter()
+            .map(GenerateContentResponse::text)
+            .collect();
+        assert_eq!(deltas, ["Hello, ", "wor", "ld", ""]);
+        assert_eq!(responses[3].finish_reason(), Some(FinishReason::Stop));
+        assert_eq!(transport.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let request = gemini
+            .generate_content()
+            .contents(vec!["Say hello"])
+            .model("gemini-1.5-flash")
+            .build();
+        transport
+            .calls
+            .store(0, std::sync::atomic::Ordering::SeqCst);
+        let results: Vec<_> = request.stream().await.collect().await;
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(ApiRequestError::ReqwestError(_))));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_stream_to() {
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(Arc::new(FlakyStreamTransport::default()))
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["Say hello"])
+            .model("gemini-1.5-flash")
+            .stream_resumes(1)
+            .build();
+
+        let mut written = Vec::new();
+        let text = request.stream_to(&mut written).await.unwrap();
+
+        assert_eq!(text, "Hello, world");
+        assert_eq!(written, b"Hello, world");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_buffered_stream() {
+        let transport = Arc::new(FlakyStreamTransport::default());
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(Arc::clone(&transport))
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["Say hello"])
+            .model("gemini-1.5-flash")
+            .stream_resumes(1)
+            .build();
+
+        let stream = request.buffered_stream(8);
+        drop(request);
+        let deltas: Vec<_> = stream.map(|chunk| chunk.unwrap().text()).collect().await;
+
+        assert_eq!(deltas, ["Hello, ", "wor", "ld", ""]);
+        assert_eq!(transport.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_multiple_candidates() {
+        let response: GenerateContentResponse = serde_json::from_value(json!({
+            "candidates": [
+                {
+                    "content": {"role": "model", "parts": [{"text": "Hello"}, {"text": " there"}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                },
+                {
+                    "content": {"role": "model", "parts": [
+                        {"functionCall": {"name": "tool_one", "args": {}}}
+                    ]},
+                    "finishReason": "STOP",
+                    "index": 1
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(response.candidates().count(), 2);
+        assert_eq!(response.nth_text(0).as_deref(), Some("Hello there"));
+        assert_eq!(response.nth_text(1).as_deref(), Some(""));
+        assert_eq!(response.nth_text(2), None);
+        assert_eq!(response.texts(), ["Hello there", ""]);
+        assert!(response.get_function_calls().is_empty());
+        assert_eq!(
+            response.candidate(1).unwrap().function_calls()[0].name,
+            "tool_one"
+        );
+
+        let content = response.into_content(1).unwrap();
+        assert_eq!(content.role, message::Role::Model);
+        assert!(content.parts()[0].as_function_call().is_some());
+
+        let empty: GenerateContentResponse =
+            serde_json::from_value(json!({"candidates": []})).unwrap();
+        assert!(Content::from(empty).is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_response_accessors() {
+        let response: GenerateContentResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Once upon"}, {"text": " a time"}]},
+                "finishReason": "MAX_TOKENS",
+                "index": 0
+            }]
+        }))
+        .unwrap();
+
+        assert!(!response.is_empty());
+        assert_eq!(response.first_candidate().unwrap().index, 0);
+        assert_eq!(response.text(), "Once upon a time");
+        assert_eq!(response.finish_reason(), Some(FinishReason::MaxTokens));
+        assert!(response.was_truncated());
+
+        let empty: GenerateContentResponse =
+            serde_json::from_value(json!({"candidates": []})).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.text(), "");
+        assert_eq!(empty.finish_reason(), None);
+        assert!(!empty.was_truncated());
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_contents_setter_conversions() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let contents_of = |request: GenerateContentRequest| request.contents;
+        let expected = vec![Content::from("hello")];
+
+        let prompt = String::from("hello");
+        let borrowed = Content::from(prompt.as_str());
+        let requests = [
+            gemini.generate_content().contents("hello"),
+            gemini.generate_content().contents(borrowed),
+            gemini.generate_content().contents(vec!["hello"]),
+            gemini.generate_content().contents([Content::from("hello")]),
+            gemini
+                .generate_content()
+                .contents(Contents::from_iter(["hello"])),
+        ];
+        for request in requests {
+            assert_eq!(
+                contents_of(request.model("gemini-1.5-flash").build()),
+                expected
+            );
+        }
+
+        let mut contents = Contents::new();
+        contents.push(Content::from("hello"));
+        contents.extend(["again"]);
+        assert_eq!(contents.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&contents).unwrap(),
+            json!([
+                {"role": "user", "parts": [{"text": "hello"}]},
+                {"role": "user", "parts": [{"text": "again"}]}
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_content_appends() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let image = Content::builder()
+            .role(message::Role::User)
+            .parts(vec![Part::from(message::Blob::new("image/png", b"png"))])
+            .build();
+        let request = gemini
+            .generate_content()
+            .content("preamble")
+            .content(image.clone())
+            .content(String::from("question"))
+            .model("gemini-1.5-flash")
+            .build();
+        assert_eq!(
+            request.contents,
+            vec![Content::from("preamble"), image, Content::from("question")]
+        );
+
+        let request = gemini
+            .generate_content()
+            .content("dropped")
+            .contents(["first", "second"])
+            .content("third")
+            .model("gemini-1.5-flash")
+            .build();
+        assert_eq!(request.contents.len(), 3);
+        assert_eq!(request.contents[0], Content::from("first"));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_error_for_block() {
+        let blocked_prompt: GenerateContentResponse = serde_json::from_value(json!({
+            "candidates": [],
+            "promptFeedback": {
+                "blockReason": "SAFETY",
+                "safetyRatings": [
+                    {"category": "HARM_CATEGORY_HARASSMENT", "probability": "HIGH"}
+                ]
+            }
+        }))
+        .unwrap();
+        assert!(matches!(
+            blocked_prompt.error_for_block(),
+            Err(ApiRequestError::Blocked { reason, safety_ratings })
+                if reason == "SAFETY" && safety_ratings.len() == 1
+        ));
+
+        let blocked_candidate: GenerateContentResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": []},
+                "finishReason": "RECITATION",
+                "index": 0
+            }]
+        }))
+        .unwrap();
+        assert!(matches!(
+            blocked_candidate.error_for_block(),
+            Err(ApiRequestError::Blocked { reason, .. }) if reason == "RECITATION"
+        ));
+
+        let answered: GenerateContentResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Hi"}]},
+                "finishReason": "STOP",
+                "index": 0
+            }]
+        }))
+        .unwrap();
+        assert_eq!(answered.error_for_block().unwrap().text(), "Hi");
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_system_instruction_serialization() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .system_instruction(["Answer in Polish."])
+            .model("gemini-1.5-flash")
+            .build();
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["system_instruction"],
+            json!({"parts": [{"text": "Answer in Polish."}]})
+        );
+
+        let style_guide = message::FileData {
+            mime_type: Some("text/plain".to_string()),
+            file_uri: "https://generativelanguage.googleapis.com/v1beta/files/style".to_string(),
+            video_metadata: None,
+        };
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .system_instruction(vec![
+                Part::from("Follow the attached style guide."),
+                Part::from(style_guide),
+            ])
+            .model("gemini-1.5-flash")
+            .build();
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["system_instruction"],
+            json!({"parts": [
+                {"text": "Follow the attached style guide."},
+                {"fileData": {
+                    "mimeType": "text/plain",
+                    "fileUri": "https://generativelanguage.googleapis.com/v1beta/files/style"
+                }}
+            ]})
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_requests_and_responses_are_owned() {
+        fn assert_owned<T: Send + Sync + 'static>() {}
+        assert_owned::<GenerateContentRequest>();
+        assert_owned::<GenerateContentResponse>();
+
+        let prompt = String::from("hello");
+        let request = Gemini::builder()
+            .build()
+            .generate_content()
+            .contents([Content::from(&prompt).to_owned()])
+            .model("gemini-1.5-flash")
+            .build();
+        drop(prompt);
+        assert_eq!(request.estimate_tokens(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_requests_share_client() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let requests: Vec<_> = (0..3)
+            .map(|_| gemini.generate_content().model("gemini-1.5-flash").build())
+            .collect();
+
+        for request in &requests {
+            assert!(std::sync::Arc::ptr_eq(&request.gemini.inner, &gemini.inner));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_estimate_tokens() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["Hello, world"])
+            .system_instruction(Content::from("Be brief"))
+            .model("gemini-1.5-flash")
+            .build();
+        assert_eq!(request.estimate_tokens(), 3 + 2);
+
+        let tools = ToolBox::default();
+        tools.add(ToolOne);
+        let request = gemini
+            .generate_content()
+            .contents(vec!["Hello, world"])
+            .model("gemini-1.5-flash")
+            .tools(tools)
+            .build();
+        assert!(request.estimate_tokens() > 3);
+    }
+
     #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
     async fn test_function_calling() {
@@ -432,10 +1936,10 @@ mod tests {
 
         let api_key = get_api_key();
         let gemini = Gemini::builder().api_key(api_key).build();
-        dbg!(ResponseSchema::from::<Book>());
+        dbg!(ResponseSchema::from::<Book>().unwrap());
         let config = GenerationConfig::builder()
             .response_mime_type("application/json".to_string())
-            .response_schema(ResponseSchema::from::<Book>())
+            .response_schema(ResponseSchema::from::<Book>().unwrap())
             .build();
 
         let request = gemini
@@ -456,6 +1960,96 @@ mod tests {
         dbg!(&json);
     }
 
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_generate_content_blocking() {
+        let api_key = get_api_key();
+        let gemini = Gemini::builder().api_key(api_key).build();
+        let response = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-flash")
+            .build()
+            .send_blocking()
+            .unwrap();
+
+        assert!(response.content().is_some_and(|c| !c.is_empty()));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_send_as() {
+        #[derive(Debug, Deserialize, JsonSchema)]
+        struct Book {
+            title: String,
+            author: String,
+        }
+
+        let api_key = get_api_key();
+        let gemini = Gemini::builder().api_key(api_key).build();
+
+        let book = gemini
+            .generate_content()
+            .contents(vec![r"Describe Peter Watts Echopraxia book"])
+            .model("gemini-1.5-flash")
+            .build()
+            .send_as::<Book>()
+            .await
+            .unwrap();
+
+        assert!(!book.title.is_empty());
+        assert!(!book.author.is_empty());
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_send_as_leaves_request_unchanged() {
+        #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+        struct Book {
+            title: String,
+            author: String,
+        }
+
+        let transport = crate::transport::tests::MockTransport::json(
+            200,
+            &json!({"candidates": [{"content": {"role": "model", "parts": [
+                {"text": "{\"title\": \"Echopraxia\", \"author\": \"Peter Watts\"}"}
+            ]}, "finishReason": "STOP", "index": 0}]}),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents("Describe Peter Watts' Echopraxia")
+            .model("gemini-1.5-flash")
+            .build();
+
+        let book = request.send_as::<Book>().await.unwrap();
+        assert_eq!(
+            book,
+            Book {
+                title: "Echopraxia".to_string(),
+                author: "Peter Watts".to_string(),
+            }
+        );
+        assert!(request.generation_config.is_none());
+        request.send().await.unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        let sent = |i: usize| -> Value {
+            serde_json::from_slice(requests[i].body().unwrap().as_bytes().unwrap()).unwrap()
+        };
+        let config = &sent(0)["generation_config"];
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert_eq!(
+            config["responseSchema"]["properties"],
+            json!({"title": {"type": "string"}, "author": {"type": "string"}})
+        );
+        assert!(sent(1).get("generation_config").is_none());
+    }
+
     #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
     async fn test_messages_request_success() {
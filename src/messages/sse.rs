@@ -0,0 +1,174 @@
+use futures::{stream, Stream, StreamExt};
+
+use crate::ApiRequestError;
+
+/// Incremental decoder for `text/event-stream` bodies.
+///
+/// Network chunks (and browser fetch chunks in particular) may split an event, a line, or even
+/// a multi-byte UTF-8 character, so bytes are buffered until a full line is available and
+/// events are only emitted once their terminating blank line has been seen.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    line: Vec<u8>,
+    data: Vec<String>,
+    invalid: Vec<String>,
+    /// Whether the previous chunk ended with `\r`, so a leading `\n` belongs to that line.
+    pending_cr: bool,
+}
+
+impl SseDecoder {
+    /// Feeds a chunk and returns the data payloads of all events completed by it.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<Result<String, ApiRequestError>> {
+        let mut events = Vec::new();
+        for &byte in chunk {
+            let pending_cr = std::mem::take(&mut self.pending_cr);
+            match byte {
+                b'\n' if pending_cr => {}
+                b'\n' | b'\r' => {
+                    self.pending_cr = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    events.extend(self.process_line(&line));
+                }
+                _ => self.line.push(byte),
+            }
+        }
+        events
+    }
+
+    /// Flushes an event left unterminated at the end of the body.
+    pub(crate) fn finish(&mut self) -> Option<Result<String, ApiRequestError>> {
+        let line = std::mem::take(&mut self.line);
+        if !line.is_empty() {
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Option<Result<String, ApiRequestError>> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        let line = String::from_utf8_lossy(line);
+        // Comments (`: keep-alive`) and the fields Gemini doesn't use are legal and ignored.
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((&line, ""));
+        match field {
+            "data" => self
+                .data
+                .push(value.strip_prefix(' ').unwrap_or(value).to_string()),
+            "event" | "id" | "retry" => {}
+            _ => self.invalid.push(line.into_owned()),
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<Result<String, ApiRequestError>> {
+        let data = std::mem::take(&mut self.data);
+        let invalid = std::mem::take(&mut self.invalid);
+        if !invalid.is_empty() {
+            return Some(Err(ApiRequestError::InvalidEventData(invalid.join("\n"))));
+        }
+        (!data.is_empty()).then(|| Ok(data.join("\n")))
+    }
+}
+
+/// Turns an HTTP body into a stream of SSE data payloads.
+///
+/// Only `futures` combinators are used, so this runs the same on tokio and on
+/// `wasm32-unknown-unknown`, where the body is a fetch `ReadableStream`.
+pub(crate) fn events<S, B>(body: S) -> impl Stream<Item = Result<String, ApiRequestError>>
+where
+    S: Stream<Item = Result<B, reqwest::Error>>,
+    B: AsRef<[u8]>,
+{
+    stream::unfold(
+        Some((Box::pin(body), SseDecoder::default())),
+        |state| async move {
+            let (mut body, mut decoder) = state?;
+            match body.next().await {
+                Some(Ok(chunk)) => Some((decoder.push(chunk.as_ref()), Some((body, decoder)))),
+                Some(Err(e)) => Some((vec![Err(ApiRequestError::ReqwestError(e))], None)),
+                None => Some((decoder.finish().into_iter().collect(), None)),
+            }
+        },
+    )
+    .flat_map(stream::iter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    fn decode(chunks: &[&[u8]]) -> Vec<String> {
+        let mut decoder = SseDecoder::default();
+        let mut events: Vec<String> = chunks
+            .iter()
+            .flat_map(|chunk| decoder.push(chunk))
+            .map(Result::unwrap)
+            .collect();
+        events.extend(decoder.finish().map(Result::unwrap));
+        events
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_events_split_across_chunks() {
+        let events = decode(&[b"data: {\"a\":", b"1}\r\n", b"\r\ndata: {\"b\":2}\n\n"]);
+        assert_eq!(events, [r#"{"a":1}"#, r#"{"b":2}"#]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_multibyte_character_split_across_chunks() {
+        let bytes = "data: \"zażółć\"\n\n".as_bytes();
+        let (head, tail) = bytes.split_at(10);
+        assert_eq!(decode(&[head, tail]), ["\"zażółć\""]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_unterminated_event_is_flushed() {
+        assert_eq!(decode(&[b"data: {}"]), ["{}"]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_invalid_event_data() {
+        let mut decoder = SseDecoder::default();
+        let events = decoder.push(b"garbage\n\n");
+        assert!(matches!(
+            events.as_slice(),
+            [Err(ApiRequestError::InvalidEventData(data))] if data == "garbage"
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_comments_and_heartbeats_are_skipped() {
+        let events = decode(&[
+            b": keep-alive\n\n",
+            b"\n\n",
+            b"event: message\nid: 7\ndata: 1\n: inline comment\n\n",
+            b":\r\n\r\ndata: 2\n\n",
+        ]);
+        assert_eq!(events, ["1", "2"]);
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_events_stream() {
+        let body = stream::iter(vec![
+            Ok::<_, reqwest::Error>(b"data: 1\n\nda".to_vec()),
+            Ok(b"ta: 2\n\n".to_vec()),
+        ]);
+        let events: Vec<String> = events(body).map(Result::unwrap).collect().await;
+        assert_eq!(events, ["1", "2"]);
+    }
+}
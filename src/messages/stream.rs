@@ -0,0 +1,184 @@
+//! Combinators for the stream returned by [`GenerateContentRequest::stream`].
+//!
+//! [`GenerateContentRequest::stream`]: super::GenerateContentRequest::stream
+
+use std::future::Future;
+
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
+
+use super::{
+    message::{FunctionCall, Part},
+    GenerateContentResponse, UsageMetadata,
+};
+use crate::ApiRequestError;
+
+/// Consumes a `generateContent` response stream without indexing into every chunk.
+///
+/// ```no_run
+/// # async fn run(request: gemini_ox::messages::GenerateContentRequest) -> Result<(), gemini_ox::ApiRequestError> {
+/// use futures::StreamExt;
+/// use gemini_ox::messages::stream::GenerateContentStreamExt;
+///
+/// let mut deltas = std::pin::pin!(request.stream().await.text_deltas());
+/// while let Some(delta) = deltas.next().await {
+///     print!("{}", delta?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub trait GenerateContentStreamExt:
+    Stream<Item = Result<GenerateContentResponse, ApiRequestError>> + Sized
+{
+    /// The text of the first candidate as it arrives, skipping chunks without text.
+    fn text_deltas(self) -> impl Stream<Item = Result<String, ApiRequestError>> {
+        self.try_filter_map(|chunk| {
+            let text = chunk.text();
+            future::ready(Ok((!text.is_empty()).then_some(text)))
+        })
+    }
+
+    /// Merges the chunks into the response a non-streaming request would have returned:
+    /// the parts of every candidate concatenated, with the last finish reason, safety
+    /// ratings and usage.
+    fn collect_response(
+        self,
+    ) -> impl Future<Output = Result<GenerateContentResponse, ApiRequestError>> {
+        let empty = GenerateContentResponse {
+            candidates: Vec::new(),
+            prompt_feedback: None,
+            usage_metadata: None,
+            model_version: None,
+            response_id: None,
+            served_by: None,
+        };
+        self.try_fold(empty, |mut response, chunk| {
+            merge(&mut response, chunk);
+            future::ready(Ok(response))
+        })
+    }
+
+    /// The function calls of the first candidate, each as soon as its chunk arrives.
+    fn function_calls(self) -> impl Stream<Item = Result<FunctionCall, ApiRequestError>> {
+        self.map_ok(|chunk| {
+            let calls: Vec<_> = chunk.get_function_calls().into_iter().cloned().collect();
+            stream::iter(calls).map(Ok)
+        })
+        .try_flatten()
+    }
+
+    /// Drains the stream and returns the final usage, which the API reports with the
+    /// last chunk.
+    fn usage(self) -> impl Future<Output = Result<Option<UsageMetadata>, ApiRequestError>> {
+        self.try_fold(None, |usage, chunk| {
+            future::ready(Ok(chunk.usage_metadata.or(usage)))
+        })
+    }
+}
+
+impl<S> GenerateContentStreamExt for S where
+    S: Stream<Item = Result<GenerateContentResponse, ApiRequestError>>
+{
+}
+
+fn merge(response: &mut GenerateContentResponse, chunk: GenerateContentResponse) {
+    for candidate in chunk.candidates {
+        let Some(merged) = response
+            .candidates
+            .iter_mut()
+            .find(|c| c.index == candidate.index)
+        else {
+            response.candidates.push(candidate);
+            continue;
+        };
+        for part in candidate.content.parts {
+            match (merged.content.parts.last_mut(), part) {
+                (Some(Part::Text(text)), Part::Text(delta)) => text.0.to_mut().push_str(&delta.0),
+                (_, part) => merged.content.parts.push(part),
+            }
+        }
+        merged.finish_reason = candidate.finish_reason;
+        if candidate.safety_ratings.is_some() {
+            merged.safety_ratings = candidate.safety_ratings;
+        }
+    }
+    response.prompt_feedback = chunk.prompt_feedback.or(response.prompt_feedback.take());
+    response.usage_metadata = chunk.usage_metadata.or(response.usage_metadata.take());
+    response.model_version = chunk.model_version.or(response.model_version.take());
+    response.response_id = chunk.response_id.or(response.response_id.take());
+    response.served_by = chunk.served_by.or(response.served_by.take());
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::messages::FinishReason;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    fn chunks() -> impl Stream<Item = Result<GenerateContentResponse, ApiRequestError>> {
+        let chunk = |parts: serde_json::Value, finish_reason: &str| {
+            serde_json::from_value(json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": parts},
+                    "finishReason": finish_reason,
+                    "index": 0
+                }],
+                "usageMetadata": {"promptTokenCount": 4, "totalTokenCount": 4},
+                "modelVersion": "gemini-1.5-flash-002",
+                "responseId": "resp-1"
+            }))
+            .map_err(ApiRequestError::SerdeError)
+        };
+        stream::iter([
+            chunk(json!([{"text": "Checking "}]), "FINISH_REASON_UNSPECIFIED"),
+            chunk(
+                json!([{"text": "the weather."}]),
+                "FINISH_REASON_UNSPECIFIED",
+            ),
+            chunk(
+                json!([{"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}}]),
+                "STOP",
+            ),
+        ])
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_stream_combinators() {
+        let deltas: Vec<_> = chunks().text_deltas().try_collect().await.unwrap();
+        assert_eq!(deltas, ["Checking ", "the weather."]);
+
+        let calls: Vec<_> = chunks().function_calls().try_collect().await.unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "get_weather");
+
+        let usage = chunks().usage().await.unwrap().unwrap();
+        assert_eq!(usage.prompt_token_count, 4);
+
+        let response = chunks().collect_response().await.unwrap();
+        assert_eq!(response.candidates.len(), 1);
+        assert_eq!(response.content().unwrap().parts().len(), 2);
+        assert_eq!(response.text(), "Checking the weather.");
+        assert_eq!(response.finish_reason(), Some(FinishReason::Stop));
+        assert!(response.usage_metadata.is_some());
+        assert_eq!(
+            response.model_version.as_deref(),
+            Some("gemini-1.5-flash-002")
+        );
+        assert_eq!(response.response_id.as_deref(), Some("resp-1"));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_stream_error_is_passed_on() {
+        let failing = chunks().chain(stream::once(async { Err(ApiRequestError::RateLimit) }));
+
+        assert!(matches!(
+            failing.collect_response().await,
+            Err(ApiRequestError::RateLimit)
+        ));
+    }
+}
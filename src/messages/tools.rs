@@ -6,16 +6,17 @@ use std::{
 use async_trait::async_trait;
 use schemars::JsonSchema;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 use super::message::{FunctionCall, FunctionResponse};
+use crate::{operations::Backoff, schema::SchemaError};
 
 #[async_trait]
 pub trait AnyTool: Send + Sync {
     fn name(&self) -> String;
     fn description(&self) -> Option<String>;
     async fn invoke_any(&self, function_call: FunctionCall) -> FunctionResponse;
-    fn input_schema(&self) -> Value;
+    fn input_schema(&self) -> Result<Option<Value>, SchemaError>;
 }
 
 #[async_trait]
@@ -28,24 +29,16 @@ pub trait Tool: Clone + Send + Sync {
         None
     }
     async fn invoke(&self, input: Self::Input) -> Result<Self::Output, Self::Error>;
-    fn input_schema(&self) -> Value {
-        let settings = schemars::gen::SchemaSettings::openapi3().with(|s| {
-            s.inline_subschemas = true;
-            s.meta_schema = None;
-        });
-        let gen = schemars::gen::SchemaGenerator::new(settings);
-        let json_schema = gen.into_root_schema_for::<Self::Input>();
-        let mut input_schema = serde_json::to_value(json_schema).unwrap();
-        input_schema
-            .as_object_mut()
-            .unwrap()
-            .remove("title")
-            .unwrap();
-        if input_schema.get("properties").is_some() {
-            input_schema
-        } else {
-            serde_json::json!(None::<()>)
-        }
+    /// Whether arguments are checked against [`Tool::input_schema`] before deserializing, so
+    /// the model gets every mismatch with its path instead of serde's first error.
+    fn validates_input(&self) -> bool {
+        false
+    }
+    /// The schema of [`Tool::Input`], or none if it has no properties. Fails if the schema
+    /// can't be generated, e.g. for a recursive type.
+    fn input_schema(&self) -> Result<Option<Value>, SchemaError> {
+        let schema = crate::ResponseSchema::from::<Self::Input>()?;
+        Ok(schema.get("properties").is_some().then_some(schema))
     }
 }
 
@@ -60,47 +53,398 @@ impl<T: Tool + Send + Sync> AnyTool for T {
     }
 
     async fn invoke_any(&self, function_call: FunctionCall) -> FunctionResponse {
-        let Some(input) = function_call.args else {
-            return FunctionResponse {
-                name: function_call.name,
-                response: FunctionCallError::MissingArguments.to_string().into(),
-            };
-        };
+        FunctionResponse {
+            response: call_tool(self, function_call.args)
+                .await
+                .unwrap_or_else(ToolFailure::into_response),
+            name: function_call.name,
+        }
+    }
+
+    fn input_schema(&self) -> Result<Option<Value>, SchemaError> {
+        self.input_schema()
+    }
+}
+
+/// Why a tool call produced no output.
+enum ToolFailure<E> {
+    /// The call itself was unusable: invalid arguments or unserializable output.
+    Call(FunctionCallError),
+    /// The tool returned an error.
+    Tool(E),
+}
+
+impl<E: ToString> ToolFailure<E> {
+    fn into_response(self) -> Value {
+        match self {
+            ToolFailure::Call(e) => e.to_string().into(),
+            ToolFailure::Tool(e) => e.to_string().into(),
+        }
+    }
+}
+
+/// Validates and deserializes `args`, invokes `tool` and serializes its output.
+async fn call_tool<T: Tool>(tool: &T, args: Option<Value>) -> Result<Value, ToolFailure<T::Error>> {
+    if tool.validates_input() {
+        let schema = Tool::input_schema(tool).map_err(|e| {
+            ToolFailure::Call(FunctionCallError::SchemaGenerationFailed(e.to_string()))
+        })?;
+        let errors = validate_args(
+            &schema.unwrap_or_default(),
+            args.as_ref().unwrap_or(&Value::Object(Map::new())),
+        );
+        if !errors.is_empty() {
+            return Err(ToolFailure::Call(FunctionCallError::InvalidArguments(
+                errors,
+            )));
+        }
+    }
+    let input: T::Input = deserialize_args(args).map_err(|e| {
+        ToolFailure::Call(FunctionCallError::InputDeserializationFailed(e.to_string()))
+    })?;
+    let output = tool.invoke(input).await.map_err(ToolFailure::Tool)?;
+    serde_json::to_value(output)
+        .map_err(|e| ToolFailure::Call(FunctionCallError::OutputSerializationFailed(e.to_string())))
+}
+
+/// How often a tool is retried when it fails, see [`ToolBox::add_with_retry`].
+///
+/// Only errors returned by the tool itself are retried; invalid arguments are not. The
+/// backoff's `timeout` is ignored.
+pub struct ToolRetry<E> {
+    max_attempts: u32,
+    backoff: Backoff,
+    retry_if: Arc<dyn Fn(&E) -> bool + Send + Sync>,
+}
+
+impl<E> ToolRetry<E> {
+    /// Makes up to `max_attempts` attempts in total, retrying every error after a short
+    /// exponential backoff.
+    #[must_use]
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Backoff {
+                initial_delay: std::time::Duration::from_millis(200),
+                max_delay: std::time::Duration::from_secs(5),
+                ..Backoff::default()
+            },
+            retry_if: Arc::new(|_| true),
+        }
+    }
+
+    #[must_use]
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
 
-        let typed_input: T::Input = match serde_json::from_value(input) {
-            Ok(input) => input,
-            Err(e) => {
-                return FunctionResponse {
-                    name: function_call.name,
-                    response: FunctionCallError::InputDeserializationFailed(e.to_string())
-                        .to_string()
-                        .into(),
+    /// Retries only errors for which `predicate` returns `true`.
+    #[must_use]
+    pub fn retry_if(mut self, predicate: impl Fn(&E) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_if = Arc::new(predicate);
+        self
+    }
+}
+
+impl<E> Clone for ToolRetry<E> {
+    fn clone(&self) -> Self {
+        Self {
+            max_attempts: self.max_attempts,
+            backoff: self.backoff.clone(),
+            retry_if: Arc::clone(&self.retry_if),
+        }
+    }
+}
+
+impl<E> fmt::Debug for ToolRetry<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToolRetry")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+struct RetryingTool<T: Tool> {
+    tool: T,
+    retry: ToolRetry<T::Error>,
+}
+
+#[async_trait]
+impl<T: Tool> AnyTool for RetryingTool<T> {
+    fn name(&self) -> String {
+        self.tool.name()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.tool.description()
+    }
+
+    async fn invoke_any(&self, function_call: FunctionCall) -> FunctionResponse {
+        let mut delays = self.retry.backoff.delays();
+        let mut attempt = 1;
+        loop {
+            match call_tool(&self.tool, function_call.args.clone()).await {
+                Err(ToolFailure::Tool(e))
+                    if attempt < self.retry.max_attempts && (self.retry.retry_if)(&e) => {}
+                result => {
+                    return FunctionResponse {
+                        name: function_call.name,
+                        response: result.unwrap_or_else(ToolFailure::into_response),
+                    }
                 }
             }
-        };
+            let delay = delays.next().unwrap_or(self.retry.backoff.max_delay);
+            futures_timer::Delay::new(delay).await;
+            attempt += 1;
+        }
+    }
 
-        match self.invoke(typed_input).await {
-            Ok(output) => match serde_json::to_value(output) {
-                Ok(value) => FunctionResponse {
-                    name: function_call.name,
-                    response: value,
-                },
-                Err(e) => FunctionResponse {
-                    name: function_call.name,
-                    response: FunctionCallError::OutputSerializationFailed(e.to_string())
-                        .to_string()
-                        .into(),
-                },
-            },
-            Err(e) => FunctionResponse {
-                name: function_call.name,
-                response: e.to_string().into(),
-            },
+    fn input_schema(&self) -> Result<Option<Value>, SchemaError> {
+        Tool::input_schema(&self.tool)
+    }
+}
+
+/// Returns `response` unchanged if it serializes to at most `max_bytes`, otherwise a
+/// shortened copy wrapped as `{"truncated": <note>, "content": <shortened>}`.
+fn limit_response(response: Value, max_bytes: usize) -> Value {
+    let size = serialized_len(&response);
+    if size <= max_bytes {
+        return response;
+    }
+    let note = |content_bytes: usize| {
+        format!(
+            "Output truncated from {size} to about {content_bytes} bytes; the rest was omitted. Ask for a narrower result if you need it."
+        )
+    };
+    // The note states the content's size, which is at most its budget, so the budget is
+    // taken as that size when measuring the room the note needs.
+    let wrapper =
+        |note: String, content: Value| serde_json::json!({ "truncated": note, "content": content });
+    let note_bytes = serialized_len(&wrapper(note(max_bytes), Value::Null)) - "null".len();
+    let content = truncate_value(response, max_bytes.saturating_sub(note_bytes));
+    wrapper(note(serialized_len(&content)), content)
+}
+
+/// Shortens `value` to serialize to at most `budget` bytes: strings are cut, and arrays and
+/// objects keep their entries in order up to the first that doesn't fit, which ends them
+/// shortened, if anything of it fits. Only values that can't be shortened, like numbers, or
+/// a budget too small for even `""`, `[]` or `{}`, may exceed it.
+fn truncate_value(value: Value, budget: usize) -> Value {
+    match value {
+        Value::String(mut text) => {
+            let mut used = 2;
+            let end = text
+                .char_indices()
+                .find_map(|(i, c)| {
+                    used += escaped_len(c);
+                    (used > budget).then_some(i)
+                })
+                .unwrap_or(text.len());
+            text.truncate(end);
+            Value::String(text)
+        }
+        Value::Array(items) => {
+            let mut used = 2;
+            let mut kept = Vec::new();
+            for item in items {
+                let remaining = budget.saturating_sub(used + usize::from(!kept.is_empty()));
+                let shortened = serialized_len(&item) > remaining;
+                let item = if shortened {
+                    truncate_value(item, remaining)
+                } else {
+                    item
+                };
+                let item_len = serialized_len(&item);
+                if item_len > remaining {
+                    break;
+                }
+                used += item_len + usize::from(!kept.is_empty());
+                kept.push(item);
+                if shortened {
+                    break;
+                }
+            }
+            Value::Array(kept)
+        }
+        Value::Object(fields) => {
+            let mut used = 2;
+            let mut kept = Map::new();
+            for (key, value) in fields {
+                // The quoted key, the colon and the comma before all but the first field.
+                let key_len = 2 + key.chars().map(escaped_len).sum::<usize>() + 1;
+                let remaining =
+                    budget.saturating_sub(used + key_len + usize::from(!kept.is_empty()));
+                let shortened = serialized_len(&value) > remaining;
+                let value = if shortened {
+                    truncate_value(value, remaining)
+                } else {
+                    value
+                };
+                let value_len = serialized_len(&value);
+                if value_len > remaining {
+                    break;
+                }
+                used += key_len + value_len + usize::from(!kept.is_empty());
+                kept.insert(key, value);
+                if shortened {
+                    break;
+                }
+            }
+            Value::Object(kept)
         }
+        value => value,
     }
+}
 
-    fn input_schema(&self) -> Value {
-        self.input_schema()
+/// Length of `c` in a JSON string, which escapes quotes, backslashes and control characters.
+fn escaped_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+        c if c < ' ' => 6,
+        c => c.len_utf8(),
+    }
+}
+
+fn serialized_len(value: &Value) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
+
+/// Deserializes function call arguments into the tool input type.
+///
+/// Parameterless tools are called without `args` (or with an empty object), so
+/// both struct-like inputs (`{}`) and unit inputs (`null`) are accepted then.
+fn deserialize_args<T: DeserializeOwned>(args: Option<Value>) -> Result<T, serde_json::Error> {
+    let args = args.unwrap_or_else(|| Value::Object(Map::new()));
+    let is_empty = args.as_object().is_some_and(Map::is_empty);
+    match serde_json::from_value(args) {
+        Err(_) if is_empty => serde_json::from_value(Value::Null),
+        result => result,
+    }
+}
+
+/// A mismatch between function call arguments and a tool's input schema.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{path}: {kind}")]
+pub struct ArgumentError {
+    /// Location of the offending value, e.g. `$.items[0].name`.
+    pub path: String,
+    pub kind: ArgumentErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ArgumentErrorKind {
+    #[error("missing required field `{0}`")]
+    MissingField(String),
+    #[error("expected {expected}, got {actual}")]
+    WrongType { expected: String, actual: String },
+    #[error("expected one of {allowed}, got {actual}")]
+    NotInEnum { allowed: String, actual: String },
+    #[error("matches none of the allowed shapes")]
+    NoVariantMatched,
+}
+
+/// Checks `args` against an OpenAPI-style input schema, as produced by
+/// [`Tool::input_schema`], collecting every mismatch. Only `type`, `nullable`, `properties`,
+/// `required`, `items`, `enum`, `anyOf` and `oneOf` are checked; other keywords are ignored.
+#[must_use]
+pub fn validate_args(schema: &Value, args: &Value) -> Vec<ArgumentError> {
+    let mut errors = Vec::new();
+    validate_value(schema, args, "$", &mut errors);
+    errors
+}
+
+fn validate_value(schema: &Value, value: &Value, path: &str, errors: &mut Vec<ArgumentError>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+    let error = |kind| ArgumentError {
+        path: path.to_string(),
+        kind,
+    };
+    if value.is_null() && schema.get("nullable").and_then(Value::as_bool) == Some(true) {
+        return;
+    }
+    if let Some(variants) = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array)
+    {
+        if !variants
+            .iter()
+            .any(|variant| validate_args(variant, value).is_empty())
+        {
+            errors.push(error(ArgumentErrorKind::NoVariantMatched));
+        }
+        return;
+    }
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            _ => true,
+        };
+        if !matches {
+            errors.push(error(ArgumentErrorKind::WrongType {
+                expected: expected.to_string(),
+                actual: json_type(value).to_string(),
+            }));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            errors.push(error(ArgumentErrorKind::NotInEnum {
+                allowed: allowed
+                    .iter()
+                    .map(Value::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                actual: value.to_string(),
+            }));
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for field in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(field) {
+                errors.push(error(ArgumentErrorKind::MissingField(field.to_string())));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                if let Some(value) = object.get(name) {
+                    validate_value(property, value, &format!("{path}.{name}"), errors);
+                }
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (index, item) in array.iter().enumerate() {
+            validate_value(items, item, &format!("{path}[{index}]"), errors);
+        }
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
     }
 }
 
@@ -112,9 +456,39 @@ pub struct ToolMetadataInfo {
     pub parameters: Value,
 }
 
+/// A declaration on its own acts as a tool the model can call but that has no implementation
+/// here, for callers that execute function calls themselves. Invoking it fails.
+#[async_trait]
+impl AnyTool for ToolMetadataInfo {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    async fn invoke_any(&self, function_call: FunctionCall) -> FunctionResponse {
+        FunctionResponse {
+            name: function_call.name,
+            response: FunctionCallError::ExecutionFailed(format!(
+                "{} has no implementation",
+                self.name
+            ))
+            .to_string()
+            .into(),
+        }
+    }
+
+    fn input_schema(&self) -> Result<Option<Value>, SchemaError> {
+        Ok((!self.parameters.is_null()).then(|| self.parameters.clone()))
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct ToolBox {
     tools: Arc<RwLock<std::collections::HashMap<String, Arc<dyn AnyTool>>>>,
+    max_response_bytes: Option<usize>,
 }
 
 impl fmt::Debug for ToolBox {
@@ -122,6 +496,7 @@ impl fmt::Debug for ToolBox {
         let tools = self.tools.read().map_err(|_| fmt::Error)?;
         f.debug_struct("ToolBox")
             .field("tools", &format!("HashMap with {} entries", tools.len()))
+            .field("max_response_bytes", &self.max_response_bytes)
             .finish()
     }
 }
@@ -140,6 +515,8 @@ pub enum FunctionCallError {
     SchemaGenerationFailed(String),
     #[error("Missing arguments")]
     MissingArguments,
+    #[error("Invalid arguments: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    InvalidArguments(Vec<ArgumentError>),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -149,17 +526,34 @@ pub struct FunctionDeclarations {
 
 impl ToolBox {
     pub fn add<T: Tool + 'static>(&self, tool: T) {
-        let name = tool.name().to_string();
+        let name = tool.name().clone();
         self.tools.write().unwrap().insert(name, Arc::new(tool));
     }
 
+    /// Adds `tool`, retrying its failed invocations according to `retry`.
+    pub fn add_with_retry<T: Tool + 'static>(&self, tool: T, retry: ToolRetry<T::Error>) {
+        let name = tool.name();
+        self.tools
+            .write()
+            .unwrap()
+            .insert(name, Arc::new(RetryingTool { tool, retry }));
+    }
+
     #[must_use]
     pub fn get(&self, name: &str) -> Option<Arc<dyn AnyTool>> {
         self.tools.read().unwrap().get(name).cloned()
     }
 
+    /// Caps the serialized size of every tool response. Larger responses are shortened to
+    /// fit and wrapped with a note telling the model the output was truncated.
+    #[must_use]
+    pub fn max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = Some(max_response_bytes);
+        self
+    }
+
     pub async fn invoke(&self, function_call: FunctionCall) -> FunctionResponse {
-        match self.get(&function_call.name) {
+        let mut response = match self.get(&function_call.name) {
             Some(tool) => tool.invoke_any(function_call).await,
             None => FunctionResponse {
                 name: function_call.name.clone(),
@@ -167,7 +561,11 @@ impl ToolBox {
                     .to_string()
                     .into(),
             },
+        };
+        if let Some(max_bytes) = self.max_response_bytes {
+            response.response = limit_response(response.response, max_bytes);
         }
+        response
     }
 
     #[must_use]
@@ -180,23 +578,43 @@ impl ToolBox {
         self.tools.read().unwrap().len()
     }
 
+    /// Returns a new `ToolBox` containing only the tools with the given names.
     #[must_use]
-    pub fn metadata(&self) -> Vec<FunctionDeclarations> {
-        let tools = self
+    pub fn filter_by_names<S: AsRef<str>>(&self, names: &[S]) -> ToolBox {
+        let tools = self.tools.read().unwrap();
+        ToolBox {
+            max_response_bytes: self.max_response_bytes,
+            ..names
+                .iter()
+                .filter_map(|name| tools.get(name.as_ref()).cloned())
+                .collect()
+        }
+    }
+
+    /// The declarations of all tools, sorted by name. Fails if a tool's input schema can't
+    /// be generated.
+    pub fn declarations(&self) -> Result<Vec<ToolMetadataInfo>, SchemaError> {
+        let mut declarations = self
             .tools
             .read()
             .unwrap()
             .values()
-            .map(|tool| ToolMetadataInfo {
-                name: tool.name(),
-                description: tool.description(),
-                parameters: tool.input_schema(),
+            .map(|tool| {
+                Ok(ToolMetadataInfo {
+                    name: tool.name(),
+                    description: tool.description(),
+                    parameters: tool.input_schema()?.unwrap_or_default(),
+                })
             })
-            .collect();
+            .collect::<Result<Vec<_>, SchemaError>>()?;
+        declarations.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(declarations)
+    }
 
-        vec![FunctionDeclarations {
-            function_declarations: tools,
-        }]
+    pub fn metadata(&self) -> Result<Vec<FunctionDeclarations>, SchemaError> {
+        Ok(vec![FunctionDeclarations {
+            function_declarations: self.declarations()?,
+        }])
     }
 }
 
@@ -205,7 +623,9 @@ impl Serialize for ToolBox {
     where
         S: serde::ser::Serializer,
     {
-        self.metadata().serialize(serializer)
+        self.metadata()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
     }
 }
 
@@ -227,7 +647,7 @@ impl FromIterator<Arc<dyn AnyTool>> for ToolBox {
                 .tools
                 .write()
                 .unwrap()
-                .insert(tool.name().to_string(), tool);
+                .insert(tool.name().clone(), tool);
         }
         toolbox
     }
@@ -265,9 +685,10 @@ impl FunctionCallBuilder {
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
-#[serde(rename = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct ToolConfig {
     /// Function calling config.
+    #[serde(skip_serializing_if = "Option::is_none")]
     function_calling_config: Option<FunctionCallingConfig>,
 }
 
@@ -287,14 +708,29 @@ impl ToolConfig {
         let fcc = FunctionCallingConfig::default().mode(mode);
         self.function_calling_config(fcc)
     }
+
+    /// Limit the functions the model may call. The API only honours the allowed names in
+    /// `Mode::Any`, so the mode is switched accordingly.
+    #[must_use]
+    pub fn allowed_function_names(mut self, allowed_function_names: Vec<String>) -> Self {
+        let fcc = self
+            .function_calling_config
+            .take()
+            .unwrap_or_default()
+            .mode(Mode::Any)
+            .allowed_function_names(allowed_function_names);
+        self.function_calling_config(fcc)
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
-#[serde(rename = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct FunctionCallingConfig {
     /// Specifies the mode in which function calling should execute.
+    #[serde(skip_serializing_if = "Option::is_none")]
     mode: Option<Mode>,
     /// A set of function names that, when provided, limits the functions the model will call.
+    #[serde(skip_serializing_if = "Option::is_none")]
     allowed_function_names: Option<Vec<String>>,
 }
 
@@ -324,7 +760,7 @@ impl FunctionCallingConfig {
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename = "camelCase")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Mode {
     /// Unspecified function calling mode. This value should not be used.
     ModeUnspecified,
@@ -340,3 +776,386 @@ pub enum Mode {
     /// any function declarations.
     None,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[derive(Clone)]
+    struct NoArgsTool;
+
+    #[async_trait]
+    impl Tool for NoArgsTool {
+        type Input = ();
+        type Output = String;
+        type Error = String;
+
+        fn name(&self) -> String {
+            "no_args_tool".to_string()
+        }
+
+        async fn invoke(&self, _input: Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok("called".to_string())
+        }
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    struct EmptyParams;
+
+    #[derive(Clone)]
+    struct EmptyParamsTool;
+
+    #[async_trait]
+    impl Tool for EmptyParamsTool {
+        type Input = EmptyParams;
+        type Output = String;
+        type Error = String;
+
+        fn name(&self) -> String {
+            "empty_params_tool".to_string()
+        }
+
+        async fn invoke(&self, _input: Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok("called".to_string())
+        }
+    }
+
+    #[derive(Clone)]
+    struct EchoTool;
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        type Input = Value;
+        type Output = Value;
+        type Error = String;
+
+        fn name(&self) -> String {
+            "echo".to_string()
+        }
+
+        async fn invoke(&self, input: Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok(input)
+        }
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct ForecastParams {
+        city: String,
+        days: u8,
+        unit: Option<Unit>,
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    enum Unit {
+        Celsius,
+        Fahrenheit,
+    }
+
+    #[derive(Clone)]
+    struct ForecastTool;
+
+    #[async_trait]
+    impl Tool for ForecastTool {
+        type Input = ForecastParams;
+        type Output = String;
+        type Error = String;
+
+        fn name(&self) -> String {
+            "forecast".to_string()
+        }
+
+        fn validates_input(&self) -> bool {
+            true
+        }
+
+        async fn invoke(&self, _input: Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok("sunny".to_string())
+        }
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_validate_args() {
+        let schema = Tool::input_schema(&ForecastTool).unwrap().unwrap();
+        assert!(validate_args(&schema, &json!({"city": "Oslo", "days": 3})).is_empty());
+        assert!(
+            validate_args(&schema, &json!({"city": "Oslo", "days": 3, "unit": null})).is_empty()
+        );
+
+        let errors = validate_args(&schema, &json!({"days": "3", "unit": "kelvin"}));
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "$: missing required field `city`",
+                "$.days: expected integer, got string",
+                "$.unit: expected one of \"celsius\", \"fahrenheit\", got \"kelvin\"",
+            ]
+        );
+
+        let list = json!({"type": "array", "items": {"type": "number"}});
+        assert_eq!(
+            validate_args(&list, &json!([1, 2.5, true])),
+            [ArgumentError {
+                path: "$[2]".to_string(),
+                kind: ArgumentErrorKind::WrongType {
+                    expected: "number".to_string(),
+                    actual: "boolean".to_string(),
+                },
+            }]
+        );
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct Node {
+        children: Vec<Node>,
+    }
+
+    #[derive(Clone)]
+    struct TreeTool;
+
+    #[async_trait]
+    impl Tool for TreeTool {
+        type Input = Node;
+        type Output = String;
+        type Error = String;
+
+        fn name(&self) -> String {
+            "tree".to_string()
+        }
+
+        fn validates_input(&self) -> bool {
+            true
+        }
+
+        async fn invoke(&self, _input: Self::Input) -> Result<Self::Output, Self::Error> {
+            Ok("leaf".to_string())
+        }
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_input_schema_errors_are_reported() {
+        assert!(matches!(
+            Tool::input_schema(&TreeTool),
+            Err(SchemaError::RecursiveType(name)) if name == "Node"
+        ));
+        assert_eq!(Tool::input_schema(&EmptyParamsTool).unwrap(), None);
+
+        let tools = ToolBox::default();
+        tools.add(TreeTool);
+        assert!(tools.declarations().is_err());
+        assert!(serde_json::to_value(&tools).is_err());
+        let response = tools
+            .invoke(FunctionCall {
+                name: "tree".to_string(),
+                args: Some(json!({"children": []})),
+            })
+            .await;
+        assert_eq!(
+            response.response,
+            json!("Failed to generate input schema: Type Node is recursive, which Gemini schemas can't express")
+        );
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_invoke_validates_args() {
+        let tools = ToolBox::default();
+        tools.add(ForecastTool);
+
+        let response = tools
+            .invoke(FunctionCall {
+                name: "forecast".to_string(),
+                args: Some(json!({"city": 7})),
+            })
+            .await;
+        assert_eq!(
+            response.response,
+            json!("Invalid arguments: $: missing required field `days`; $.city: expected string, got integer")
+        );
+
+        let response = tools
+            .invoke(FunctionCall {
+                name: "forecast".to_string(),
+                args: Some(json!({"city": "Oslo", "days": 1})),
+            })
+            .await;
+        assert_eq!(response.response, json!("sunny"));
+    }
+
+    /// Fails with the attempt number until `succeed_on`, counting attempts across clones.
+    #[derive(Clone)]
+    struct FlakyTool {
+        attempts: Arc<std::sync::atomic::AtomicU32>,
+        succeed_on: u32,
+    }
+
+    #[async_trait]
+    impl Tool for FlakyTool {
+        type Input = ();
+        type Output = String;
+        type Error = String;
+
+        fn name(&self) -> String {
+            "flaky".to_string()
+        }
+
+        async fn invoke(&self, _input: Self::Input) -> Result<Self::Output, Self::Error> {
+            let attempt = self
+                .attempts
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                + 1;
+            if attempt >= self.succeed_on {
+                Ok("done".to_string())
+            } else if attempt == 1 {
+                Err("unavailable".to_string())
+            } else {
+                Err("forbidden".to_string())
+            }
+        }
+    }
+
+    fn fast_retry(max_attempts: u32) -> ToolRetry<String> {
+        ToolRetry::new(max_attempts).backoff(Backoff {
+            initial_delay: std::time::Duration::from_millis(1),
+            ..Backoff::default()
+        })
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_tool_retry() {
+        let call = FunctionCall {
+            name: "flaky".to_string(),
+            args: None,
+        };
+
+        let tool = FlakyTool {
+            attempts: Arc::default(),
+            succeed_on: 3,
+        };
+        let tools = ToolBox::default();
+        tools.add_with_retry(tool.clone(), fast_retry(3));
+        assert_eq!(tools.invoke(call.clone()).await.response, json!("done"));
+        assert_eq!(tool.attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let tool = FlakyTool {
+            attempts: Arc::default(),
+            succeed_on: 3,
+        };
+        tools.add_with_retry(
+            tool.clone(),
+            fast_retry(3).retry_if(|e: &String| e == "unavailable"),
+        );
+        assert_eq!(
+            tools.invoke(call.clone()).await.response,
+            json!("forbidden")
+        );
+        assert_eq!(tool.attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let tool = FlakyTool {
+            attempts: Arc::default(),
+            succeed_on: 5,
+        };
+        tools.add_with_retry(tool.clone(), fast_retry(2));
+        assert_eq!(tools.invoke(call).await.response, json!("forbidden"));
+        assert_eq!(tool.attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_limit_response() {
+        let small = json!({"ok": true});
+        assert_eq!(limit_response(small.clone(), 1000), small);
+
+        let text = json!("é".repeat(1000));
+        let limited = limit_response(text, 400);
+        assert!(serialized_len(&limited) <= 400);
+        assert!(limited["truncated"]
+            .as_str()
+            .unwrap()
+            .starts_with("Output truncated from 2002 to about"));
+        assert!(limited["content"].as_str().unwrap().starts_with("éé"));
+
+        let rows =
+            json!({"total": 500, "rows": (0..500).map(|i| json!({"id": i})).collect::<Vec<_>>()});
+        let limited = limit_response(rows, 600);
+        assert!(serialized_len(&limited) <= 600);
+        assert_eq!(limited["content"]["total"], 500);
+        let kept = limited["content"]["rows"].as_array().unwrap();
+        assert!(!kept.is_empty());
+        assert_eq!(kept[0], json!({"id": 0}));
+
+        // Items are kept in order until one doesn't fit, which is shortened and ends the array.
+        let strings = json!(["a".repeat(50), "b".repeat(50), "c".repeat(50)]);
+        let shortened = truncate_value(strings, 80);
+        assert_eq!(serialized_len(&shortened), 80);
+        assert_eq!(shortened, json!(["a".repeat(50), "b".repeat(23)]));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_invoke_limits_response_size() {
+        let tools = ToolBox::default().max_response_bytes(200);
+        tools.add(EmptyParamsTool);
+        let response = tools
+            .invoke(FunctionCall {
+                name: "empty_params_tool".to_string(),
+                args: None,
+            })
+            .await;
+        assert_eq!(response.response, json!("called"));
+
+        let tools = tools.filter_by_names(&["empty_params_tool"]);
+        assert_eq!(tools.max_response_bytes, Some(200));
+
+        // Quotes, backslashes and control characters take twice their size or more.
+        let escaped = "\"\\\n\u{1}".repeat(100);
+        let tools = ToolBox::default().max_response_bytes(300);
+        tools.add(EchoTool);
+        for args in [
+            json!({"text": escaped}),
+            json!({"text": "x", "nested": {"lines": [escaped, escaped]}}),
+            json!({"text": "x", escaped.clone(): escaped}),
+        ] {
+            let response = tools
+                .invoke(FunctionCall {
+                    name: "echo".to_string(),
+                    args: Some(args),
+                })
+                .await;
+            assert!(response.response.get("truncated").is_some());
+            assert!(serde_json::to_vec(&response.response).unwrap().len() <= 300);
+        }
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_invoke_tool_without_args() {
+        let tools = ToolBox::default();
+        tools.add(NoArgsTool);
+        tools.add(EmptyParamsTool);
+
+        for name in ["no_args_tool", "empty_params_tool"] {
+            for args in [None, Some(json!({}))] {
+                let response = tools
+                    .invoke(FunctionCall {
+                        name: name.to_string(),
+                        args,
+                    })
+                    .await;
+                assert_eq!(response.name, name);
+                assert_eq!(response.response, json!("called"));
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Versioned, serializable conversation history, so chats can be persisted and resumed.
+
+use std::{fmt::Write, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::message::{Content, Part, Role};
+
+/// Format version written by this crate.
+pub const TRANSCRIPT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum TranscriptError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("Unsupported transcript version {0}, expected at most {TRANSCRIPT_VERSION}")]
+    UnsupportedVersion(u32),
+}
+
+/// A conversation: its turns plus the model and system instruction it was held with.
+///
+/// ```no_run
+/// # use gemini_ox::messages::{message::Content, transcript::Transcript};
+/// let mut transcript = Transcript::new("gemini-1.5-flash");
+/// transcript.push(&Content::from("Hello!"));
+/// transcript.save_json("chat.json")?;
+///
+/// let transcript = Transcript::load_json("chat.json")?;
+/// # Ok::<(), gemini_ox::messages::transcript::TranscriptError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content<'static>>,
+    #[serde(default)]
+    pub contents: Vec<Content<'static>>,
+}
+
+impl Default for Transcript {
+    fn default() -> Self {
+        Self {
+            version: TRANSCRIPT_VERSION,
+            model: None,
+            system_instruction: None,
+            contents: Vec::new(),
+        }
+    }
+}
+
+impl Transcript {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: Some(model.into()),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_system_instruction(mut self, instruction: &Content<'_>) -> Self {
+        self.system_instruction = Some(instruction.to_owned());
+        self
+    }
+
+    /// Appends a turn, copying any borrowed data.
+    pub fn push(&mut self, content: &Content<'_>) {
+        self.contents.push(content.to_owned());
+    }
+
+    pub fn to_json(&self) -> Result<String, TranscriptError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a transcript, rejecting versions newer than this crate understands.
+    pub fn from_json(json: &str) -> Result<Self, TranscriptError> {
+        let transcript: Self = serde_json::from_str(json)?;
+        if transcript.version > TRANSCRIPT_VERSION {
+            return Err(TranscriptError::UnsupportedVersion(transcript.version));
+        }
+        Ok(transcript)
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) -> Result<(), TranscriptError> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load_json(path: impl AsRef<Path>) -> Result<Self, TranscriptError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Renders the system instruction and all turns, see [`render_markdown`].
+    #[must_use]
+    pub fn to_markdown(&self) -> String {
+        render_markdown(self.system_instruction.iter().chain(&self.contents))
+    }
+}
+
+/// Renders turns as a readable markdown transcript, for logging and debugging agent sessions.
+///
+/// Each turn gets a heading with its role; function calls and responses are shown with their
+/// JSON payloads and media is replaced by a placeholder naming its type.
+pub fn render_markdown<'a, 'b: 'a>(contents: impl IntoIterator<Item = &'a Content<'b>>) -> String {
+    let mut out = String::new();
+    for content in contents {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let role = match content.role {
+            Role::User => "User",
+            Role::Model => "Model",
+            Role::System => "System",
+        };
+        let _ = writeln!(out, "### {role}");
+        for part in content.iter() {
+            out.push('\n');
+            render_part(&mut out, part);
+        }
+    }
+    out
+}
+
+fn render_part(out: &mut String, part: &Part<'_>) {
+    match part {
+        Part::Text(text) => {
+            let _ = writeln!(out, "{}", text.0.trim_end());
+        }
+        Part::FunctionCall(call) => {
+            let _ = writeln!(out, "**Function call:** `{}`", call.name);
+            if let Some(args) = &call.args {
+                render_json(out, args);
+            }
+        }
+        Part::FunctionResponse(response) => {
+            let _ = writeln!(out, "**Function response:** `{}`", response.name);
+            render_json(out, &response.response);
+        }
+        Part::InlineData(blob) => {
+            let _ = writeln!(out, "*[inline {}]*", blob.mime_type);
+        }
+        Part::FileData(file) => {
+            let mime_type = file.mime_type.as_deref().unwrap_or("file");
+            let _ = writeln!(out, "*[{mime_type}: {}]*", file.file_uri);
+        }
+    }
+}
+
+fn render_json(out: &mut String, value: &serde_json::Value) {
+    let json = serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string());
+    let _ = writeln!(out, "```json\n{json}\n```");
+}
+
+impl<'a> FromIterator<Content<'a>> for Transcript {
+    fn from_iter<T: IntoIterator<Item = Content<'a>>>(iter: T) -> Self {
+        Self {
+            contents: iter.into_iter().map(|content| content.to_owned()).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::messages::message::{Blob, FunctionCall, FunctionResponse};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_transcript_round_trip() {
+        let question = String::from("What is Rust?");
+        let mut transcript = Transcript::new("gemini-1.5-flash")
+            .with_system_instruction(&Content::system(["Be brief."]));
+        transcript.push(&Content::from(&question));
+        transcript.push(
+            &Content::builder()
+                .role(Role::Model)
+                .parts([Part::from("A language.")])
+                .build(),
+        );
+
+        let json = transcript.to_json().unwrap();
+        assert_eq!(Transcript::from_json(&json).unwrap(), transcript);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()["version"],
+            TRANSCRIPT_VERSION
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_newer_version_is_rejected() {
+        let json = json!({"version": TRANSCRIPT_VERSION + 1, "contents": []}).to_string();
+        assert!(matches!(
+            Transcript::from_json(&json),
+            Err(TranscriptError::UnsupportedVersion(v)) if v == TRANSCRIPT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_render_markdown() {
+        let transcript: Transcript = [
+            Content::from("Weather in Paris?"),
+            Content::builder()
+                .role(Role::Model)
+                .parts([Part::FunctionCall(FunctionCall {
+                    name: "get_weather".to_string(),
+                    args: Some(json!({"city": "Paris"})),
+                })])
+                .build(),
+            Content::builder()
+                .role(Role::User)
+                .parts([
+                    Part::FunctionResponse(FunctionResponse {
+                        name: "get_weather".to_string(),
+                        response: json!({"temp": 21}),
+                    }),
+                    Part::InlineData(Blob::new("image/png", [0u8; 4])),
+                ])
+                .build(),
+        ]
+        .into_iter()
+        .collect();
+
+        let expected = "\
+### User
+
+Weather in Paris?
+
+### Model
+
+**Function call:** `get_weather`
+```json
+{
+  \"city\": \"Paris\"
+}
+```
+
+### User
+
+**Function response:** `get_weather`
+```json
+{
+  \"temp\": 21
+}
+```
+
+*[inline image/png]*
+";
+        assert_eq!(transcript.to_markdown(), expected);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("gemini-ox-chat-{}.json", std::process::id()));
+        let transcript: Transcript = [Content::from("Hi")].into_iter().collect();
+
+        transcript.save_json(&path).unwrap();
+        assert_eq!(Transcript::load_json(&path).unwrap(), transcript);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
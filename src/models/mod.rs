@@ -0,0 +1,438 @@
+//! Model metadata from `models.get` and `models.list`, and checks of requests against a
+//! model's capabilities so unsupported requests fail before they cost quota.
+
+use std::fmt;
+
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{ApiRequestError, Gemini, BASE_URL};
+
+/// How a model is addressed in resource paths. Parsed from the model names requests take,
+/// and rendered as the resource name the API expects.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ModelResource {
+    /// A base model such as `gemini-1.5-flash`, rendered as `models/gemini-1.5-flash`.
+    Base(String),
+    /// A tuned model, rendered as `tunedModels/*`.
+    Tuned(String),
+    /// Any other fully qualified resource name, used as is.
+    Name(String),
+}
+
+impl ModelResource {
+    /// Accepts bare base model ids as well as `models/*`, `tunedModels/*` and other
+    /// resource names.
+    #[must_use]
+    pub fn parse(model: &str) -> Self {
+        if let Some(id) = model.strip_prefix("models/") {
+            ModelResource::Base(id.to_string())
+        } else if let Some(id) = model.strip_prefix("tunedModels/") {
+            ModelResource::Tuned(id.to_string())
+        } else if model.contains('/') {
+            ModelResource::Name(model.to_string())
+        } else {
+            ModelResource::Base(model.to_string())
+        }
+    }
+}
+
+impl fmt::Display for ModelResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelResource::Base(id) => write!(f, "models/{id}"),
+            ModelResource::Tuned(id) => write!(f, "tunedModels/{id}"),
+            ModelResource::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    /// Resource name, `models/*`.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub input_token_limit: u32,
+    #[serde(default)]
+    pub output_token_limit: u32,
+    /// API methods the model can be called with, e.g. `generateContent`, `countTokens`.
+    #[serde(default)]
+    pub supported_generation_methods: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListModelsResponse {
+    #[serde(default)]
+    pub models: Vec<ModelInfo>,
+    /// Pass to [`Gemini::list_models`] to fetch the next page; `None` on the last page.
+    pub next_page_token: Option<String>,
+}
+
+impl ModelInfo {
+    /// The model name without the `models/` prefix.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        self.name.strip_prefix("models/").unwrap_or(&self.name)
+    }
+
+    #[must_use]
+    pub fn supports_method(&self, method: &str) -> bool {
+        self.supported_generation_methods
+            .iter()
+            .any(|supported| supported == method)
+    }
+
+    /// The API doesn't report tool support, so this is derived from the model family:
+    /// embedding, AQA and the Gemini 1.0 vision models don't support function calling.
+    #[must_use]
+    pub fn supports_tools(&self) -> bool {
+        let id = self.id();
+        !(id.contains("embedding")
+            || id.starts_with("aqa")
+            || id.starts_with("gemini-pro-vision")
+            || id.starts_with("gemini-1.0-pro-vision"))
+    }
+
+    /// The API doesn't report vision support, so this is derived from the model family:
+    /// the Gemini 1.0 Pro text models, embedding and AQA models accept no images or video.
+    #[must_use]
+    pub fn supports_vision(&self) -> bool {
+        let id = self.id();
+        let text_only_pro = (id.starts_with("gemini-pro") || id.starts_with("gemini-1.0-pro"))
+            && !id.contains("vision");
+        !(text_only_pro || id.contains("embedding") || id.starts_with("aqa"))
+    }
+
+    /// Gemini 1.0 models return a single candidate; later ones up to 8.
+    #[must_use]
+    pub fn max_candidate_count(&self) -> i32 {
+        let id = self.id();
+        if id.starts_with("gemini-pro") || id.starts_with("gemini-1.0") {
+            1
+        } else {
+            8
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CapabilityError {
+    #[error("Model {model} does not support {method}")]
+    UnsupportedMethod { model: String, method: String },
+    #[error("Model {0} does not support tools")]
+    ToolsUnsupported(String),
+    #[error("Model {0} does not accept images or video")]
+    VisionUnsupported(String),
+    #[error("Model {model} supports at most {max} candidates, got {requested}")]
+    UnsupportedCandidateCount {
+        model: String,
+        requested: i32,
+        max: i32,
+    },
+    #[error(
+        "Prompt of about {estimated} tokens exceeds the input limit of {limit} of model {model}"
+    )]
+    InputTokenLimitExceeded {
+        model: String,
+        estimated: u32,
+        limit: u32,
+    },
+}
+
+impl Gemini {
+    /// Fetches the metadata of `model`, with or without the `models/` prefix.
+    pub async fn get_model(&self, model: &str) -> Result<ModelInfo, ApiRequestError> {
+        let url = format!(
+            "{}/{}/{}",
+            BASE_URL,
+            self.api_version,
+            ModelResource::parse(model)
+        );
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    /// Lists one page of models; pass the returned `next_page_token` to get the next one.
+    pub async fn list_models(
+        &self,
+        page_token: Option<&str>,
+    ) -> Result<ListModelsResponse, ApiRequestError> {
+        let url = format!("{}/{}/models", BASE_URL, self.api_version);
+        let mut request = self.client.get(&url);
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    /// Lists all models, e.g. for a model picker, fetching the next page only once the
+    /// previous one is consumed.
+    pub fn list_models_stream(
+        &self,
+    ) -> impl Stream<Item = Result<ModelInfo, ApiRequestError>> + '_ {
+        crate::paginate(move |page_token| async move {
+            let page = self.list_models(page_token.as_deref()).await?;
+            Ok((page.models, page.next_page_token))
+        })
+    }
+
+    /// URL of calling `method` (`generateContent`, `embedContent`, ...) on `model`.
+    pub(crate) fn model_url(&self, model: &str, method: &str) -> String {
+        format!(
+            "{}/{}/{}:{}",
+            BASE_URL,
+            self.api_version,
+            ModelResource::parse(model),
+            method
+        )
+    }
+
+    /// Like [`Gemini::get_model`], but remembers the result for the lifetime of the client,
+    /// as model metadata rarely changes.
+    pub async fn model_info(&self, model: &str) -> Result<ModelInfo, ApiRequestError> {
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        if let Some(info) = self.model_infos.lock().unwrap().get(model) {
+            return Ok(info.clone());
+        }
+        let info = self.get_model(model).await?;
+        self.model_infos
+            .lock()
+            .unwrap()
+            .insert(model.to_string(), info.clone());
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        messages::message::{Blob, Content, Part, Role},
+        transport::tests::{MockTransport, PagedTransport},
+        GenerationConfig,
+    };
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    fn model(name: &str, input_token_limit: u32) -> ModelInfo {
+        ModelInfo {
+            name: format!("models/{name}"),
+            input_token_limit,
+            supported_generation_methods: vec![
+                "generateContent".to_string(),
+                "countTokens".to_string(),
+            ],
+            ..ModelInfo::default()
+        }
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_model_resource() {
+        for (model, resource) in [
+            ("gemini-1.5-flash", "models/gemini-1.5-flash"),
+            ("models/gemini-1.5-flash", "models/gemini-1.5-flash"),
+            ("tunedModels/my-model-123", "tunedModels/my-model-123"),
+            (
+                "projects/p/locations/l/endpoints/e",
+                "projects/p/locations/l/endpoints/e",
+            ),
+        ] {
+            assert_eq!(ModelResource::parse(model).to_string(), resource);
+        }
+        assert_eq!(
+            ModelResource::parse("tunedModels/my-model-123"),
+            ModelResource::Tuned("my-model-123".to_string())
+        );
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_tuned_model_url() {
+        let transport = MockTransport::json(200, &json!({"candidates": []}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        gemini
+            .generate_content()
+            .contents("hello")
+            .model("tunedModels/my-model-123")
+            .build()
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            transport.requests.lock().unwrap()[0].url().path(),
+            "/v1beta/tunedModels/my-model-123:generateContent"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_check_capabilities() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let image = Content::builder()
+            .role(Role::User)
+            .parts([Part::from(Blob::new("image/png", b"png"))])
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents([image])
+            .generation_config(GenerationConfig::builder().candidate_count(Some(2)).build())
+            .model("gemini-pro")
+            .build();
+
+        assert_eq!(
+            request.check_capabilities(&model("gemini-1.5-flash", 1_000_000)),
+            Ok(())
+        );
+        assert_eq!(
+            request.check_capabilities(&model("gemini-pro", 30_720)),
+            Err(CapabilityError::VisionUnsupported("gemini-pro".to_string()))
+        );
+        assert_eq!(
+            request.check_capabilities(&model("gemini-pro-vision", 12_288)),
+            Err(CapabilityError::UnsupportedCandidateCount {
+                model: "gemini-pro-vision".to_string(),
+                requested: 2,
+                max: 1,
+            })
+        );
+        assert!(matches!(
+            request.check_capabilities(&model("gemini-1.5-flash", 1)),
+            Err(CapabilityError::InputTokenLimitExceeded { limit: 1, .. })
+        ));
+
+        let embedding = ModelInfo {
+            supported_generation_methods: vec!["embedContent".to_string()],
+            ..model("text-embedding-004", 2048)
+        };
+        assert_eq!(
+            request.check_capabilities(&embedding),
+            Err(CapabilityError::UnsupportedMethod {
+                model: "text-embedding-004".to_string(),
+                method: "generateContent".to_string(),
+            })
+        );
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_model_info_is_cached() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "name": "models/gemini-1.5-flash",
+                "inputTokenLimit": 1_048_576,
+                "outputTokenLimit": 8192,
+                "supportedGenerationMethods": ["generateContent", "countTokens"]
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let info = gemini.model_info("models/gemini-1.5-flash").await.unwrap();
+        assert_eq!(info.input_token_limit, 1_048_576);
+        assert!(info.supports_method("countTokens"));
+        gemini.model_info("gemini-1.5-flash").await.unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url().path(), "/v1beta/models/gemini-1.5-flash");
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_list_models_stream() {
+        use futures::TryStreamExt;
+
+        let transport = PagedTransport::new([
+            (
+                None,
+                json!({
+                    "models": [{"name": "models/gemini-1.5-flash"}],
+                    "nextPageToken": "next"
+                }),
+            ),
+            (
+                Some("next"),
+                json!({"models": [{"name": "models/gemini-1.5-pro"}]}),
+            ),
+        ]);
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let models: Vec<_> = gemini.list_models_stream().try_collect().await.unwrap();
+
+        let ids: Vec<_> = models.iter().map(ModelInfo::id).collect();
+        assert_eq!(ids, ["gemini-1.5-flash", "gemini-1.5-pro"]);
+        assert_eq!(
+            *transport.page_tokens.lock().unwrap(),
+            [None, Some("next".to_string())]
+        );
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_validated_request_fails_before_sending() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "name": "models/gemini-1.5-flash",
+                "inputTokenLimit": 2,
+                "supportedGenerationMethods": ["generateContent"]
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let err = gemini
+            .generate_content()
+            .contents("a prompt well over two tokens long")
+            .model("gemini-1.5-flash")
+            .validate_capabilities(true)
+            .build()
+            .send()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            ApiRequestError::UnsupportedByModel(CapabilityError::InputTokenLimitExceeded { .. })
+        ));
+        // Only the model lookup went out, not the generation request.
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+}
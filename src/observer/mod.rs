@@ -0,0 +1,179 @@
+//! Hooks for feeding request metrics (Prometheus, StatsD, ...) without parsing logs.
+//!
+//! Register an [`Observer`] with `Gemini::builder().observer(...)`; it is notified when a call
+//! starts, completes or fails, and when token usage is reported.
+
+use std::{future::Future, time::Duration};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::circuit_breaker::CircuitState;
+use crate::{messages::UsageMetadata, ApiRequestError, Gemini};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Operation {
+    GenerateContent,
+    StreamGenerateContent,
+    UploadFile,
+}
+
+/// The call an observer event belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestInfo<'a> {
+    pub operation: Operation,
+    pub model: Option<&'a str>,
+}
+
+/// Receives request lifecycle events. Every method defaults to doing nothing.
+///
+/// Latencies are `None` on wasm32, where no monotonic clock is available. For streams, a
+/// request completes once the response starts streaming and tokens are reported when it ends.
+pub trait Observer: Send + Sync {
+    fn on_request_started(&self, _request: &RequestInfo<'_>) {}
+    fn on_request_completed(&self, _request: &RequestInfo<'_>, _latency: Option<Duration>) {}
+    fn on_request_failed(
+        &self,
+        _request: &RequestInfo<'_>,
+        _error: &ApiRequestError,
+        _latency: Option<Duration>,
+    ) {
+    }
+    fn on_tokens_used(&self, _request: &RequestInfo<'_>, _usage: &UsageMetadata) {}
+    /// The client's circuit breaker changed to `state`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn on_circuit_state_changed(&self, _state: CircuitState) {}
+}
+
+impl Gemini {
+    /// Runs `future`, reporting its start and outcome to the observer.
+    pub(crate) async fn observed<T>(
+        &self,
+        request: RequestInfo<'_>,
+        future: impl Future<Output = Result<T, ApiRequestError>>,
+    ) -> Result<T, ApiRequestError> {
+        let Some(observer) = &self.observer else {
+            return future.await;
+        };
+        observer.on_request_started(&request);
+        #[cfg(not(target_arch = "wasm32"))]
+        let started = std::time::Instant::now();
+
+        let result = future.await;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let latency = Some(started.elapsed());
+        #[cfg(target_arch = "wasm32")]
+        let latency = None;
+        match &result {
+            Ok(_) => observer.on_request_completed(&request, latency),
+            Err(e) => observer.on_request_failed(&request, e, latency),
+        }
+        result
+    }
+
+    pub(crate) fn observe_usage(&self, request: &RequestInfo<'_>, usage: Option<&UsageMetadata>) {
+        if let (Some(observer), Some(usage)) = (&self.observer, usage) {
+            observer.on_tokens_used(request, usage);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Observer for Arc<RecordingObserver> {
+        fn on_request_started(&self, request: &RequestInfo<'_>) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("started {}", request.operation));
+        }
+
+        fn on_request_completed(&self, request: &RequestInfo<'_>, latency: Option<Duration>) {
+            assert!(latency.is_some());
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("completed {}", request.operation));
+        }
+
+        fn on_request_failed(
+            &self,
+            request: &RequestInfo<'_>,
+            _error: &ApiRequestError,
+            _latency: Option<Duration>,
+        ) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("failed {}", request.operation));
+        }
+
+        fn on_tokens_used(&self, request: &RequestInfo<'_>, usage: &UsageMetadata) {
+            self.events.lock().unwrap().push(format!(
+                "tokens {} {} {}",
+                request.model.unwrap_or_default(),
+                usage.prompt_token_count,
+                usage.total_token_count
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observer_events() {
+        let observer = Arc::new(RecordingObserver::default());
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": "Hi!"}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }],
+                "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 2, "totalTokenCount": 5}
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport)
+            .observer(Arc::clone(&observer))
+            .build();
+
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-flash")
+            .build();
+        request.send().await.unwrap();
+
+        let failing = Gemini::builder()
+            .api_key("test")
+            .transport(MockTransport::new(429, ""))
+            .observer(Arc::clone(&observer))
+            .build();
+        let request = failing.generate_content().model("gemini-1.5-flash").build();
+        request.send().await.unwrap_err();
+
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            [
+                "started generate_content",
+                "completed generate_content",
+                "tokens gemini-1.5-flash 3 5",
+                "started generate_content",
+                "failed generate_content",
+            ]
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Long-running operations, as returned by batch jobs, model tuning and video generation,
+//! and helpers to wait for them to finish.
+
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{ApiRequestError, Gemini, BASE_URL};
+
+/// The `google.rpc.Status` an operation failed with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    #[serde(default)]
+    pub code: i32,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<Value>,
+}
+
+impl From<Status> for ApiRequestError {
+    fn from(status: Status) -> Self {
+        ApiRequestError::InvalidRequestError {
+            code: Some(status.code.to_string()),
+            details: Box::new(Value::Array(status.details)),
+            message: status.message,
+            status: None,
+            http: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Operation {
+    pub name: String,
+    pub metadata: Option<Value>,
+    #[serde(default)]
+    pub done: bool,
+    pub error: Option<Status>,
+    pub response: Option<Value>,
+}
+
+impl Operation {
+    /// The response of a finished operation, or the error it failed with.
+    ///
+    /// Returns `Ok(None)` while the operation is still running.
+    pub fn into_result<T: DeserializeOwned>(self) -> Result<Option<T>, ApiRequestError> {
+        if let Some(error) = self.error {
+            return Err(error.into());
+        }
+        match self.response {
+            Some(response) if self.done => Ok(Some(serde_json::from_value(response)?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn metadata_as<T: DeserializeOwned>(&self) -> Result<Option<T>, ApiRequestError> {
+        Ok(self
+            .metadata
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()?)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListOperationsResponse {
+    #[serde(default)]
+    pub operations: Vec<Operation>,
+    pub next_page_token: Option<String>,
+}
+
+/// A resource that can be polled until it finishes.
+pub trait LongRunning: DeserializeOwned {
+    fn is_done(&self) -> bool;
+}
+
+impl LongRunning for Operation {
+    fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Polling schedule for [`Gemini::wait_for`]: exponential backoff capped at `max_delay`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Total time spent waiting before giving up; `None` waits indefinitely.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_mins(1),
+            multiplier: 2.0,
+            timeout: None,
+        }
+    }
+}
+
+impl Backoff {
+    pub(crate) fn delays(&self) -> impl Iterator<Item = Duration> + '_ {
+        std::iter::successors(Some(self.initial_delay), |delay| {
+            Some(delay.mul_f64(self.multiplier).min(self.max_delay))
+        })
+    }
+}
+
+impl Gemini {
+    /// Fetches an operation by its full name, e.g. `tunedModels/my-model/operations/123`.
+    pub async fn get_operation(&self, name: &str) -> Result<Operation, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let res = self.send_checked(self.client.get(&url)).await?;
+        crate::read_json(res).await
+    }
+
+    /// Lists one page of the operations in `collection`, e.g. `tunedModels/my-model/operations`.
+    pub async fn list_operations(
+        &self,
+        collection: &str,
+        page_token: Option<&str>,
+    ) -> Result<ListOperationsResponse, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, collection);
+        let mut request = self.client.get(&url);
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        let res = self.send_checked(request).await?;
+        crate::read_json(res).await
+    }
+
+    pub async fn cancel_operation(&self, name: &str) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}:cancel", BASE_URL, self.api_version, name);
+        self.send_checked(self.client.post(&url)).await?;
+        Ok(())
+    }
+
+    pub async fn delete_operation(&self, name: &str) -> Result<(), ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        self.send_checked(self.client.delete(&url)).await?;
+        Ok(())
+    }
+
+    /// Polls the resource `name` (an [`Operation`], a [`crate::batches::BatchJob`], ...)
+    /// until it is done, sleeping between polls according to `backoff`.
+    pub async fn wait_for<T: LongRunning>(
+        &self,
+        name: &str,
+        backoff: &Backoff,
+    ) -> Result<T, ApiRequestError> {
+        let url = format!("{}/{}/{}", BASE_URL, self.api_version, name);
+        let mut waited = Duration::ZERO;
+        let mut delays = backoff.delays();
+        loop {
+            let resource: T = self
+                .send_checked(self.client.get(&url))
+                .await?
+                .json()
+                .await?;
+            if resource.is_done() {
+                return Ok(resource);
+            }
+            let delay = delays.next().unwrap_or(backoff.max_delay);
+            if backoff
+                .timeout
+                .is_some_and(|timeout| waited + delay > timeout)
+            {
+                return Err(ApiRequestError::OperationTimeout(name.to_string()));
+            }
+            futures_timer::Delay::new(delay).await;
+            waited += delay;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_backoff_delays() {
+        let backoff = Backoff {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+            timeout: None,
+        };
+        let delays: Vec<u64> = backoff.delays().take(5).map(|d| d.as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_failed_operation_into_result() {
+        let operation: Operation = serde_json::from_value(json!({
+            "name": "operations/1",
+            "done": true,
+            "error": {"code": 3, "message": "invalid video"}
+        }))
+        .unwrap();
+        let err = operation.into_result::<Value>().unwrap_err();
+        assert!(matches!(
+            err,
+            ApiRequestError::InvalidRequestError { ref code, ref message, .. }
+                if code.as_deref() == Some("3") && message == "invalid video"
+        ));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_wait_for_times_out() {
+        let transport = MockTransport::json(200, &json!({"name": "operations/1", "done": false}));
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let backoff = Backoff {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(4),
+            multiplier: 2.0,
+            timeout: Some(Duration::from_millis(10)),
+        };
+
+        let err = gemini
+            .wait_for::<Operation>("operations/1", &backoff)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, ApiRequestError::OperationTimeout(ref name) if name == "operations/1")
+        );
+        // Polled after waiting 0, 1, 3 and 7 ms; the next 4 ms delay would exceed 10 ms.
+        assert_eq!(transport.requests.lock().unwrap().len(), 4);
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_wait_for_done() {
+        let transport = MockTransport::json(
+            200,
+            &json!({"name": "operations/1", "done": true, "response": {"ok": true}}),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport)
+            .build();
+
+        let operation: Operation = gemini
+            .wait_for("operations/1", &Backoff::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            operation.into_result::<Value>().unwrap(),
+            Some(json!({"ok": true}))
+        );
+    }
+}
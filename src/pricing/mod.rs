@@ -0,0 +1,194 @@
+//! Per-model token prices for estimating the cost of calls.
+//!
+//! The built-in table follows the published paid-tier prices for prompts up to the first
+//! context tier. Prices change, so treat the numbers as estimates and override them with
+//! [`PriceTable::insert`] where exact figures matter.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{messages::UsageMetadata, usage::TokenUsage};
+
+/// Prices of a single model in USD per one million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    /// Price of prompt tokens served from cached content.
+    pub cached_input: f64,
+}
+
+impl ModelPricing {
+    #[must_use]
+    pub const fn new(input: f64, output: f64, cached_input: f64) -> Self {
+        Self {
+            input,
+            output,
+            cached_input,
+        }
+    }
+
+    /// Cost in USD. Thinking tokens are billed as output tokens.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let uncached = usage.prompt_tokens.saturating_sub(usage.cached_tokens);
+        let output = usage.candidates_tokens + usage.thinking_tokens;
+        (uncached as f64 * self.input
+            + usage.cached_tokens as f64 * self.cached_input
+            + output as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// Maps model names to their prices.
+///
+/// Lookups use the longest matching prefix, so versioned names such as
+/// `gemini-1.5-flash-002` or `models/gemini-2.5-pro` resolve to their base model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceTable {
+    models: HashMap<String, ModelPricing>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        [
+            ("gemini-2.5-pro", ModelPricing::new(1.25, 10.0, 0.31)),
+            ("gemini-2.5-flash", ModelPricing::new(0.30, 2.50, 0.075)),
+            (
+                "gemini-2.5-flash-lite",
+                ModelPricing::new(0.10, 0.40, 0.025),
+            ),
+            ("gemini-2.0-flash", ModelPricing::new(0.10, 0.40, 0.025)),
+            (
+                "gemini-2.0-flash-lite",
+                ModelPricing::new(0.075, 0.30, 0.018_75),
+            ),
+            ("gemini-1.5-pro", ModelPricing::new(1.25, 5.0, 0.3125)),
+            ("gemini-1.5-flash", ModelPricing::new(0.075, 0.30, 0.018_75)),
+            ("gemini-1.5-flash-8b", ModelPricing::new(0.0375, 0.15, 0.01)),
+        ]
+        .into_iter()
+        .collect()
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, ModelPricing)> for PriceTable {
+    fn from_iter<I: IntoIterator<Item = (S, ModelPricing)>>(iter: I) -> Self {
+        Self {
+            models: iter
+                .into_iter()
+                .map(|(model, pricing)| (model.into(), pricing))
+                .collect(),
+        }
+    }
+}
+
+impl PriceTable {
+    /// An empty table, for callers that want to supply every price themselves.
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            models: HashMap::new(),
+        }
+    }
+
+    /// Adds or overrides the prices of `model`.
+    pub fn insert(&mut self, model: impl Into<String>, pricing: ModelPricing) -> &mut Self {
+        self.models.insert(model.into(), pricing);
+        self
+    }
+
+    #[must_use]
+    pub fn get(&self, model: &str) -> Option<&ModelPricing> {
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        self.models
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, pricing)| pricing)
+    }
+
+    /// Estimated cost in USD, or `None` if `model` has no known price.
+    #[must_use]
+    pub fn cost(&self, model: &str, usage: &TokenUsage) -> Option<f64> {
+        self.get(model).map(|pricing| pricing.cost(usage))
+    }
+}
+
+impl UsageMetadata {
+    /// Estimated cost in USD according to the built-in [`PriceTable`].
+    #[must_use]
+    pub fn estimated_cost(&self, model: &str) -> Option<f64> {
+        self.estimated_cost_with(&PriceTable::default(), model)
+    }
+
+    #[must_use]
+    pub fn estimated_cost_with(&self, prices: &PriceTable, model: &str) -> Option<f64> {
+        let mut usage = TokenUsage::default();
+        usage += self;
+        prices.cost(model, &usage)
+    }
+}
+
+impl TokenUsage {
+    /// Estimated cost in USD according to the built-in [`PriceTable`].
+    #[must_use]
+    pub fn estimated_cost(&self, model: &str) -> Option<f64> {
+        PriceTable::default().cost(model, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    fn usage() -> UsageMetadata {
+        UsageMetadata {
+            prompt_token_count: 1_000_000,
+            cached_content_token_count: Some(400_000),
+            candidates_token_count: Some(100_000),
+            thoughts_token_count: Some(100_000),
+            total_token_count: 1_200_000,
+        }
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_longest_prefix_lookup() {
+        let prices = PriceTable::default();
+        assert_eq!(
+            prices.get("models/gemini-2.5-flash-lite-preview"),
+            prices.get("gemini-2.5-flash-lite")
+        );
+        assert_ne!(
+            prices.get("gemini-2.5-flash-001"),
+            prices.get("gemini-2.5-flash-lite")
+        );
+        assert!(prices.get("text-embedding-004").is_none());
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_estimated_cost() {
+        // 600k uncached * 1.25 + 400k cached * 0.31 + 200k output * 10.0
+        let cost = usage().estimated_cost("gemini-2.5-pro").unwrap();
+        assert!((cost - (0.75 + 0.124 + 2.0)).abs() < 1e-9);
+        assert_eq!(usage().estimated_cost("unknown-model"), None);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_overridden_prices() {
+        let mut prices = PriceTable::empty();
+        prices.insert("my-tuned-model", ModelPricing::new(1.0, 2.0, 0.5));
+        let cost = usage()
+            .estimated_cost_with(&prices, "my-tuned-model")
+            .unwrap();
+        assert!((cost - (0.6 + 0.2 + 0.4)).abs() < 1e-9);
+    }
+}
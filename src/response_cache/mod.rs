@@ -0,0 +1,214 @@
+//! Local cache of `generateContent` responses, so identical calls (eval reruns, CI) are
+//! answered without hitting the API.
+//!
+//! Register a cache with `Gemini::builder().response_cache(...)`. Requests are keyed by a
+//! hash of the model and the serialized request body, so any change to the contents, tools
+//! or generation config misses the cache. Only [`GenerateContentRequest::send`] is cached,
+//! not streams.
+//!
+//! [`GenerateContentRequest::send`]: crate::messages::GenerateContentRequest::send
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use crate::messages::GenerateContentResponse;
+
+/// Storage for cached responses. Failing to read or write the cache is not an error; the
+/// request is simply sent.
+#[async_trait]
+pub trait ResponseCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<GenerateContentResponse>;
+    async fn put(&self, key: &str, response: &GenerateContentResponse);
+}
+
+/// Lets a cache be shared with the caller, e.g. to inspect or clear it.
+#[async_trait]
+impl<T: ResponseCache + ?Sized> ResponseCache for Arc<T> {
+    async fn get(&self, key: &str) -> Option<GenerateContentResponse> {
+        (**self).get(key).await
+    }
+
+    async fn put(&self, key: &str, response: &GenerateContentResponse) {
+        (**self).put(key, response).await;
+    }
+}
+
+/// Cache key of a request to `model` with the serialized `body`: a 64-bit FNV-1a hash,
+/// which unlike `DefaultHasher` is stable across Rust versions and so usable on disk.
+pub(crate) fn cache_key(model: &str, body: &str) -> String {
+    let hash = [model.as_bytes(), b"\n", body.as_bytes()]
+        .into_iter()
+        .flatten()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
+
+/// Keeps responses for the lifetime of the process.
+#[derive(Debug, Default)]
+pub struct InMemoryCache {
+    responses: Mutex<HashMap<String, GenerateContentResponse>>,
+}
+
+impl InMemoryCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.responses.lock().unwrap().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl ResponseCache for InMemoryCache {
+    async fn get(&self, key: &str) -> Option<GenerateContentResponse> {
+        self.responses.lock().unwrap().get(key).cloned()
+    }
+
+    async fn put(&self, key: &str, response: &GenerateContentResponse) {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), response.clone());
+    }
+}
+
+/// Stores every response as a JSON file named after its key, e.g. in a directory checked
+/// into the repository or cached between CI runs.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiskCache {
+    /// Caches into `dir`, which is created on the first write.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl ResponseCache for DiskCache {
+    async fn get(&self, key: &str) -> Option<GenerateContentResponse> {
+        let path = self.path(key);
+        let json = crate::unblock(move || std::fs::read(path)).await.ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    async fn put(&self, key: &str, response: &GenerateContentResponse) {
+        let Ok(json) = serde_json::to_vec_pretty(response) else {
+            return;
+        };
+        let (dir, path) = (self.dir.clone(), self.path(key));
+        let _ = crate::unblock(move || {
+            std::fs::create_dir_all(dir)?;
+            std::fs::write(path, json)
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::tests::MockTransport, Gemini};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_cache_key() {
+        assert_eq!(
+            cache_key("gemini-1.5-flash", "{}"),
+            cache_key("gemini-1.5-flash", "{}")
+        );
+        assert_ne!(
+            cache_key("gemini-1.5-flash", "{}"),
+            cache_key("gemini-1.5-pro", "{}")
+        );
+        assert_eq!(cache_key("", "").len(), 16);
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_identical_requests_are_served_from_cache() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": "Hi!"}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }]
+            }),
+        );
+        let cache = Arc::new(InMemoryCache::new());
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .response_cache(Arc::clone(&cache))
+            .build();
+        let request = |prompt: &'static str| {
+            gemini
+                .generate_content()
+                .contents(prompt)
+                .model("gemini-1.5-flash")
+                .build()
+        };
+
+        let first = request("hello").send().await.unwrap();
+        let second = request("hello").send().await.unwrap();
+        request("goodbye").send().await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(second.text(), "Hi!");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_disk_cache() {
+        let dir = std::env::temp_dir().join(format!("gemini-ox-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&dir);
+        let response: GenerateContentResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Hi!"}]},
+                "finishReason": "STOP",
+                "index": 0
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(cache.get("abc").await, None);
+        cache.put("abc", &response).await;
+        assert_eq!(cache.get("abc").await, Some(response));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,483 @@
+//! Fits generated JSON schemas to the OpenAPI 3 subset Gemini accepts for response schemas
+//! and function parameters.
+//!
+//! schemars emits keywords the API rejects with a 400, such as `additionalProperties`,
+//! `$ref`/`definitions` and formats like `uint8`. [`sanitize`] inlines references and
+//! drops everything outside the supported subset.
+
+use serde_json::{json, Map, Value};
+
+/// Keywords of the Schema object Gemini supports.
+const SUPPORTED_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "title",
+    "description",
+    "nullable",
+    "enum",
+    "maxItems",
+    "minItems",
+    "properties",
+    "required",
+    "minProperties",
+    "maxProperties",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "example",
+    "anyOf",
+    "propertyOrdering",
+    "default",
+    "items",
+    "minimum",
+    "maximum",
+];
+
+/// Formats Gemini supports, by type; any other format is dropped.
+fn is_supported_format(ty: Option<&str>, format: &str) -> bool {
+    match ty {
+        Some("string") => matches!(format, "enum" | "date-time"),
+        Some("integer") => matches!(format, "int32" | "int64"),
+        Some("number") => matches!(format, "float" | "double"),
+        _ => false,
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error("Schema must be an object, got {0}")]
+    NotAnObject(Value),
+    #[error("Schema references {0}, which is not defined in it")]
+    UnresolvedReference(String),
+    /// Gemini schemas have no references, so can't describe a type that contains itself.
+    #[error("Type {0} is recursive, which Gemini schemas can't express")]
+    RecursiveType(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// Rewrites `schema` into Gemini's subset: references are inlined (failing for recursive
+/// types), `oneOf` becomes `anyOf`,
+/// `const` becomes a single-value `enum`, `allOf` schemas are merged into their parent and
+/// unsupported keywords and formats are dropped, as is the title of the root.
+pub fn sanitize(mut schema: Value) -> Result<Value, SchemaError> {
+    let Value::Object(root) = &mut schema else {
+        return Err(SchemaError::NotAnObject(schema));
+    };
+    let title = root.remove("title");
+    let mut definitions = Map::new();
+    for key in ["definitions", "$defs"] {
+        if let Some(Value::Object(defs)) = root.remove(key) {
+            definitions.extend(defs);
+        }
+    }
+    if let Some(Value::Object(mut components)) = root.remove("components") {
+        if let Some(Value::Object(defs)) = components.remove("schemas") {
+            definitions.extend(defs);
+        }
+    }
+    let root = title
+        .as_ref()
+        .and_then(Value::as_str)
+        .unwrap_or("#")
+        .to_string();
+    // The root of a recursive type is also among the definitions it refers to.
+    let expanding = definitions.contains_key(&root).then(|| root.clone());
+    let mut refs = Refs {
+        definitions,
+        expanding: expanding.into_iter().collect(),
+        root,
+    };
+    sanitize_schema(schema, &mut refs)
+}
+
+/// The definitions references point to, and those being inlined, to detect cycles.
+struct Refs {
+    definitions: Map<String, Value>,
+    expanding: Vec<String>,
+    /// The name of the root type.
+    root: String,
+}
+
+fn sanitize_schema(schema: Value, refs: &mut Refs) -> Result<Value, SchemaError> {
+    let mut map = match schema {
+        Value::Object(map) => map,
+        // `true` accepts anything, which the subset can't express beyond an empty schema.
+        Value::Bool(_) => return Ok(Value::Object(Map::new())),
+        other => return Err(SchemaError::NotAnObject(other)),
+    };
+
+    if let Some(Value::String(reference)) = map.remove("$ref") {
+        let name = reference.rsplit('/').next().unwrap_or_default().to_string();
+        if reference == "#" {
+            return Err(SchemaError::RecursiveType(refs.root.clone()));
+        }
+        if refs.expanding.contains(&name) {
+            return Err(SchemaError::RecursiveType(name));
+        }
+        let target = refs
+            .definitions
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| SchemaError::UnresolvedReference(reference.clone()))?;
+        refs.expanding.push(name);
+        let target = sanitize_schema(target, refs)?;
+        refs.expanding.pop();
+        let Value::Object(target) = target else {
+            return Err(SchemaError::UnresolvedReference(reference));
+        };
+        // Keywords next to the reference, e.g. a field's description, take precedence.
+        merge(&mut map, target);
+    }
+    if let Some(Value::Array(all_of)) = map.remove("allOf") {
+        for schema in all_of {
+            if let Value::Object(inner) = sanitize_schema(schema, refs)? {
+                merge(&mut map, inner);
+            }
+        }
+    }
+    if let Some(one_of) = map.remove("oneOf") {
+        map.entry("anyOf").or_insert(one_of);
+    }
+    if let Some(value) = map.remove("const") {
+        map.entry("enum").or_insert(Value::Array(vec![value]));
+    }
+    make_nullable(&mut map, refs)?;
+
+    let ty = map.get("type").and_then(Value::as_str).map(str::to_string);
+    let mut sanitized = Map::new();
+    for (key, value) in map {
+        let value = match key.as_str() {
+            "properties" => match value {
+                Value::Object(properties) => Value::Object(
+                    properties
+                        .into_iter()
+                        .map(|(name, schema)| Ok((name, sanitize_schema(schema, refs)?)))
+                        .collect::<Result<_, SchemaError>>()?,
+                ),
+                other => other,
+            },
+            // Tuples have no equivalent; their first item type is the closest fit.
+            "items" => match value {
+                Value::Array(mut items) if !items.is_empty() => {
+                    sanitize_schema(items.remove(0), refs)?
+                }
+                Value::Array(_) => continue,
+                schema => sanitize_schema(schema, refs)?,
+            },
+            "anyOf" => match value {
+                Value::Array(variants) => Value::Array(
+                    variants
+                        .into_iter()
+                        .map(|schema| sanitize_schema(schema, refs))
+                        .collect::<Result<_, _>>()?,
+                ),
+                other => other,
+            },
+            "format"
+                if !value
+                    .as_str()
+                    .is_some_and(|format| is_supported_format(ty.as_deref(), format)) =>
+            {
+                continue
+            }
+            key if SUPPORTED_KEYWORDS.contains(&key) => value,
+            _ => continue,
+        };
+        sanitized.insert(key, value);
+    }
+    Ok(Value::Object(sanitized))
+}
+
+/// Rewrites the ways JSON Schema allows `null`, e.g. from an `Option` field, into the
+/// `nullable` keyword: `"type": ["string", "null"]`, a `null` variant of `anyOf` and a `null`
+/// value of `enum`.
+fn make_nullable(schema: &mut Map<String, Value>, refs: &mut Refs) -> Result<(), SchemaError> {
+    fn is_null(schema: &Value) -> bool {
+        schema.get("type").and_then(Value::as_str) == Some("null")
+            || schema.get("enum") == Some(&Value::Array(vec![Value::Null]))
+            || schema.get("const") == Some(&Value::Null)
+    }
+
+    let mut nullable = false;
+    if let Some(Value::Array(types)) = schema.get_mut("type") {
+        let len = types.len();
+        types.retain(|ty| ty != "null");
+        nullable |= types.len() < len;
+        match types.len() {
+            0 => {
+                schema.remove("type");
+            }
+            1 => {
+                let ty = types.remove(0);
+                schema.insert("type".to_string(), ty);
+            }
+            _ => {
+                let types = std::mem::take(types);
+                schema.remove("type");
+                let variants = types.into_iter().map(|ty| json!({ "type": ty })).collect();
+                schema.insert("anyOf".to_string(), Value::Array(variants));
+            }
+        }
+    }
+    if let Some(Value::Array(values)) = schema.get_mut("enum") {
+        let len = values.len();
+        values.retain(|value| !value.is_null());
+        nullable |= values.len() < len;
+    }
+    if let Some(Value::Array(variants)) = schema.get_mut("anyOf") {
+        let len = variants.len();
+        variants.retain(|variant| !is_null(variant));
+        nullable |= variants.len() < len;
+        if variants.len() == 1 {
+            if let Value::Object(variant) = sanitize_schema(variants.remove(0), refs)? {
+                schema.remove("anyOf");
+                merge(schema, variant);
+            }
+        }
+    }
+    if nullable {
+        schema.insert("nullable".to_string(), Value::Bool(true));
+    }
+    Ok(())
+}
+
+/// Merges the keywords of `other` into `schema`, combining their properties and required
+/// properties; for any other keyword `schema` takes precedence.
+fn merge(schema: &mut Map<String, Value>, other: Map<String, Value>) {
+    for (key, value) in other {
+        match (schema.get_mut(&key), value) {
+            (Some(Value::Object(properties)), Value::Object(more)) if key == "properties" => {
+                for (name, property) in more {
+                    properties.entry(name).or_insert(property);
+                }
+            }
+            (Some(Value::Array(required)), Value::Array(more)) if key == "required" => {
+                for name in more {
+                    if !required.contains(&name) {
+                        required.push(name);
+                    }
+                }
+            }
+            (Some(_), _) => {}
+            (None, value) => {
+                schema.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_sanitize() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "Order",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "id": {"type": "string", "format": "uuid"},
+                "quantity": {"type": "integer", "format": "uint8", "minimum": 0},
+                "placed": {"type": "string", "format": "date-time"},
+                "status": {"oneOf": [{"const": "open"}, {"const": "closed"}]},
+                "customer": {"description": "Who placed it", "allOf": [{"$ref": "#/definitions/Customer"}]},
+                "pair": {"type": "array", "items": [{"type": "integer"}, {"type": "integer"}]}
+            },
+            "required": ["id"],
+            "definitions": {
+                "Customer": {
+                    "title": "Customer",
+                    "description": "A customer",
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                    "additionalProperties": false
+                }
+            }
+        });
+
+        assert_eq!(
+            sanitize(schema).unwrap(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string"},
+                    "quantity": {"type": "integer", "minimum": 0},
+                    "placed": {"type": "string", "format": "date-time"},
+                    "status": {"anyOf": [{"enum": ["open"]}, {"enum": ["closed"]}]},
+                    "customer": {
+                        "description": "Who placed it",
+                        "title": "Customer",
+                        "type": "object",
+                        "properties": {"name": {"type": "string"}}
+                    },
+                    "pair": {"type": "array", "items": {"type": "integer"}}
+                },
+                "required": ["id"]
+            })
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_sanitize_edge_cases() {
+        assert!(matches!(
+            sanitize(json!({"$ref": "#/definitions/Missing"})),
+            Err(SchemaError::UnresolvedReference(reference)) if reference == "#/definitions/Missing"
+        ));
+        assert_eq!(
+            sanitize(json!({"allOf": [
+                {"type": "object", "properties": {"a": {"type": "string"}}, "required": ["a"]},
+                {"properties": {"b": {"type": "integer"}}, "required": ["b"]}
+            ]}))
+            .unwrap(),
+            json!({
+                "type": "object",
+                "properties": {"a": {"type": "string"}, "b": {"type": "integer"}},
+                "required": ["a", "b"]
+            })
+        );
+        assert!(matches!(
+            sanitize(json!(true)),
+            Err(SchemaError::NotAnObject(_))
+        ));
+        assert_eq!(
+            sanitize(json!({"type": "string"})).unwrap(),
+            json!({"type": "string"})
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_null_becomes_nullable() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": ["string", "null"]},
+                "id": {"type": ["string", "integer"]},
+                "address": {"anyOf": [
+                    {"$ref": "#/$defs/Address"},
+                    {"type": "null"}
+                ]},
+                "color": {"enum": ["red", "green", null]}
+            },
+            "$defs": {
+                "Address": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }
+        });
+
+        assert_eq!(
+            sanitize(schema).unwrap()["properties"],
+            json!({
+                "name": {"type": "string", "nullable": true},
+                "id": {"anyOf": [{"type": "string"}, {"type": "integer"}]},
+                "address": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "nullable": true
+                },
+                "color": {"enum": ["red", "green"], "nullable": true}
+            })
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_option_fields_are_nullable() {
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Author {
+            name: String,
+        }
+
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Book {
+            title: String,
+            subtitle: Option<String>,
+            author: Option<Author>,
+        }
+
+        let schema = crate::ResponseSchema::from::<Book>().unwrap();
+
+        assert_eq!(
+            schema["properties"]["subtitle"],
+            json!({"type": "string", "nullable": true})
+        );
+        assert_eq!(schema["properties"]["author"]["type"], "object");
+        assert_eq!(schema["properties"]["author"]["nullable"], true);
+        assert_eq!(schema["required"], json!(["title"]));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_recursive_types_fail() {
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Node {
+            name: String,
+            children: Vec<Node>,
+        }
+
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Employee {
+            name: String,
+            manager: Option<Box<Employee>>,
+        }
+
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Team {
+            lead: Employee,
+        }
+
+        assert!(matches!(
+            crate::ResponseSchema::from::<Node>(),
+            Err(SchemaError::RecursiveType(name)) if name == "Node"
+        ));
+        assert!(matches!(
+            crate::ResponseSchema::from::<Team>(),
+            Err(SchemaError::RecursiveType(name)) if name == "Employee"
+        ));
+        assert!(matches!(
+            sanitize(json!({"title": "Node", "properties": {"next": {"$ref": "#"}}})),
+            Err(SchemaError::RecursiveType(name)) if name == "Node"
+        ));
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    async fn test_send_as_recursive_type() {
+        #[derive(serde::Deserialize, schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Node {
+            children: Vec<Node>,
+        }
+
+        let transport = crate::transport::tests::MockTransport::json(200, &json!({}));
+        let gemini = crate::Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents("List the tree")
+            .model("gemini-1.5-flash")
+            .build();
+
+        assert!(matches!(
+            request.send_as::<Node>().await,
+            Err(crate::ApiRequestError::SchemaGenerationFailed(
+                SchemaError::RecursiveType(_)
+            ))
+        ));
+        assert!(transport.requests.lock().unwrap().is_empty());
+    }
+}
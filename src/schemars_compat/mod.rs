@@ -0,0 +1,241 @@
+//! Compatibility with schemars 1.x, for types deriving the newer `JsonSchema`.
+//!
+//! The crate generates schemas with schemars 0.8. Wrapping a type in [`Schemars1`] makes it
+//! usable wherever a 0.8 `JsonSchema` is expected, e.g. as `Tool::Input`, with
+//! `GenerationConfig::response_schema_for` or with `GenerateContentRequest::send_as`:
+//!
+//! ```no_run
+//! # async fn run(mut request: gemini_ox::messages::GenerateContentRequest) -> Result<(), gemini_ox::ApiRequestError> {
+//! use gemini_ox::schemars_compat::Schemars1;
+//!
+//! #[derive(serde::Deserialize, schemars1::JsonSchema)]
+//! #[schemars(crate = "schemars1")]
+//! struct Answer {
+//!     city: String,
+//! }
+//!
+//! let Schemars1(answer) = request.send_as::<Schemars1<Answer>>().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    borrow::Cow,
+    ops::{Deref, DerefMut},
+};
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A value whose schema is generated by schemars 1.x. (De)serializes exactly like `T`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schemars1<T>(pub T);
+
+impl<T> Schemars1<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Schemars1<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Schemars1<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: schemars1::JsonSchema> JsonSchema for Schemars1<T> {
+    fn schema_name() -> String {
+        T::schema_name().into_owned()
+    }
+
+    fn schema_id() -> Cow<'static, str> {
+        T::schema_id()
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    /// Generates the schema with the same OpenAPI 3 settings the crate uses for 0.8 and
+    /// passes it on unchanged; both versions emit plain JSON Schema. The definitions are
+    /// added to `gen`, so [`schema::sanitize`](crate::schema::sanitize) inlines them, failing
+    /// with [`SchemaError::RecursiveType`](crate::schema::SchemaError::RecursiveType) for
+    /// recursive types like it does for 0.8 ones.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema: Value = schemars1::generate::SchemaSettings::openapi3()
+            .with(|s| {
+                s.meta_schema = None;
+            })
+            .into_generator()
+            .into_root_schema_for::<T>()
+            .into();
+        if let Some(Value::Object(mut components)) = schema
+            .as_object_mut()
+            .and_then(|root| root.remove("components"))
+        {
+            if let Some(Value::Object(definitions)) = components.remove("schemas") {
+                gen.definitions_mut().extend(
+                    definitions
+                        .into_iter()
+                        .map(|(name, definition)| (name, unchanged(definition))),
+                );
+            }
+        }
+        // `#` would refer to whatever schema this one is embedded in, so the root of a
+        // recursive type is referenced as a definition too.
+        let reference = format!("{}{}", gen.settings().definitions_path, T::schema_name());
+        if replace_root_references(&mut schema, &reference) {
+            gen.definitions_mut()
+                .insert(T::schema_name().into_owned(), unchanged(schema.clone()));
+        }
+        unchanged(schema)
+    }
+}
+
+/// Replaces `{"$ref": "#"}` by `reference`, returning whether there were any.
+fn replace_root_references(schema: &mut Value, reference: &str) -> bool {
+    match schema {
+        Value::Object(map) => {
+            let mut replaced = false;
+            for (key, value) in map.iter_mut() {
+                if key == "$ref" && value == "#" {
+                    *value = Value::String(reference.to_string());
+                    replaced = true;
+                } else {
+                    replaced |= replace_root_references(value, reference);
+                }
+            }
+            replaced
+        }
+        Value::Array(items) => {
+            let mut replaced = false;
+            for item in items {
+                replaced |= replace_root_references(item, reference);
+            }
+            replaced
+        }
+        _ => false,
+    }
+}
+
+/// A schemars 0.8 schema serializing exactly to `schema`, as no keyword is parsed.
+fn unchanged(schema: Value) -> Schema {
+    match schema {
+        Value::Object(keywords) => Schema::Object(SchemaObject {
+            extensions: keywords.into_iter().collect(),
+            ..SchemaObject::default()
+        }),
+        Value::Bool(accepts) => Schema::Bool(accepts),
+        other => unreachable!("schemars emits objects and booleans, not {other}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{schema::SchemaError, ResponseSchema};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[derive(Deserialize, schemars1::JsonSchema)]
+    #[schemars(crate = "schemars1")]
+    #[allow(dead_code)]
+    struct Weather {
+        /// Name of the city.
+        city: String,
+        days: Option<u8>,
+        unit: Unit,
+    }
+
+    #[derive(Deserialize, schemars1::JsonSchema)]
+    #[schemars(crate = "schemars1")]
+    #[allow(dead_code)]
+    enum Unit {
+        Celsius,
+        Fahrenheit,
+    }
+
+    #[derive(schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Forecast {
+        today: Schemars1<Weather>,
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_schemars1_schema() {
+        let schema = ResponseSchema::from::<Schemars1<Weather>>().unwrap();
+
+        assert_eq!(schema["type"], "object");
+        assert_eq!(
+            schema["properties"]["city"]["description"],
+            "Name of the city."
+        );
+        assert_eq!(
+            schema["properties"]["unit"]["enum"],
+            json!(["Celsius", "Fahrenheit"])
+        );
+        assert_eq!(schema["required"], json!(["city", "unit"]));
+        assert_eq!(schema["properties"]["days"]["nullable"], true);
+        assert_eq!(schema["properties"]["days"]["type"], "integer");
+        assert_eq!(schema["propertyOrdering"], json!(["city", "days", "unit"]));
+        assert!(schema.get("title").is_none());
+        assert!(schema.get("$schema").is_none());
+
+        // Definitions still resolve when the schema is embedded in a 0.8 one.
+        let schema = ResponseSchema::from::<Forecast>().unwrap();
+        assert_eq!(
+            schema["properties"]["today"]["properties"]["unit"]["enum"],
+            json!(["Celsius", "Fahrenheit"])
+        );
+
+        let Schemars1(weather) = serde_json::from_value::<Schemars1<Weather>>(
+            json!({"city": "Paris", "unit": "Celsius"}),
+        )
+        .unwrap();
+        assert_eq!(weather.city, "Paris");
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_schemars1_recursive_type() {
+        #[derive(schemars1::JsonSchema)]
+        #[schemars(crate = "schemars1")]
+        #[allow(dead_code)]
+        struct Node {
+            children: Vec<Node>,
+        }
+
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Tree {
+            root: Schemars1<Node>,
+        }
+
+        assert!(matches!(
+            ResponseSchema::from::<Schemars1<Node>>(),
+            Err(SchemaError::RecursiveType(name)) if name == "Node"
+        ));
+        assert!(matches!(
+            ResponseSchema::from::<Tree>(),
+            Err(SchemaError::RecursiveType(name)) if name == "Node"
+        ));
+    }
+}
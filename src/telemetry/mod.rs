@@ -0,0 +1,92 @@
+//! `tracing` instrumentation, enabled with the `tracing` feature.
+//!
+//! Spans carry the model, request size, HTTP status, latency, token usage, finish reason and
+//! the model version that answered.
+//! URLs are never recorded, as they may carry the API key.
+
+use std::future::Future;
+
+use tracing::{field::Empty, Instrument, Span};
+
+use crate::{messages::GenerateContentResponse, ApiRequestError};
+
+pub(crate) fn generate_content_span(operation: &'static str, model: &str) -> Span {
+    tracing::info_span!(
+        "gemini.generate_content",
+        operation,
+        model,
+        request_bytes = Empty,
+        status = Empty,
+        latency_ms = Empty,
+        prompt_tokens = Empty,
+        cached_tokens = Empty,
+        candidates_tokens = Empty,
+        total_tokens = Empty,
+        finish_reason = Empty,
+        model_version = Empty,
+    )
+}
+
+pub(crate) fn upload_file_span(mime_type: &str, file_bytes: usize) -> Span {
+    tracing::info_span!(
+        "gemini.upload_file",
+        mime_type,
+        file_bytes,
+        request_bytes = Empty,
+        status = Empty,
+        latency_ms = Empty,
+    )
+}
+
+/// Runs `future` inside `span`, logging a failure as an event of the span.
+pub(crate) async fn traced<T>(
+    span: Span,
+    future: impl Future<Output = Result<T, ApiRequestError>>,
+) -> Result<T, ApiRequestError> {
+    let result = future.instrument(span.clone()).await;
+    if let Err(e) = &result {
+        tracing::warn!(parent: &span, error = %e, "request failed");
+    }
+    result
+}
+
+/// Records the HTTP-level details of a request on the current span.
+pub(crate) fn record_request(request: &reqwest::Request) {
+    let request_bytes = request
+        .body()
+        .and_then(reqwest::Body::as_bytes)
+        .map_or(0, <[u8]>::len);
+    Span::current().record("request_bytes", request_bytes);
+}
+
+pub(crate) fn record_status(res: &reqwest::Response, latency_ms: Option<u128>) {
+    let span = Span::current();
+    span.record("status", res.status().as_u16());
+    if let Some(latency_ms) = latency_ms {
+        span.record("latency_ms", u64::try_from(latency_ms).unwrap_or(u64::MAX));
+    }
+}
+
+/// Records token usage and finish reason; streamed chunks overwrite earlier values, so the
+/// span ends up with the totals reported by the final chunk.
+pub(crate) fn record_response(span: &Span, response: &GenerateContentResponse) {
+    if let Some(usage) = &response.usage_metadata {
+        span.record("prompt_tokens", usage.prompt_token_count);
+        if let Some(cached_tokens) = usage.cached_content_token_count {
+            span.record("cached_tokens", cached_tokens);
+        }
+        if let Some(candidates_tokens) = usage.candidates_token_count {
+            span.record("candidates_tokens", candidates_tokens);
+        }
+        span.record("total_tokens", usage.total_token_count);
+    }
+    if let Some(model_version) = &response.model_version {
+        span.record("model_version", model_version.as_str());
+    }
+    if let Some(candidate) = response.candidates.first() {
+        span.record(
+            "finish_reason",
+            tracing::field::display(candidate.finish_reason),
+        );
+    }
+}
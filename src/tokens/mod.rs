@@ -0,0 +1,325 @@
+//! Offline prompt size estimation, for context-window management without calling
+//! `countTokens`.
+//!
+//! Estimates are approximate and err on the high side; use
+//! [`GenerateContentRequest::count_tokens`] when exact numbers matter.
+//!
+//! [`GenerateContentRequest::count_tokens`]: crate::messages::GenerateContentRequest::count_tokens
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    messages::{
+        chat::HistoryPolicy,
+        message::{Content, Part},
+    },
+    models::ModelInfo,
+};
+
+/// The exact prompt size, as counted by `countTokens`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensResponse {
+    pub total_tokens: u32,
+    /// Tokens of the prompt served from cached content.
+    pub cached_content_token_count: Option<u32>,
+    /// The total broken down by modality.
+    #[serde(default)]
+    pub prompt_tokens_details: Vec<ModalityTokenCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModalityTokenCount {
+    /// `TEXT`, `IMAGE`, `AUDIO`, `VIDEO` or `DOCUMENT`.
+    pub modality: String,
+    #[serde(default)]
+    pub token_count: u32,
+}
+
+/// Tokens the API bills for a single image, regardless of its resolution.
+pub const IMAGE_TOKENS: u32 = 258;
+
+/// Estimates token counts locally.
+///
+/// Only [`TokenEstimator::estimate_text`] is required; media and function parts fall back to
+/// fixed costs and their JSON text respectively.
+pub trait TokenEstimator: Send + Sync {
+    fn estimate_text(&self, text: &str) -> u32;
+
+    fn estimate_part(&self, part: &Part<'_>) -> u32 {
+        match part {
+            Part::Text(text) => self.estimate_text(&text.0),
+            Part::InlineData(_) | Part::FileData(_) => IMAGE_TOKENS,
+            Part::FunctionCall(call) => {
+                self.estimate_text(&call.name)
+                    + call
+                        .args
+                        .as_ref()
+                        .map_or(0, |args| self.estimate_text(&args.to_string()))
+            }
+            Part::FunctionResponse(response) => {
+                self.estimate_text(&response.name)
+                    + self.estimate_text(&response.response.to_string())
+            }
+        }
+    }
+
+    fn estimate_content(&self, content: &Content<'_>) -> u32 {
+        content.iter().map(|part| self.estimate_part(part)).sum()
+    }
+
+    fn estimate_contents(&self, contents: &[Content<'_>]) -> u32 {
+        contents
+            .iter()
+            .map(|content| self.estimate_content(content))
+            .sum()
+    }
+}
+
+/// Character-count heuristic: roughly four ASCII characters per token, and one token per
+/// non-ASCII character, which overestimates accented Latin text and is close for CJK.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeuristicEstimator {
+    pub chars_per_token: f32,
+}
+
+impl Default for HeuristicEstimator {
+    fn default() -> Self {
+        Self {
+            chars_per_token: 4.0,
+        }
+    }
+}
+
+impl TokenEstimator for HeuristicEstimator {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn estimate_text(&self, text: &str) -> u32 {
+        let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+            if c.is_ascii() {
+                (ascii + 1, other)
+            } else {
+                (ascii, other + 1)
+            }
+        });
+        (ascii as f32 / self.chars_per_token).ceil() as u32 + other as u32
+    }
+}
+
+impl<F> TokenEstimator for F
+where
+    F: Fn(&str) -> u32 + Send + Sync,
+{
+    fn estimate_text(&self, text: &str) -> u32 {
+        self(text)
+    }
+}
+
+/// Splits a model's context window between the system instruction, retrieved documents,
+/// the conversation history and the answer.
+///
+/// The answer's `max_output_tokens` are reserved from the window as well, so prompt and
+/// answer fit even on models that share one limit between them. The system instruction is
+/// never trimmed; documents get up to `document_share` of what remains, and whatever either
+/// of documents and history leaves unused goes to the other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContextBudget {
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    pub document_share: f32,
+}
+
+/// Tokens granted to each part of the prompt by [`ContextBudget::allocate`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Allocation {
+    pub system: u32,
+    pub documents: u32,
+    pub history: u32,
+    pub max_output_tokens: u32,
+}
+
+impl ContextBudget {
+    #[must_use]
+    pub fn new(context_window: u32, max_output_tokens: u32) -> Self {
+        Self {
+            context_window,
+            max_output_tokens: max_output_tokens.min(context_window),
+            document_share: 0.5,
+        }
+    }
+
+    /// Uses the model's input limit as the window and its output limit as the answer size.
+    #[must_use]
+    pub fn for_model(model: &ModelInfo) -> Self {
+        Self::new(model.input_token_limit, model.output_token_limit)
+    }
+
+    #[must_use]
+    pub fn with_max_output_tokens(mut self, max_output_tokens: u32) -> Self {
+        self.max_output_tokens = max_output_tokens.min(self.context_window);
+        self
+    }
+
+    #[must_use]
+    pub fn with_document_share(mut self, document_share: f32) -> Self {
+        self.document_share = document_share.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Grants tokens to prompt parts that need `system`, `documents` and `history` tokens.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn allocate(&self, system: u32, documents: u32, history: u32) -> Allocation {
+        let available = self
+            .context_window
+            .saturating_sub(self.max_output_tokens)
+            .saturating_sub(system);
+        let document_cap = (available as f32 * self.document_share) as u32;
+        let history = history.min(available - documents.min(document_cap));
+        let documents = documents.min(available - history);
+        Allocation {
+            system,
+            documents,
+            history,
+            max_output_tokens: self.max_output_tokens,
+        }
+    }
+
+    /// Trims `documents`, ordered by relevance, from the end and `history` from the oldest
+    /// turn until both fit their allocation, see [`HistoryPolicy::TokenBudget`].
+    pub fn fit(
+        &self,
+        system_instruction: Option<&Content<'_>>,
+        documents: &mut Vec<Content<'_>>,
+        history: &mut Vec<Content<'_>>,
+        estimator: &impl TokenEstimator,
+    ) -> Allocation {
+        let system = system_instruction.map_or(0, |content| estimator.estimate_content(content));
+        let allocation = self.allocate(
+            system,
+            estimator.estimate_contents(documents),
+            estimator.estimate_contents(history),
+        );
+
+        let mut used = 0u32;
+        let kept = documents
+            .iter()
+            .take_while(|content| {
+                used = used.saturating_add(estimator.estimate_content(content));
+                used <= allocation.documents
+            })
+            .count();
+        documents.truncate(kept);
+        HistoryPolicy::TokenBudget(allocation.history).apply(history, 0, estimator);
+        allocation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::messages::message::{Blob, FunctionCall, Role};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_heuristic_text() {
+        let estimator = HeuristicEstimator::default();
+        assert_eq!(estimator.estimate_text(""), 0);
+        assert_eq!(estimator.estimate_text("Hello, world"), 3);
+        assert_eq!(estimator.estimate_text("日本語"), 3);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_estimate_contents() {
+        let contents = vec![
+            Content::from("What is in this picture?"),
+            Content::builder()
+                .role(Role::User)
+                .parts(vec![Part::InlineData(Blob {
+                    mime_type: "image/png".to_string(),
+                    data: "AAAA".into(),
+                })])
+                .build(),
+            Content::builder()
+                .role(Role::Model)
+                .parts(vec![Part::FunctionCall(FunctionCall {
+                    name: "describe".to_string(),
+                    args: Some(json!({})),
+                })])
+                .build(),
+        ];
+        let estimator = HeuristicEstimator::default();
+        assert_eq!(
+            estimator.estimate_contents(&contents),
+            6 + IMAGE_TOKENS + 2 + 1
+        );
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_context_budget_allocate() {
+        let budget = ContextBudget::new(1000, 200);
+        // 700 remain after the answer and the system instruction, documents may take 350.
+        assert_eq!(
+            budget.allocate(100, 500, 500),
+            Allocation {
+                system: 100,
+                documents: 350,
+                history: 350,
+                max_output_tokens: 200,
+            }
+        );
+        // Short histories leave the rest to the documents, and vice versa.
+        assert_eq!(budget.allocate(100, 900, 50).documents, 650);
+        assert_eq!(budget.allocate(100, 50, 900).history, 650);
+        assert_eq!(budget.allocate(900, 50, 50).documents, 0);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_context_budget_fit() {
+        let words = |text: &str| u32::try_from(text.split_whitespace().count()).unwrap();
+        let system = Content::system(["be brief"]);
+        let mut documents = vec![
+            Content::from("most relevant document"),
+            Content::from("less relevant document with more text"),
+        ];
+        let mut history = vec![
+            Content::from("an old question"),
+            Content::builder()
+                .role(Role::Model)
+                .parts(["an old answer"])
+                .build(),
+            Content::from("the new question"),
+        ];
+
+        let allocation =
+            ContextBudget::new(20, 4).fit(Some(&system), &mut documents, &mut history, &words);
+
+        assert_eq!(allocation.documents, 7);
+        assert_eq!(allocation.history, 7);
+        assert_eq!(documents, [Content::from("most relevant document")]);
+        assert_eq!(history, [Content::from("the new question")]);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_custom_estimator() {
+        let words = |text: &str| u32::try_from(text.split_whitespace().count()).unwrap();
+        assert_eq!(words.estimate_content(&Content::from("one two three")), 3);
+    }
+}
@@ -0,0 +1,265 @@
+//! High-level audio transcription: sends the audio inline or through the Files API depending
+//! on its size, prompts the model and parses the answer into segments.
+
+use std::fmt::Write;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{
+    messages::{
+        media::{self, MediaError},
+        message::{Blob, Content, FileData, Part, Role},
+    },
+    ApiRequestError, Gemini,
+};
+
+/// Audio larger than this is uploaded with the Files API instead of being sent inline, as
+/// requests are capped at 20 MB and base64 inflates the audio by a third.
+pub const INLINE_AUDIO_LIMIT: usize = 15 * 1024 * 1024;
+
+/// Audio to transcribe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioSource {
+    /// Raw audio; the format is detected from the data.
+    Bytes(Vec<u8>),
+    /// An audio file; the format is taken from its extension or detected from its contents.
+    #[cfg(not(target_arch = "wasm32"))]
+    Path(PathBuf),
+    /// Audio already uploaded with the Files API.
+    File(FileData),
+}
+
+impl From<Vec<u8>> for AudioSource {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Bytes(data)
+    }
+}
+
+impl From<&[u8]> for AudioSource {
+    fn from(data: &[u8]) -> Self {
+        Self::Bytes(data.to_vec())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<PathBuf> for AudioSource {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<&Path> for AudioSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+impl From<FileData> for AudioSource {
+    fn from(file: FileData) -> Self {
+        Self::File(file)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TranscriptionSegment {
+    /// Offset of the segment start as `MM:SS`, when timestamps were requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    /// Offset of the segment end as `MM:SS`, when timestamps were requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Transcription {
+    pub segments: Vec<TranscriptionSegment>,
+}
+
+impl Transcription {
+    /// The whole transcription as plain text, one segment per line.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct TranscriptionRequest {
+    source: AudioSource,
+    /// Language of the audio, e.g. `"Polish"`; the transcription is written in it too.
+    #[builder(default, setter(into, strip_option))]
+    language: Option<String>,
+    /// Whether segments carry `start` and `end` offsets.
+    #[builder(default)]
+    timestamps: bool,
+    #[builder(default = "gemini-1.5-flash".to_string(), setter(into))]
+    model: String,
+    gemini: Gemini,
+}
+
+/// Builder returned by [`Gemini::transcribe`], with the source and client already set.
+pub type TranscribeBuilder = TranscriptionRequestBuilder<((AudioSource,), (), (), (), (Gemini,))>;
+
+impl Gemini {
+    /// Starts a transcription of `source`, e.g. a file path or raw audio bytes.
+    pub fn transcribe(&self, source: impl Into<AudioSource>) -> TranscribeBuilder {
+        TranscriptionRequest::builder()
+            .source(source.into())
+            .gemini(self.clone())
+    }
+}
+
+impl TranscriptionRequest {
+    pub async fn send(&self) -> Result<Transcription, ApiRequestError> {
+        let audio = self.audio_part().await?;
+        self.gemini
+            .generate_content()
+            .contents([Content::builder()
+                .role(Role::User)
+                .parts([audio, Part::from(self.prompt())])
+                .build()])
+            .model(self.model.clone())
+            .build()
+            .send_as::<Transcription>()
+            .await
+    }
+
+    fn prompt(&self) -> String {
+        let mut prompt = String::from(
+            "Transcribe this audio verbatim, splitting it into segments at pauses and speaker changes.",
+        );
+        if let Some(language) = &self.language {
+            let _ = write!(
+                prompt,
+                " The audio is in {language}; write the transcription in {language}."
+            );
+        }
+        if self.timestamps {
+            prompt.push_str(" Set `start` and `end` of every segment to its offset as MM:SS.");
+        } else {
+            prompt.push_str(" Leave out `start` and `end`.");
+        }
+        prompt
+    }
+
+    /// Sends small audio inline and uploads anything above [`INLINE_AUDIO_LIMIT`].
+    async fn audio_part(&self) -> Result<Part<'static>, ApiRequestError> {
+        let (data, mime_type) = match &self.source {
+            AudioSource::File(file) => return Ok(Part::FileData(file.clone())),
+            AudioSource::Bytes(data) => (
+                std::borrow::Cow::Borrowed(data),
+                media::sniff_audio(data).map(String::from),
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            AudioSource::Path(path) => {
+                let data = std::fs::read(path)?;
+                let mime_type = media::audio_type_from_extension(path)
+                    .or_else(|| media::sniff_audio(&data).map(String::from));
+                (std::borrow::Cow::Owned(data), mime_type)
+            }
+        };
+        let mime_type = mime_type.ok_or(MediaError::UnknownFormat)?;
+
+        if data.len() <= INLINE_AUDIO_LIMIT {
+            return Ok(Part::InlineData(Blob::new(mime_type, data.as_slice())));
+        }
+        let file_uri = self
+            .gemini
+            .upload_file()
+            .file_name("audio")
+            .mime_type(mime_type.clone())
+            .data(&data)
+            .build()
+            .send()
+            .await?;
+        Ok(Part::FileData(FileData {
+            mime_type: Some(mime_type),
+            file_uri,
+            video_metadata: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::transport::tests::MockTransport;
+
+    #[tokio::test]
+    async fn test_transcribe_inline_audio() {
+        let segments = json!({
+            "segments": [
+                {"start": "00:00", "end": "00:02", "text": "Dzień dobry."},
+                {"start": "00:02", "end": "00:04", "text": "Jak się masz?"}
+            ]
+        });
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": segments.to_string()}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }]
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        let transcription = gemini
+            .transcribe(wav)
+            .language("Polish")
+            .timestamps(true)
+            .build()
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(transcription.segments.len(), 2);
+        assert_eq!(transcription.segments[1].start.as_deref(), Some("00:02"));
+        assert_eq!(transcription.text(), "Dzień dobry.\nJak się masz?");
+
+        let requests = transport.requests.lock().unwrap();
+        let body: Value =
+            serde_json::from_slice(requests[0].body().unwrap().as_bytes().unwrap()).unwrap();
+        let parts = &body["contents"][0]["parts"];
+        assert_eq!(parts[0]["inlineData"]["mimeType"], "audio/wav");
+        let prompt = parts[1]["text"].as_str().unwrap();
+        assert!(prompt.contains("Polish"));
+        assert!(prompt.contains("MM:SS"));
+        assert_eq!(
+            body["generation_config"]["responseMimeType"],
+            "application/json"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_unknown_format() {
+        let gemini = Gemini::builder().api_key("test").build();
+        let err = gemini
+            .transcribe(vec![0u8; 16])
+            .build()
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ApiRequestError::MediaError(MediaError::UnknownFormat)
+        ));
+    }
+}
@@ -0,0 +1,240 @@
+//! The HTTP layer used by [`Gemini`](crate::Gemini).
+//!
+//! Every async request is built with reqwest and handed to a [`Transport`], which by default
+//! is the client's `reqwest::Client`. Tests can plug in their own transport to serve canned
+//! responses without a network or an API key.
+
+use async_trait::async_trait;
+
+use crate::ApiRequestError;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod record_replay;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use record_replay::{Interaction, RecordMode, RecordReplayTransport};
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait Transport: Send + Sync {
+    async fn execute(
+        &self,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, ApiRequestError>;
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Transport for reqwest::Client {
+    async fn execute(
+        &self,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, ApiRequestError> {
+        Ok(reqwest::Client::execute(self, request).await?)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        messages::message::{Content, Role},
+        Gemini, HttpDetails,
+    };
+
+    /// Answers every request with the same canned response and records the requests seen.
+    #[derive(Clone, Default)]
+    pub(crate) struct MockTransport {
+        status: u16,
+        body: String,
+        pub(crate) requests: Arc<Mutex<Vec<reqwest::Request>>>,
+    }
+
+    impl MockTransport {
+        pub(crate) fn new(status: u16, body: impl Into<String>) -> Self {
+            Self {
+                status,
+                body: body.into(),
+                requests: Arc::default(),
+            }
+        }
+
+        pub(crate) fn json(status: u16, body: &Value) -> Self {
+            Self::new(status, body.to_string())
+        }
+    }
+
+    #[async_trait]
+    impl Transport for MockTransport {
+        async fn execute(
+            &self,
+            request: reqwest::Request,
+        ) -> Result<reqwest::Response, ApiRequestError> {
+            self.requests.lock().unwrap().push(request);
+            let response = http::Response::builder()
+                .status(self.status)
+                .body(self.body.clone())
+                .unwrap();
+            Ok(response.into())
+        }
+    }
+
+    /// Serves a paginated list: the page for each `pageToken`, where `None` is the first
+    /// page, and 404 for unknown tokens. Records the tokens requested.
+    #[derive(Clone, Default)]
+    pub(crate) struct PagedTransport {
+        pages: Arc<HashMap<Option<String>, Value>>,
+        pub(crate) page_tokens: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    impl PagedTransport {
+        pub(crate) fn new<'a>(pages: impl IntoIterator<Item = (Option<&'a str>, Value)>) -> Self {
+            Self {
+                pages: Arc::new(
+                    pages
+                        .into_iter()
+                        .map(|(token, page)| (token.map(String::from), page))
+                        .collect(),
+                ),
+                page_tokens: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Transport for PagedTransport {
+        async fn execute(
+            &self,
+            request: reqwest::Request,
+        ) -> Result<reqwest::Response, ApiRequestError> {
+            let page_token = request
+                .url()
+                .query_pairs()
+                .find(|(key, _)| key == "pageToken")
+                .map(|(_, token)| token.into_owned());
+            let response = match self.pages.get(&page_token) {
+                Some(page) => http::Response::new(page.to_string()),
+                None => http::Response::builder()
+                    .status(404)
+                    .body("{}".to_string())
+                    .unwrap(),
+            };
+            self.page_tokens.lock().unwrap().push(page_token);
+            Ok(response.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_with_mock_transport() {
+        let transport = MockTransport::json(
+            200,
+            &json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": "Hi!"}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }]
+            }),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport.clone())
+            .build();
+
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-flash")
+            .build();
+        let response = request.send().await.unwrap();
+
+        assert_eq!(
+            response.content(),
+            Some(&Content::builder().role(Role::Model).parts(["Hi!"]).build())
+        );
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].url().path(),
+            "/v1beta/models/gemini-1.5-flash:generateContent"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_with_mock_transport() {
+        let transport = MockTransport::json(
+            400,
+            &json!({"error": {"code": 400, "message": "bad request", "status": "INVALID_ARGUMENT"}}),
+        );
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(transport)
+            .build();
+
+        let err = gemini
+            .generate_content()
+            .model("gemini-1.5-flash")
+            .build()
+            .send()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            ApiRequestError::InvalidRequestError { ref message, ref status, .. }
+                if message == "bad request" && status.as_deref() == Some("INVALID_ARGUMENT")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_errors_keep_http_details() {
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(MockTransport::new(502, "<html>Bad Gateway</html>"))
+            .build();
+        let request = gemini.generate_content().model("gemini-1.5-flash").build();
+
+        let err = request.send().await.unwrap_err();
+        assert!(matches!(
+            err,
+            ApiRequestError::HttpError { ref http }
+                if http.status == 502 && http.body == "<html>Bad Gateway</html>"
+        ));
+        assert!(err.is_transient());
+
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .transport(MockTransport::new(200, "{\"candidates\": 42}"))
+            .build();
+        let request = gemini.generate_content().model("gemini-1.5-flash").build();
+
+        assert!(matches!(
+            request.send().await.unwrap_err(),
+            ApiRequestError::DecodeError { ref http, .. }
+                if http.status == 200 && http.body == "{\"candidates\": 42}"
+        ));
+    }
+
+    #[test]
+    fn test_http_details_truncates_body() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-goog-request-id", "abc".parse().unwrap());
+        headers.insert("set-cookie", "secret".parse().unwrap());
+        let body = "é".repeat(HttpDetails::MAX_BODY_BYTES);
+
+        let http = HttpDetails::new(500, &headers, &body);
+
+        assert_eq!(
+            http.headers,
+            vec![("x-goog-request-id".to_string(), "abc".to_string())]
+        );
+        assert_eq!(http.body.len(), HttpDetails::MAX_BODY_BYTES);
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::Transport;
+use crate::ApiRequestError;
+
+/// Whether a [`RecordReplayTransport`] hits the network or serves a cassette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordMode {
+    /// Forward requests to the inner transport and save every interaction.
+    Record,
+    /// Serve recorded interactions only; unknown requests fail.
+    Replay,
+}
+
+/// A recorded request/response pair. Credentials are never stored: the `key` query parameter
+/// is stripped from the URL and request headers are not recorded at all.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub response: String,
+}
+
+/// VCR-style transport that records real interactions to a JSON cassette and replays them, so
+/// tests run deterministically without a network or an API key.
+///
+/// Identical requests are replayed in the order they were recorded.
+pub struct RecordReplayTransport {
+    mode: RecordMode,
+    path: PathBuf,
+    inner: Option<Arc<dyn Transport>>,
+    interactions: Mutex<Vec<Interaction>>,
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl RecordReplayTransport {
+    /// Records through `inner`, overwriting the cassette at `path`.
+    pub fn record(path: impl Into<PathBuf>, inner: impl Transport + 'static) -> Self {
+        Self {
+            mode: RecordMode::Record,
+            path: path.into(),
+            inner: Some(Arc::new(inner)),
+            interactions: Mutex::default(),
+            replayed: Mutex::default(),
+        }
+    }
+
+    /// Replays the cassette at `path`.
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self, ApiRequestError> {
+        let path = path.into();
+        let interactions: Vec<Interaction> = serde_json::from_slice(&std::fs::read(&path)?)?;
+        Ok(Self {
+            mode: RecordMode::Replay,
+            path,
+            replayed: Mutex::new(vec![false; interactions.len()]),
+            interactions: Mutex::new(interactions),
+            inner: None,
+        })
+    }
+
+    /// Replays the cassette at `path` if it exists, records through `inner` otherwise.
+    pub fn auto(
+        path: impl Into<PathBuf>,
+        inner: impl Transport + 'static,
+    ) -> Result<Self, ApiRequestError> {
+        let path = path.into();
+        if path.exists() {
+            Self::replay(path)
+        } else {
+            Ok(Self::record(path, inner))
+        }
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> RecordMode {
+        self.mode
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn save(&self, interactions: &[Interaction]) -> Result<(), ApiRequestError> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(interactions)?)?;
+        Ok(())
+    }
+
+    async fn record_interaction(
+        &self,
+        inner: &dyn Transport,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, ApiRequestError> {
+        let (method, url, body) = request_key(&request);
+        let res = inner.execute(request).await?;
+        let status = res.status().as_u16();
+        let headers = res
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let response = res.text().await?;
+        let interaction = Interaction {
+            method,
+            url,
+            body,
+            status,
+            headers,
+            response,
+        };
+
+        let res = to_response(&interaction);
+        let mut interactions = self.interactions.lock().unwrap();
+        interactions.push(interaction);
+        self.save(&interactions)?;
+        Ok(res)
+    }
+
+    fn replay_interaction(
+        &self,
+        request: &reqwest::Request,
+    ) -> Result<reqwest::Response, ApiRequestError> {
+        let (method, url, body) = request_key(request);
+        let interactions = self.interactions.lock().unwrap();
+        let mut replayed = self.replayed.lock().unwrap();
+        let index = interactions
+            .iter()
+            .zip(replayed.iter())
+            .position(|(i, &done)| !done && i.method == method && i.url == url && i.body == body)
+            .ok_or_else(|| ApiRequestError::UnexpectedResponse {
+                response: format!("No recorded interaction for {method} {url}"),
+            })?;
+        replayed[index] = true;
+        Ok(to_response(&interactions[index]))
+    }
+}
+
+impl fmt::Debug for RecordReplayTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordReplayTransport")
+            .field("mode", &self.mode)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Transport for RecordReplayTransport {
+    async fn execute(
+        &self,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, ApiRequestError> {
+        match (&self.mode, &self.inner) {
+            (RecordMode::Record, Some(inner)) => {
+                self.record_interaction(inner.as_ref(), request).await
+            }
+            _ => self.replay_interaction(&request),
+        }
+    }
+}
+
+/// Method, credential-free URL and body used to match requests against the cassette.
+fn request_key(request: &reqwest::Request) -> (String, String, Option<String>) {
+    let mut url = request.url().clone();
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != "key")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+    let body = request
+        .body()
+        .and_then(reqwest::Body::as_bytes)
+        .map(|bytes| String::from_utf8_lossy(bytes).into_owned());
+    (request.method().to_string(), url.to_string(), body)
+}
+
+fn to_response(interaction: &Interaction) -> reqwest::Response {
+    let mut builder = http::Response::builder().status(interaction.status);
+    for (name, value) in &interaction.headers {
+        builder = builder.header(name, value);
+    }
+    builder
+        .body(interaction.response.clone())
+        .expect("Recorded response is valid")
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::tests::MockTransport, Gemini};
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path =
+            std::env::temp_dir().join(format!("gemini-ox-cassette-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let body = json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "recorded"}]},
+                "finishReason": "STOP",
+                "index": 0
+            }]
+        });
+
+        let recorder = RecordReplayTransport::auto(&path, MockTransport::json(200, &body)).unwrap();
+        assert_eq!(recorder.mode(), RecordMode::Record);
+        let gemini = Gemini::builder()
+            .api_key("super-secret")
+            .transport(recorder)
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-flash")
+            .build();
+        let first_response = request.send().await.unwrap();
+
+        let cassette = std::fs::read_to_string(&path).unwrap();
+        assert!(!cassette.contains("super-secret"));
+
+        let replayer = RecordReplayTransport::auto(&path, MockTransport::new(500, "")).unwrap();
+        assert_eq!(replayer.mode(), RecordMode::Replay);
+        let gemini = Gemini::builder()
+            .api_key("another-key")
+            .transport(replayer)
+            .build();
+        let request = gemini
+            .generate_content()
+            .contents(vec!["hello"])
+            .model("gemini-1.5-flash")
+            .build();
+        assert_eq!(request.send().await.unwrap(), first_response);
+        assert!(request.send().await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
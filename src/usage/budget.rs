@@ -0,0 +1,175 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use leaky_bucket::RateLimiter;
+
+use crate::{messages::UsageMetadata, Gemini};
+
+/// Tokens-per-minute budget, so TPM quotas are respected even when the request rate is fine.
+///
+/// Every request reserves its estimated size (prompt plus `max_output_tokens`) before it is
+/// sent. Once the actual usage is known the difference is settled: overestimates are credited
+/// to the next request and underestimates are charged to it.
+#[derive(Debug)]
+pub struct TokenBudget {
+    limiter: RateLimiter,
+    /// Tokens owed to (positive) or by (negative) the next reservation.
+    adjustment: Mutex<i64>,
+}
+
+impl TokenBudget {
+    /// A budget of `tokens` per minute, refilled every second.
+    #[must_use]
+    pub fn per_minute(tokens: usize) -> Self {
+        Self {
+            limiter: RateLimiter::builder()
+                .max(tokens)
+                .initial(tokens)
+                .refill(tokens.div_ceil(60).max(1))
+                .interval(Duration::from_secs(1))
+                .build(),
+            adjustment: Mutex::new(0),
+        }
+    }
+
+    /// Waits until `estimate` tokens are available and takes them.
+    pub async fn reserve(&self, estimate: u32) {
+        self.limiter.acquire(self.to_acquire(estimate)).await;
+    }
+
+    /// Blocking counterpart of [`TokenBudget::reserve`].
+    #[cfg(feature = "blocking")]
+    pub fn reserve_blocking(&self, estimate: u32) {
+        let tokens = self.to_acquire(estimate);
+        while !self.limiter.try_acquire(tokens) {
+            std::thread::sleep(self.limiter.interval());
+        }
+    }
+
+    /// Corrects an earlier reservation of `estimate` tokens with the `actual` usage.
+    pub fn settle(&self, estimate: u32, actual: u32) {
+        *self.adjustment.lock().unwrap() += i64::from(estimate) - i64::from(actual);
+    }
+
+    /// Tokens left in the current window.
+    #[must_use]
+    pub fn available(&self) -> usize {
+        self.limiter.balance()
+    }
+
+    /// Applies the pending adjustment to `estimate`. Requests larger than the whole budget are
+    /// capped, so they still go through once the budget is full.
+    fn to_acquire(&self, estimate: u32) -> usize {
+        let mut adjustment = self.adjustment.lock().unwrap();
+        let tokens = i64::from(estimate) - *adjustment;
+        *adjustment = (-tokens).max(0);
+        usize::try_from(tokens.max(0))
+            .unwrap_or(usize::MAX)
+            .min(self.limiter.max())
+    }
+}
+
+/// Tokens a request reserved from the client's budget, if it has one. Dropping the
+/// reservation without settling it, e.g. because the request failed, was cancelled or its
+/// response carried no usage, refunds the whole estimate.
+#[must_use]
+pub(crate) struct TokenReservation {
+    budget: Option<Arc<TokenBudget>>,
+    estimate: u32,
+}
+
+impl TokenReservation {
+    /// Charges the actual usage instead of the estimate; without usage the estimate is
+    /// refunded.
+    pub(crate) fn settle(mut self, usage: Option<&UsageMetadata>) {
+        if let Some(usage) = usage {
+            if let Some(budget) = self.budget.take() {
+                budget.settle(self.estimate, usage.total_token_count);
+            }
+        }
+    }
+}
+
+impl Drop for TokenReservation {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.settle(self.estimate, 0);
+        }
+    }
+}
+
+impl Gemini {
+    pub(crate) async fn reserve_tokens(&self, estimate: u32) -> TokenReservation {
+        if let Some(budget) = &self.token_budget {
+            budget.reserve(estimate).await;
+        }
+        TokenReservation {
+            budget: self.token_budget.clone(),
+            estimate,
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn reserve_tokens_blocking(&self, estimate: u32) -> TokenReservation {
+        if let Some(budget) = &self.token_budget {
+            budget.reserve_blocking(estimate);
+        }
+        TokenReservation {
+            budget: self.token_budget.clone(),
+            estimate,
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_settle_adjusts_next_reservation() {
+        let budget = TokenBudget::per_minute(1000);
+
+        budget.reserve(300).await;
+        assert_eq!(budget.available(), 700);
+
+        // Used less than estimated: the next reservation is smaller.
+        budget.settle(300, 100);
+        budget.reserve(300).await;
+        assert_eq!(budget.available(), 600);
+
+        // Used more than estimated: the next reservation pays the difference.
+        budget.settle(100, 300);
+        budget.reserve(100).await;
+        assert_eq!(budget.available(), 300);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_reservation_is_refunded() {
+        let gemini = Gemini::builder()
+            .api_key("test")
+            .token_budget(TokenBudget::per_minute(1000))
+            .build();
+        let budget = gemini.token_budget.as_ref().unwrap();
+
+        // E.g. the losing request of a race, cancelled after reserving.
+        drop(gemini.reserve_tokens(300).await);
+        assert_eq!(budget.available(), 700);
+        gemini.reserve_tokens(300).await.settle(None);
+        gemini.reserve_tokens(300).await.settle(None);
+        assert_eq!(budget.available(), 700);
+    }
+
+    #[tokio::test]
+    async fn test_credit_larger_than_estimate_carries_over() {
+        let budget = TokenBudget::per_minute(1000);
+        budget.settle(500, 0);
+
+        budget.reserve(200).await;
+        budget.reserve(200).await;
+        assert_eq!(budget.available(), 1000);
+        budget.reserve(200).await;
+        assert_eq!(budget.available(), 900);
+    }
+}
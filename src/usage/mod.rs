@@ -0,0 +1,197 @@
+//! Token usage accounting across calls, e.g. for cost dashboards.
+
+use std::{
+    collections::HashMap,
+    ops::AddAssign,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    messages::UsageMetadata,
+    observer::{Observer, RequestInfo},
+};
+
+#[cfg(feature = "leaky-bucket")]
+mod budget;
+#[cfg(feature = "leaky-bucket")]
+pub use budget::TokenBudget;
+
+/// Summed token counts of one or more calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub cached_tokens: u64,
+    pub candidates_tokens: u64,
+    pub thinking_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Share of the prompt tokens served from cached content, between 0 and 1.
+    #[must_use]
+    pub fn cache_hit_ratio(&self) -> f64 {
+        crate::messages::cache_hit_ratio(self.cached_tokens, self.prompt_tokens)
+    }
+}
+
+impl AddAssign<&UsageMetadata> for TokenUsage {
+    fn add_assign(&mut self, usage: &UsageMetadata) {
+        self.requests += 1;
+        self.prompt_tokens += u64::from(usage.prompt_token_count);
+        self.cached_tokens += u64::from(usage.cached_content_token_count.unwrap_or_default());
+        self.candidates_tokens += u64::from(usage.candidates_token_count.unwrap_or_default());
+        self.thinking_tokens += u64::from(usage.thoughts_token_count.unwrap_or_default());
+        self.total_tokens += u64::from(usage.total_token_count);
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.candidates_tokens += other.candidates_tokens;
+        self.thinking_tokens += other.thinking_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+/// Accumulates `UsageMetadata` per model.
+///
+/// Clones share the same counters, so a tracker can be registered as the client's
+/// [`Observer`] to record every call automatically while another clone is read from a
+/// dashboard, or one tracker can be kept per session and fed with [`UsageTracker::record`].
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    by_model: Arc<Mutex<HashMap<String, TokenUsage>>>,
+}
+
+impl UsageTracker {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, model: impl Into<String>, usage: &UsageMetadata) {
+        *self
+            .by_model
+            .lock()
+            .unwrap()
+            .entry(model.into())
+            .or_default() += usage;
+    }
+
+    /// Usage summed over all models.
+    #[must_use]
+    pub fn total(&self) -> TokenUsage {
+        self.by_model
+            .lock()
+            .unwrap()
+            .values()
+            .fold(TokenUsage::default(), |mut total, usage| {
+                total += *usage;
+                total
+            })
+    }
+
+    #[must_use]
+    pub fn for_model(&self, model: &str) -> TokenUsage {
+        self.by_model
+            .lock()
+            .unwrap()
+            .get(model)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn by_model(&self) -> HashMap<String, TokenUsage> {
+        self.by_model.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        self.by_model.lock().unwrap().clear();
+    }
+}
+
+impl Observer for UsageTracker {
+    fn on_tokens_used(&self, request: &RequestInfo<'_>, usage: &UsageMetadata) {
+        self.record(request.model.unwrap_or_default(), usage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::GenerateContentResponse;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    fn usage(prompt: u32, candidates: u32, cached: u32, thoughts: u32) -> UsageMetadata {
+        UsageMetadata {
+            prompt_token_count: prompt,
+            cached_content_token_count: Some(cached),
+            candidates_token_count: Some(candidates),
+            thoughts_token_count: Some(thoughts),
+            total_token_count: prompt + candidates + thoughts,
+        }
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_usage_tracker() {
+        let tracker = UsageTracker::new();
+        tracker.record("gemini-1.5-flash", &usage(10, 5, 2, 0));
+        tracker.record("gemini-1.5-flash", &usage(20, 5, 0, 0));
+        tracker
+            .clone()
+            .record("gemini-2.5-pro", &usage(100, 50, 0, 30));
+
+        assert_eq!(
+            tracker.for_model("gemini-1.5-flash"),
+            TokenUsage {
+                requests: 2,
+                prompt_tokens: 30,
+                cached_tokens: 2,
+                candidates_tokens: 10,
+                thinking_tokens: 0,
+                total_tokens: 40,
+            }
+        );
+        assert!(
+            (tracker.for_model("gemini-1.5-flash").cache_hit_ratio() - 2.0 / 30.0).abs() < 1e-9
+        );
+        let total = tracker.total();
+        assert_eq!(total.requests, 3);
+        assert_eq!(total.thinking_tokens, 30);
+        assert_eq!(total.total_tokens, 220);
+
+        tracker.reset();
+        assert_eq!(tracker.total(), TokenUsage::default());
+        assert!(tracker.total().cache_hit_ratio().abs() < 1e-9);
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_cache_hit_ratio() {
+        let response: GenerateContentResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [],
+            "usageMetadata": {
+                "promptTokenCount": 40,
+                "cachedContentTokenCount": 30,
+                "totalTokenCount": 45
+            }
+        }))
+        .unwrap();
+        assert!((response.cache_hit_ratio().unwrap() - 0.75).abs() < 1e-9);
+        assert!(usage(10, 5, 0, 0).cache_hit_ratio().abs() < 1e-9);
+
+        let uncached: GenerateContentResponse =
+            serde_json::from_value(serde_json::json!({"candidates": []})).unwrap();
+        assert_eq!(uncached.cache_hit_ratio(), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! Typed object detection and segmentation output.
+//!
+//! Asked to detect objects, Gemini answers with JSON such as
+//! `[{"box_2d": [ymin, xmin, ymax, xmax], "label": "cat"}]`, with coordinates normalized to
+//! `0..=1000` regardless of the image size. Segmentation answers add a `mask`, a base64 PNG
+//! probability map covering the box.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    messages::{media::MediaError, GenerateContentResponse},
+    ApiRequestError,
+};
+
+/// The scale of normalized coordinates.
+pub const NORMALIZED_SCALE: f32 = 1000.0;
+
+/// A box in normalized `0..=1000` coordinates, in the API's `[ymin, xmin, ymax, xmax]`
+/// order on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f32; 4]", into = "[f32; 4]")]
+pub struct BoundingBox {
+    pub y_min: f32,
+    pub x_min: f32,
+    pub y_max: f32,
+    pub x_max: f32,
+}
+
+impl From<[f32; 4]> for BoundingBox {
+    fn from([y_min, x_min, y_max, x_max]: [f32; 4]) -> Self {
+        Self {
+            y_min,
+            x_min,
+            y_max,
+            x_max,
+        }
+    }
+}
+
+impl From<BoundingBox> for [f32; 4] {
+    fn from(b: BoundingBox) -> Self {
+        [b.y_min, b.x_min, b.y_max, b.x_max]
+    }
+}
+
+impl BoundingBox {
+    /// Denormalizes the box to pixel coordinates of an image of `width` × `height`,
+    /// clamped to the image.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn to_pixels(&self, width: u32, height: u32) -> PixelBox {
+        #[allow(clippy::cast_precision_loss)]
+        let scale = |value: f32, size: u32| {
+            (value.clamp(0.0, NORMALIZED_SCALE) / NORMALIZED_SCALE * size as f32).round() as u32
+        };
+        PixelBox {
+            x_min: scale(self.x_min, width),
+            y_min: scale(self.y_min, height),
+            x_max: scale(self.x_max, width),
+            y_max: scale(self.y_max, height),
+        }
+    }
+}
+
+/// A box in pixel coordinates; the maximums are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PixelBox {
+    pub x_min: u32,
+    pub y_min: u32,
+    pub x_max: u32,
+    pub y_max: u32,
+}
+
+impl PixelBox {
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.x_max.saturating_sub(self.x_min)
+    }
+
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.y_max.saturating_sub(self.y_min)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Detection {
+    #[serde(rename = "box_2d")]
+    pub bounding_box: BoundingBox,
+    #[serde(default)]
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segmentation {
+    #[serde(rename = "box_2d")]
+    pub bounding_box: BoundingBox,
+    #[serde(default)]
+    pub label: String,
+    /// A PNG probability map (0-255) covering the bounding box, as a
+    /// `data:image/png;base64,...` URL.
+    pub mask: String,
+}
+
+impl Segmentation {
+    /// The PNG bytes of the mask.
+    pub fn mask_png(&self) -> Result<Vec<u8>, MediaError> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let data = self
+            .mask
+            .split_once("base64,")
+            .map_or(self.mask.as_str(), |(_, data)| data);
+        Ok(STANDARD.decode(data)?)
+    }
+
+    /// Decodes the mask and scales it to the bounding box in an image of `width` ×
+    /// `height`, returning it with the box it covers.
+    #[cfg(feature = "image")]
+    pub fn mask_image(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Result<(image::GrayImage, PixelBox), MediaError> {
+        let pixels = self.bounding_box.to_pixels(width, height);
+        let mask = image::load_from_memory_with_format(&self.mask_png()?, image::ImageFormat::Png)?
+            .into_luma8();
+        let mask = image::imageops::resize(
+            &mask,
+            pixels.width().max(1),
+            pixels.height().max(1),
+            image::imageops::FilterType::Triangle,
+        );
+        Ok((mask, pixels))
+    }
+}
+
+/// Parses the JSON the model answered with, tolerating a surrounding Markdown code fence.
+fn parse<T: DeserializeOwned>(text: &str) -> Result<Vec<T>, ApiRequestError> {
+    let json = text.trim();
+    let json = json
+        .strip_prefix("```json")
+        .or_else(|| json.strip_prefix("```"))
+        .and_then(|json| json.strip_suffix("```"))
+        .unwrap_or(json);
+    serde_json::from_str(json).map_err(|source| ApiRequestError::ResponseParseError {
+        text: text.to_string(),
+        source,
+    })
+}
+
+pub fn parse_detections(text: &str) -> Result<Vec<Detection>, ApiRequestError> {
+    parse(text)
+}
+
+pub fn parse_segmentations(text: &str) -> Result<Vec<Segmentation>, ApiRequestError> {
+    parse(text)
+}
+
+impl GenerateContentResponse {
+    /// The detected objects in the text of the first candidate.
+    pub fn detections(&self) -> Result<Vec<Detection>, ApiRequestError> {
+        parse_detections(&self.text())
+    }
+
+    /// The segmented objects in the text of the first candidate.
+    pub fn segmentations(&self) -> Result<Vec<Segmentation>, ApiRequestError> {
+        parse_segmentations(&self.text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_parse_detections() {
+        let text = "```json\n[\n  {\"box_2d\": [100, 250, 500, 750], \"label\": \"cat\"},\n  {\"box_2d\": [0, 0, 1000, 1000], \"label\": \"sofa\"}\n]\n```";
+
+        let detections = parse_detections(text).unwrap();
+
+        assert_eq!(detections.len(), 2);
+        assert_eq!(detections[0].label, "cat");
+        assert_eq!(
+            detections[0].bounding_box.to_pixels(640, 480),
+            PixelBox {
+                x_min: 160,
+                y_min: 48,
+                x_max: 480,
+                y_max: 240,
+            }
+        );
+        assert_eq!(detections[1].bounding_box.to_pixels(640, 480).width(), 640);
+        assert!(matches!(
+            parse_detections("no objects"),
+            Err(ApiRequestError::ResponseParseError { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    fn test_parse_segmentations() {
+        let text = r#"[{"box_2d": [10, 20, 30, 40], "label": "cup", "mask": "data:image/png;base64,iVBORw0KGgo="}]"#;
+
+        let segmentations = parse_segmentations(text).unwrap();
+
+        assert_eq!(segmentations[0].label, "cup");
+        assert_eq!(segmentations[0].mask_png().unwrap(), b"\x89PNG\r\n\x1a\n");
+        assert_eq!(
+            serde_json::to_value(segmentations[0].bounding_box).unwrap(),
+            serde_json::json!([10.0, 20.0, 30.0, 40.0])
+        );
+    }
+}